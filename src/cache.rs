@@ -0,0 +1,135 @@
+//! Cache of recent command results for the request/response bridge
+//!
+//! Filled by the polling loop (as an output sink) and by bridged requests, so
+//! a dashboard asking for the same value repeatedly within `CACHE_TTL` is
+//! answered without another round trip over the Optolink bus. Only reads are
+//! cached; a write clears the whole cache, since there is no mapping from a
+//! `set` command to the values it changes.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::sink::{OutputSink, SinkFuture};
+use crate::vcontrold::{CommandResult, Value};
+
+/// Shared command → (result, read time) map; clones share the entries
+#[derive(Debug, Clone)]
+pub struct ResponseCache {
+    ttl: Duration,
+    entries: Arc<Mutex<HashMap<String, (CommandResult, Instant)>>>,
+}
+
+impl ResponseCache {
+    /// Create an empty cache whose entries are served for `ttl`
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// The cached result of `command`, if it was read within the TTL
+    pub fn get(&self, command: &str) -> Option<CommandResult> {
+        self.get_at(command, Instant::now())
+    }
+
+    fn get_at(&self, command: &str, now: Instant) -> Option<CommandResult> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(command) {
+            Some((result, read)) if now.saturating_duration_since(*read) < self.ttl => {
+                Some(result.clone())
+            }
+            Some(_) => {
+                entries.remove(command);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Remember a successful read; errors, empty values and writes are ignored
+    pub fn insert(&self, result: &CommandResult) {
+        self.insert_at(result, Instant::now());
+    }
+
+    fn insert_at(&self, result: &CommandResult, now: Instant) {
+        if result.error.is_some()
+            || matches!(result.value, Value::None)
+            || is_write_command(&result.command)
+        {
+            return;
+        }
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(result.command.clone(), (result.clone(), now));
+    }
+
+    /// Drop all entries (after a write)
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+/// Polled values refresh the cache as they are read
+impl OutputSink for ResponseCache {
+    fn publish<'a>(&'a self, result: &'a CommandResult, _time: SystemTime) -> SinkFuture<'a> {
+        self.insert(result);
+        Box::pin(std::future::ready(()))
+    }
+}
+
+/// Whether a command may change the device state
+///
+/// vcontrold write commands start with `set` by convention; any command with
+/// arguments is treated as a write as well.
+pub fn is_write_command(command: &str) -> bool {
+    let command = command.trim();
+    command.starts_with("set") || command.contains(char::is_whitespace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_serves_reads_within_ttl() {
+        let cache = ResponseCache::new(Duration::from_secs(10));
+        let start = Instant::now();
+        cache.insert_at(&CommandResult::new("getTempA", Value::Number(21.5)), start);
+
+        let hit = cache.get_at("getTempA", start + Duration::from_secs(9));
+        assert!(matches!(hit.map(|r| r.value), Some(Value::Number(n)) if n == 21.5));
+        assert!(cache
+            .get_at("getTempA", start + Duration::from_secs(10))
+            .is_none());
+        assert!(cache.get_at("getTempB", start).is_none());
+    }
+
+    #[test]
+    fn cache_ignores_errors_and_writes() {
+        let cache = ResponseCache::new(Duration::from_secs(10));
+        cache.insert(&CommandResult::failed("getTempA", "ERR: timeout"));
+        cache.insert(&CommandResult::new(
+            "setTempWWsoll 50",
+            Value::String("OK".into()),
+        ));
+        cache.insert(&CommandResult::new("getTempB", Value::None));
+        assert!(cache.get("getTempA").is_none());
+        assert!(cache.get("setTempWWsoll 50").is_none());
+        assert!(cache.get("getTempB").is_none());
+
+        cache.insert(&CommandResult::new("getTempA", Value::Number(1.0)));
+        cache.clear();
+        assert!(cache.get("getTempA").is_none());
+    }
+
+    #[test]
+    fn write_commands() {
+        assert!(is_write_command("setTempWWsoll 50"));
+        assert!(is_write_command("setBetriebArt"));
+        assert!(is_write_command("getTimerZirkuMo 1"));
+        assert!(!is_write_command("getTempA"));
+    }
+}
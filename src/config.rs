@@ -2,37 +2,249 @@
 //!
 //! Parses environment variables into a strongly-typed configuration struct.
 
+use std::collections::{HashMap, HashSet};
 use std::env;
+use std::net::{IpAddr, SocketAddr};
 use std::path::PathBuf;
 use std::time::Duration;
 
+use crate::mqtt::sanitize_name;
+
 /// Main configuration struct containing all settings
 #[derive(Debug, Clone)]
 pub struct Config {
-    /// Serial device path inside container (reserved for future use)
-    #[allow(dead_code)]
+    /// Serial device path inside container
     pub usb_device: PathBuf,
+    /// Max time to wait for the serial device before starting vcontrold (0 = don't wait)
+    pub usb_wait_timeout: Duration,
+    /// Interval of the check that the serial device still exists (0 = off)
+    pub usb_check_interval: Duration,
+    /// Pause before starting vcontrold and connecting to MQTT
+    pub startup_delay: Duration,
     /// Max character length per vclient batch
     pub max_length: usize,
+    /// Reject polled commands longer than `max_length` at startup
+    pub max_length_strict: bool,
+    /// Max number of commands per batch (`None` = unlimited)
+    pub max_batch_count: Option<usize>,
     /// Enable request/response bridge
     pub mqtt_subscribe: bool,
     /// MQTT broker configuration
     pub mqtt: MqttConfig,
     /// Seconds between polling cycles
     pub interval: Duration,
+    /// Upper bound of the random delay added to each polling cycle
+    pub interval_jitter: Duration,
     /// Comma-separated list of command names to poll
     pub commands: Vec<String>,
+    /// Poll `commands` on the interval (`false` = request/response bridge only)
+    pub polling: bool,
     /// Enable verbose logging
     pub debug: bool,
-    /// TCP port for the health check HTTP endpoint
-    pub healthcheck_port: u16,
+    /// Address the health check HTTP endpoint listens on
+    pub health_bind: SocketAddr,
+    /// Retry commands that timed out within a batch once, individually
+    pub batch_retry: bool,
+    /// Polling result publishing options
+    pub publish: PublishConfig,
+    /// Unix domain socket of vcontrold (TCP on localhost when unset)
+    pub vcontrold_socket: Option<PathBuf>,
+    /// vcontrold executable (looked up in `PATH` unless absolute)
+    pub vcontrold_bin: String,
+    /// vcontrold configuration file passed via `-x`
+    pub vcontrold_config: PathBuf,
+    /// Extra whitespace-separated arguments for vcontrold
+    pub vcontrold_args: Vec<String>,
+    /// Time vcontrold gets to exit after SIGTERM before it is killed
+    pub vcontrold_stop_grace: Duration,
+    /// How long vcontrold may take to accept connections after starting
+    pub readiness_timeout: Duration,
+    /// Pause between readiness probes
+    pub readiness_probe_interval: Duration,
+    /// Prompt printed by vcontrold when ready for a command
+    pub vcontrold_prompt: String,
+    /// Largest vcontrold response in bytes before the connection is dropped
+    pub vcontrold_max_response: usize,
+    /// How long an empty vcontrold response waits for a value sent after the
+    /// prompt (zero disables the wait)
+    pub vcontrold_late_response_wait: Duration,
+    /// Additional attempts for polled commands failing with connection errors
+    pub command_retries: u32,
+    /// Allowed numeric argument range per write command (request bridge)
+    pub set_ranges: HashMap<String, ArgRange>,
+    /// Publish per-command stats every N polling cycles (0 = disabled)
+    pub stats_interval: u64,
+    /// Additional (request topic, response topic) pairs for the bridge
+    pub request_topics: Vec<(String, String)>,
+    /// Suffix of the default request topic (`{base}/request`)
+    pub request_suffix: String,
+    /// Suffix of the default response topic (`{base}/response`)
+    pub response_suffix: String,
+    /// Publish synthetic values without starting or talking to vcontrold
+    pub dry_run: bool,
+    /// Time allowed on shutdown for queued publishes to reach the broker
+    pub shutdown_grace: Duration,
+    /// Commands whose response must be numeric (anything else is an error)
+    pub numeric_commands: HashSet<String>,
+    /// Commands whose response is parsed as space-separated hex bytes
+    pub hex_commands: HashSet<String>,
+    /// InfluxDB output for polled values (disabled when `INFLUX_URL` is unset)
+    pub influx: Option<InfluxConfig>,
+    /// Abort startup if a polled command is unknown to vcontrold
+    pub strict_commands: bool,
+    /// Abort startup on a configuration that would do nothing
+    pub strict: bool,
+    /// Separator between commands in a request payload
+    pub request_separator: String,
+    /// Length of the min/max/avg aggregation window (zero = disabled)
+    pub agg_window: Duration,
+    /// Commands to aggregate (all numeric commands when empty)
+    pub agg_commands: HashSet<String>,
+    /// Keep publishing the per-cycle values of aggregated commands
+    pub agg_raw: bool,
+    /// Restart vcontrold after this many cycles without a successful read (0 = disabled)
+    pub watchdog_failures: u32,
+    /// Exit when the polling loop makes no progress for this many intervals (0 = disabled)
+    pub stall_multiplier: u32,
+    /// Pause between consecutive commands sent to vcontrold
+    pub command_delay: Duration,
+    /// Run a polling cycle as soon as MQTT is connected instead of after one interval
+    pub poll_on_start: bool,
+    /// Snapshot of the last good value per command, rewritten every cycle
+    pub state_file: Option<PathBuf>,
+    /// Publish the values of `state_file` once before the first cycle
+    pub republish_on_start: bool,
+    /// Clear the retained topics of commands in `state_file` that are no
+    /// longer configured, once at startup
+    pub clear_stale_on_start: bool,
+    /// Serve the admin control topics (`{base}/admin/reload`)
+    pub admin_enabled: bool,
+    /// Response timeout per command of bridged requests
+    pub request_timeout: Duration,
+    /// Publish a bridged response after this long with what finished so far
+    pub request_deadline: Option<Duration>,
+    /// Answer bridged reads from values read within this time (zero = disabled)
+    pub cache_ttl: Duration,
+    /// Also write each polled value as a JSON line to stdout
+    pub stdout_output: bool,
+    /// Leave commands without a value out of JSON outputs instead of `null`
+    pub omit_nulls: bool,
+    /// Add the raw vcontrold responses to JSON outputs
+    pub publish_raw: bool,
+    /// Add the units of the values to JSON outputs
+    pub publish_units: bool,
+    /// Unit reported instead of the one vcontrold sends, per command
+    pub units: HashMap<String, String>,
+    /// Poll one command at a time in `COMMANDS` order instead of batching
+    pub ordered: bool,
+    /// Serve topic-addressed requests on `{base}/get/<command>`
+    pub mqtt_get_topics: bool,
+    /// Serve a mock vcontrold with these command responses instead of
+    /// starting the real daemon (`VCONTROLD_MOCK`)
+    pub vcontrold_mock: Option<HashMap<String, String>>,
+}
+
+/// InfluxDB v2 write API settings
+#[derive(Debug, Clone)]
+pub struct InfluxConfig {
+    /// Base URL (`http://host:8086` or `https://...`)
+    pub url: String,
+    /// Target bucket
+    pub bucket: String,
+    /// Organization (optional for single-org setups)
+    pub org: Option<String>,
+    /// API token
+    pub token: Option<String>,
+    /// Measurement name of the written points
+    pub measurement: String,
+}
+
+/// Inclusive numeric range allowed for a command argument
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ArgRange {
+    pub min: f64,
+    pub max: f64,
+}
+
+impl ArgRange {
+    /// Parse `min..max` (inclusive)
+    fn parse(s: &str) -> Option<Self> {
+        let (min, max) = s.split_once("..")?;
+        let min: f64 = min.trim().parse().ok()?;
+        let max: f64 = max.trim().parse().ok()?;
+        (min <= max).then_some(Self { min, max })
+    }
+
+    /// Whether `value` lies within the range
+    pub fn contains(&self, value: f64) -> bool {
+        value >= self.min && value <= self.max
+    }
+}
+
+/// printf-style format of a published number (`%.1f`, `%f`, `%d`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumberFormat {
+    /// Fixed number of decimal places
+    pub decimals: usize,
+}
+
+impl NumberFormat {
+    /// Parse `%.Nf` (N decimals), `%f` (6 decimals) or `%d` (rounded integer)
+    fn parse(s: &str) -> Option<Self> {
+        let decimals = match s.trim() {
+            "%d" => 0,
+            "%f" => 6,
+            spec => spec
+                .strip_prefix("%.")?
+                .strip_suffix('f')?
+                .parse()
+                .ok()
+                .filter(|&n| n <= 10)?,
+        };
+        Some(Self { decimals })
+    }
+
+    /// Format `value` with the fixed number of decimals
+    pub fn format(&self, value: f64) -> String {
+        format!("{:.*}", self.decimals, value)
+    }
+}
+
+/// Labels published instead of numeric codes (`0=Standby;1=Heating`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueMap(pub Vec<(f64, String)>);
+
+impl ValueMap {
+    /// Parse `;`-separated `code=label` pairs
+    fn parse(s: &str) -> Option<Self> {
+        s.split(';')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let (code, label) = entry.split_once('=')?;
+                let code = code.trim().parse::<f64>().ok()?;
+                let label = label.trim();
+                (code.is_finite() && !label.is_empty()).then(|| (code, label.to_string()))
+            })
+            .collect::<Option<Vec<_>>>()
+            .filter(|entries| !entries.is_empty())
+            .map(Self)
+    }
+
+    /// Label of `value`, if it is one of the mapped codes
+    pub fn label(&self, value: f64) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(code, _)| *code == value)
+            .map(|(_, label)| label.as_str())
+    }
 }
 
 /// MQTT-specific configuration
 #[derive(Debug, Clone)]
 pub struct MqttConfig {
-    /// Broker hostname/IP
-    pub host: String,
+    /// Broker hostnames/IPs, primary first; the others are failover brokers
+    pub hosts: Vec<String>,
     /// Broker TCP port
     pub port: u16,
     /// Base topic prefix
@@ -48,10 +260,140 @@ pub struct MqttConfig {
     pub timeout: Duration,
     /// TLS configuration
     pub tls: Option<TlsConfig>,
+    /// Network transport to the broker
+    pub transport: MqttTransport,
+    /// HTTP path of the WebSocket endpoint (`ws`/`wss` transports)
+    pub ws_path: String,
+    /// Keep-alive interval in seconds
+    pub keep_alive: u16,
+    /// Capacity of the client's request channel to the event loop
+    pub cap: usize,
+    /// Start a fresh session on connect (false = resume a persistent session)
+    pub clean_start: bool,
+    /// Exact client ID, overriding the generated one
+    pub client_id: Option<String>,
+    /// Generate `{prefix}-{hostname}` without pid/timestamp
+    pub client_id_stable: bool,
+    /// Exit after this many rejected logins without a successful connect (0 = never)
+    pub auth_failure_limit: u32,
+    /// Exit after this many failed connects before the first successful one (0 = never)
+    pub connect_max_retries: u32,
+    /// Restore subscriptions with one SUBSCRIBE request instead of one per topic
+    pub subscribe_batch: bool,
+    /// QoS level (0-2) of request/response bridge responses
+    pub response_qos: u8,
+    /// MQTT v5 user properties attached to every published message
+    pub user_properties: Vec<(String, String)>,
 }
 
-/// TLS configuration for MQTT
+/// Network transport used to reach the MQTT broker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MqttTransport {
+    /// Plain TCP (TLS when `MQTT_TLS=true`)
+    Tcp,
+    /// MQTT over WebSockets
+    Ws,
+    /// MQTT over secure WebSockets
+    Wss,
+}
+
+impl MqttTransport {
+    fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "tcp" => Some(MqttTransport::Tcp),
+            "ws" => Some(MqttTransport::Ws),
+            "wss" => Some(MqttTransport::Wss),
+            _ => None,
+        }
+    }
+}
+
+/// Default topic layout for polled values
+pub const DEFAULT_TOPIC_TEMPLATE: &str = "{base}/command/{cmd}";
+
+/// Publishing options for polling results
 #[derive(Debug, Clone)]
+pub struct PublishConfig {
+    /// Publish an RFC3339 timestamp to `{topic}/ts` alongside each value
+    pub timestamp: bool,
+    /// Topic layout with `{base}` and `{cmd}` placeholders
+    pub topic_template: String,
+    /// Full topic override per command
+    pub topic_map: HashMap<String, String>,
+    /// Published name per command (topics and response keys)
+    pub aliases: HashMap<String, String>,
+    /// Publish polled values retained
+    pub retain: bool,
+    /// Commands always published non-retained (overrides `retain`)
+    pub no_retain_commands: HashSet<String>,
+    /// Fixed number format per command (replaces trailing-zero trimming)
+    pub formats: HashMap<String, NumberFormat>,
+    /// Labels replacing numeric codes per command (takes precedence over `formats`)
+    pub value_maps: HashMap<String, ValueMap>,
+    /// Publish numbers with a decimal comma (`48,1`) instead of a point
+    pub decimal_comma: bool,
+    /// Exponential moving average smoothing factor (alpha) per command
+    pub ema: HashMap<String, f64>,
+    /// Also publish the unsmoothed value of `ema` commands to `{topic}/raw`
+    pub ema_raw: bool,
+    /// Flag a command's value as stale via `{topic}/stale` once it has not
+    /// been published for this long
+    pub max_age: Option<Duration>,
+    /// Splits command names into a device segment and the command
+    /// (`dev1.getTempA` with `.`), nesting the device under the base topic
+    pub device_separator: Option<String>,
+    /// Publish retained `true` to `{topic}/online` on a command's first value
+    /// in this run (`false` at startup)
+    pub online_flags: bool,
+}
+
+impl PublishConfig {
+    /// Whether a command's value is published retained
+    pub fn retain(&self, command: &str) -> bool {
+        self.retain && !self.no_retain_commands.contains(command)
+    }
+
+    /// Name a command is published under (its alias, or the command itself)
+    pub fn alias<'a>(&'a self, command: &'a str) -> &'a str {
+        self.aliases.get(command).map_or(command, String::as_str)
+    }
+
+    /// Split a command into its device segment (if any) and the command name
+    ///
+    /// Commands without the separator, or with an empty part on either side
+    /// of it, have no device.
+    pub fn split_device<'a>(&self, command: &'a str) -> (Option<&'a str>, &'a str) {
+        self.device_separator
+            .as_deref()
+            .and_then(|separator| command.split_once(separator))
+            .filter(|(device, name)| !device.is_empty() && !name.is_empty())
+            .map_or((None, command), |(device, name)| (Some(device), name))
+    }
+}
+
+impl Default for PublishConfig {
+    fn default() -> Self {
+        Self {
+            timestamp: false,
+            topic_template: DEFAULT_TOPIC_TEMPLATE.to_string(),
+            topic_map: HashMap::new(),
+            aliases: HashMap::new(),
+            retain: true,
+            no_retain_commands: HashSet::new(),
+            formats: HashMap::new(),
+            value_maps: HashMap::new(),
+            decimal_comma: false,
+            ema: HashMap::new(),
+            ema_raw: false,
+            max_age: None,
+            device_separator: None,
+            online_flags: false,
+        }
+    }
+}
+
+/// TLS configuration for MQTT
+#[derive(Debug, Clone, Default)]
 pub struct TlsConfig {
     /// CA certificate file path
     pub ca_file: Option<PathBuf>,
@@ -61,6 +403,8 @@ pub struct TlsConfig {
     pub cert_file: Option<PathBuf>,
     /// Private key path
     pub key_file: Option<PathBuf>,
+    /// Passphrase of an encrypted PKCS#8 private key
+    pub key_password: Option<String>,
     /// TLS version hint (reserved for future use, rustls handles this automatically)
     #[allow(dead_code)]
     pub tls_version: Option<String>,
@@ -75,69 +419,313 @@ pub enum ConfigError {
     MissingRequired(&'static str),
     #[error("invalid value for {0}: {1}")]
     InvalidValue(&'static str, String),
+    #[error("neither polling nor the request/response bridge is enabled (set COMMANDS or MQTT_SUBSCRIBE=true)")]
+    NothingEnabled,
+    #[error("command {0} is longer than MAX_LENGTH ({1} characters)")]
+    CommandTooLong(String, usize),
+}
+
+/// Source of configuration variables
+struct Vars<'a>(&'a dyn Fn(&str) -> Option<String>);
+
+impl Vars<'_> {
+    /// Look up a variable, with the same result as `std::env::var`
+    fn var(&self, name: &str) -> Result<String, env::VarError> {
+        (self.0)(name).ok_or(env::VarError::NotPresent)
+    }
 }
 
 impl Config {
     /// Load configuration from environment variables
     pub fn from_env() -> Result<Self, ConfigError> {
-        let mqtt_subscribe = parse_bool("MQTT_SUBSCRIBE", false);
+        Self::from_lookup(|name| env::var(name).ok())
+    }
+
+    /// Load configuration from `lookup`, which returns the value of a variable
+    /// or `None` if it is unset
+    ///
+    /// Used by [`Config::from_env`]; lets tests and embedders build a
+    /// configuration without touching the process environment.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        let vars = &Vars(&lookup);
+        let mqtt_subscribe = parse_bool(vars, "MQTT_SUBSCRIBE", false);
 
         // MQTT_HOST and MQTT_TOPIC are always required
-        let mqtt_host =
-            env::var("MQTT_HOST").map_err(|_| ConfigError::MissingRequired("MQTT_HOST"))?;
-        let mqtt_topic =
-            env::var("MQTT_TOPIC").map_err(|_| ConfigError::MissingRequired("MQTT_TOPIC"))?;
+        let mqtt_hosts = parse_list(&vars.var("MQTT_HOST").unwrap_or_default());
+        if mqtt_hosts.is_empty() {
+            return Err(ConfigError::MissingRequired("MQTT_HOST"));
+        }
+        let mqtt_topic = parse_base_topic(
+            vars.var("MQTT_TOPIC")
+                .map_err(|_| ConfigError::MissingRequired("MQTT_TOPIC"))?,
+        )?;
 
-        let tls_enabled = parse_bool("MQTT_TLS", false);
+        let tls_enabled = parse_bool(vars, "MQTT_TLS", false);
         let tls = if tls_enabled {
             Some(TlsConfig {
-                ca_file: env::var("MQTT_CAFILE").ok().map(PathBuf::from),
-                ca_path: env::var("MQTT_CAPATH").ok().map(PathBuf::from),
-                cert_file: env::var("MQTT_CERTFILE").ok().map(PathBuf::from),
-                key_file: env::var("MQTT_KEYFILE").ok().map(PathBuf::from),
-                tls_version: env::var("MQTT_TLS_VERSION").ok().filter(|s| !s.is_empty()),
-                insecure: parse_bool("MQTT_TLS_INSECURE", false),
+                ca_file: vars.var("MQTT_CAFILE").ok().map(PathBuf::from),
+                ca_path: vars.var("MQTT_CAPATH").ok().map(PathBuf::from),
+                cert_file: vars.var("MQTT_CERTFILE").ok().map(PathBuf::from),
+                key_file: vars.var("MQTT_KEYFILE").ok().map(PathBuf::from),
+                key_password: parse_secret(
+                    vars,
+                    "MQTT_KEYFILE_PASSWORD",
+                    "MQTT_KEYFILE_PASSWORD_FILE",
+                )?,
+                tls_version: vars.var("MQTT_TLS_VERSION").ok().filter(|s| !s.is_empty()),
+                insecure: parse_bool(vars, "MQTT_TLS_INSECURE", false),
             })
         } else {
             None
         };
 
-        let commands_str = env::var("COMMANDS").unwrap_or_default();
-        let commands: Vec<String> = commands_str
-            .split(',')
-            .map(|s| s.trim().to_string())
+        let mut commands = parse_commands(&vars.var("COMMANDS").unwrap_or_default())?;
+        if let Some(path) = vars.var("COMMANDS_FILE").ok().filter(|s| !s.is_empty()) {
+            let contents = std::fs::read_to_string(&path).map_err(|e| {
+                ConfigError::InvalidValue("COMMANDS_FILE", format!("{}: {}", path, e))
+            })?;
+            commands.extend(parse_commands_file(&contents)?);
+        }
+        let commands = dedup_commands(commands)?;
+        let interval = Duration::from_secs(parse_u64(vars, "INTERVAL", 60)?);
+        let state_file = vars
+            .var("STATE_FILE")
+            .ok()
             .filter(|s| !s.is_empty())
-            .collect();
+            .map(PathBuf::from);
+        let republish_on_start = parse_bool(vars, "REPUBLISH_ON_START", false);
+        if republish_on_start && state_file.is_none() {
+            return Err(ConfigError::MissingRequired("STATE_FILE"));
+        }
+        let clear_stale_on_start = parse_bool(vars, "CLEAR_STALE_ON_START", false);
+        if clear_stale_on_start && state_file.is_none() {
+            return Err(ConfigError::MissingRequired("STATE_FILE"));
+        }
+        // Both run in the polling loop, which does not start without commands
+        let polling = parse_bool(vars, "POLLING", true);
+        if !polling || commands.is_empty() {
+            for (name, enabled) in [
+                ("REPUBLISH_ON_START", republish_on_start),
+                ("CLEAR_STALE_ON_START", clear_stale_on_start),
+            ] {
+                if enabled {
+                    return Err(ConfigError::InvalidValue(
+                        name,
+                        "requires POLLING=true and COMMANDS".to_string(),
+                    ));
+                }
+            }
+        }
+        let interval_jitter =
+            parse_jitter(&vars.var("INTERVAL_JITTER").unwrap_or_default(), interval)?;
+        let (request_suffix, response_suffix) = parse_topic_suffixes(vars)?;
 
         Ok(Config {
             usb_device: PathBuf::from(
-                env::var("USB_DEVICE").unwrap_or_else(|_| "/dev/vitocal".to_string()),
+                vars.var("USB_DEVICE")
+                    .unwrap_or_else(|_| "/dev/vitocal".to_string()),
             ),
-            max_length: parse_usize("MAX_LENGTH", 512)?,
+            usb_wait_timeout: Duration::from_secs(parse_u64(vars, "USB_WAIT_TIMEOUT", 30)?),
+            usb_check_interval: Duration::from_secs(parse_u64(vars, "USB_CHECK_INTERVAL", 0)?),
+            startup_delay: Duration::from_secs(parse_u64(vars, "STARTUP_DELAY", 0)?),
+            max_length: parse_usize(vars, "MAX_LENGTH", 512)?,
+            max_length_strict: parse_bool(vars, "MAX_LENGTH_STRICT", false),
+            max_batch_count: Some(parse_usize(vars, "MAX_BATCH_COUNT", 0)?)
+                .filter(|count| *count > 0),
             mqtt_subscribe,
             mqtt: MqttConfig {
-                host: mqtt_host,
-                port: parse_u16("MQTT_PORT", 1883)?,
+                hosts: mqtt_hosts,
+                port: parse_u16(vars, "MQTT_PORT", 1883)?,
                 topic: mqtt_topic,
-                user: env::var("MQTT_USER").ok().filter(|s| !s.is_empty()),
-                password: env::var("MQTT_PASSWORD").ok().filter(|s| !s.is_empty()),
-                client_id_prefix: env::var("MQTT_CLIENT_ID_PREFIX")
+                user: vars.var("MQTT_USER").ok().filter(|s| !s.is_empty()),
+                password: vars.var("MQTT_PASSWORD").ok().filter(|s| !s.is_empty()),
+                client_id_prefix: vars
+                    .var("MQTT_CLIENT_ID_PREFIX")
                     .unwrap_or_else(|_| "vcontrold".to_string()),
-                timeout: Duration::from_secs(parse_u64("MQTT_TIMEOUT", 10)?),
+                timeout: Duration::from_secs(parse_u64(vars, "MQTT_TIMEOUT", 10)?),
                 tls,
+                transport: parse_transport(vars)?,
+                ws_path: vars
+                    .var("MQTT_WS_PATH")
+                    .ok()
+                    .filter(|s| !s.is_empty())
+                    .unwrap_or_else(|| "/mqtt".to_string()),
+                keep_alive: parse_keep_alive(vars)?,
+                cap: parse_usize(vars, "MQTT_CAP", 100)?,
+                clean_start: parse_bool(vars, "MQTT_CLEAN_START", true),
+                client_id: vars.var("MQTT_CLIENT_ID").ok().filter(|s| !s.is_empty()),
+                client_id_stable: parse_bool(vars, "MQTT_CLIENT_ID_STABLE", false),
+                auth_failure_limit: parse_u32(vars, "MQTT_AUTH_FAILURES", 3)?,
+                connect_max_retries: parse_u32(vars, "MQTT_CONNECT_MAX_RETRIES", 0)?,
+                subscribe_batch: parse_bool(vars, "MQTT_SUBSCRIBE_BATCH", false),
+                response_qos: parse_qos("MQTT_RESPONSE_QOS", vars.var("MQTT_RESPONSE_QOS").ok())?,
+                user_properties: parse_user_props(
+                    &vars.var("MQTT_USER_PROPS").unwrap_or_default(),
+                )?,
             },
-            interval: Duration::from_secs(parse_u64("INTERVAL", 60)?),
+            interval,
+            interval_jitter,
             commands,
-            debug: parse_bool("DEBUG", false),
-            healthcheck_port: parse_u16("HEALTHCHECK_PORT", 8080)?,
+            polling,
+            debug: parse_bool(vars, "DEBUG", false),
+            health_bind: parse_bind(
+                "HEALTH_BIND",
+                vars.var("HEALTH_BIND").ok(),
+                parse_u16(vars, "HEALTHCHECK_PORT", 8080)?,
+            )?,
+            batch_retry: parse_bool(vars, "BATCH_RETRY", false),
+            publish: PublishConfig {
+                timestamp: parse_bool(vars, "PUBLISH_TIMESTAMP", false),
+                topic_template: parse_topic_template(vars)?,
+                topic_map: parse_map("TOPIC_MAP", &vars.var("TOPIC_MAP").unwrap_or_default())?,
+                aliases: parse_map("ALIASES", &vars.var("ALIASES").unwrap_or_default())?,
+                retain: parse_bool(vars, "MQTT_RETAIN", true),
+                no_retain_commands: parse_list(&vars.var("NO_RETAIN_COMMANDS").unwrap_or_default())
+                    .into_iter()
+                    .collect(),
+                formats: parse_formats(&vars.var("FORMAT").unwrap_or_default())?,
+                value_maps: parse_value_maps(&vars.var("MAP").unwrap_or_default())?,
+                decimal_comma: parse_bool(vars, "OUTPUT_DECIMAL_COMMA", false),
+                ema: parse_ema(&vars.var("EMA").unwrap_or_default())?,
+                ema_raw: parse_bool(vars, "EMA_RAW", false),
+                max_age: Some(Duration::from_secs(parse_u64(vars, "MAX_AGE", 0)?))
+                    .filter(|age| !age.is_zero()),
+                device_separator: vars.var("DEVICE_SEPARATOR").ok().filter(|s| !s.is_empty()),
+                online_flags: parse_bool(vars, "PUBLISH_ONLINE", false),
+            },
+            vcontrold_socket: vars
+                .var("VCONTROLD_SOCKET")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .map(|s| PathBuf::from(s.strip_prefix("unix:").unwrap_or(&s))),
+            vcontrold_bin: vars
+                .var("VCONTROLD_BIN")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .unwrap_or_else(|| "vcontrold".to_string()),
+            vcontrold_config: PathBuf::from(
+                vars.var("VCONTROLD_CONFIG")
+                    .ok()
+                    .filter(|s| !s.is_empty())
+                    .unwrap_or_else(|| "/config/vcontrold.xml".to_string()),
+            ),
+            vcontrold_args: vars
+                .var("VCONTROLD_ARGS")
+                .unwrap_or_default()
+                .split_whitespace()
+                .map(str::to_string)
+                .collect(),
+            vcontrold_stop_grace: Duration::from_secs(parse_u64(vars, "VCONTROLD_STOP_GRACE", 5)?),
+            readiness_timeout: Duration::from_secs(parse_u64(
+                vars,
+                "READINESS_TIMEOUT",
+                crate::process::DEFAULT_READINESS_TIMEOUT.as_secs(),
+            )?),
+            readiness_probe_interval: parse_probe_interval(vars)?,
+            vcontrold_prompt: parse_prompt(vars.var("VCONTROLD_PROMPT").ok())?,
+            vcontrold_max_response: parse_max_response(vars)?,
+            vcontrold_late_response_wait: Duration::from_millis(parse_u64(
+                vars,
+                "VCONTROLD_LATE_RESPONSE_WAIT",
+                0,
+            )?),
+            command_retries: parse_u32(vars, "COMMAND_RETRIES", 1)?,
+            set_ranges: parse_set_ranges(&vars.var("SET_RANGES").unwrap_or_default())?,
+            stats_interval: parse_u64(vars, "STATS_INTERVAL", 0)?,
+            request_topics: parse_request_topics(
+                &vars.var("MQTT_REQUEST_TOPICS").unwrap_or_default(),
+            )?,
+            request_suffix,
+            response_suffix,
+            dry_run: parse_bool(vars, "DRY_RUN", false),
+            shutdown_grace: Duration::from_secs(parse_u64(vars, "SHUTDOWN_GRACE", 5)?),
+            numeric_commands: parse_list(&vars.var("NUMERIC_COMMANDS").unwrap_or_default())
+                .into_iter()
+                .collect(),
+            hex_commands: parse_list(&vars.var("HEX_COMMANDS").unwrap_or_default())
+                .into_iter()
+                .collect(),
+            influx: parse_influx(vars)?,
+            strict_commands: parse_bool(vars, "STRICT_COMMANDS", false),
+            strict: parse_bool(vars, "STRICT", false),
+            request_separator: vars
+                .var("REQUEST_SEPARATOR")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .unwrap_or_else(|| ",".to_string()),
+            agg_window: Duration::from_secs(parse_u64(vars, "AGG_WINDOW", 0)?),
+            agg_commands: parse_list(&vars.var("AGG_COMMANDS").unwrap_or_default())
+                .into_iter()
+                .collect(),
+            agg_raw: parse_bool(vars, "AGG_RAW", true),
+            watchdog_failures: parse_u32(vars, "WATCHDOG_FAILURES", 0)?,
+            stall_multiplier: parse_u32(vars, "STALL_MULTIPLIER", 0)?,
+            command_delay: Duration::from_millis(parse_u64(vars, "COMMAND_DELAY", 0)?),
+            poll_on_start: parse_bool(vars, "POLL_ON_START", true),
+            state_file,
+            republish_on_start,
+            clear_stale_on_start,
+            admin_enabled: parse_bool(vars, "ADMIN_ENABLED", false),
+            request_timeout: parse_request_timeout(vars)?,
+            request_deadline: Some(Duration::from_secs(parse_u64(vars, "REQUEST_DEADLINE", 0)?))
+                .filter(|deadline| !deadline.is_zero()),
+            cache_ttl: Duration::from_secs(parse_u64(vars, "CACHE_TTL", 0)?),
+            stdout_output: parse_bool(vars, "STDOUT_OUTPUT", false),
+            omit_nulls: parse_bool(vars, "OMIT_NULLS", false),
+            publish_raw: parse_bool(vars, "PUBLISH_RAW", false),
+            publish_units: parse_bool(vars, "PUBLISH_UNITS", false),
+            units: parse_map("UNITS", &vars.var("UNITS").unwrap_or_default())?,
+            vcontrold_mock: parse_mock(vars)?,
+            mqtt_get_topics: parse_bool(vars, "MQTT_GET_TOPICS", false),
+            ordered: parse_bool(vars, "ORDERED", false),
         })
     }
 
-    /// Generate a unique client ID for the publisher
+    /// Commands polled on the interval (empty when `POLLING=false`)
+    pub fn polled_commands(&self) -> &[String] {
+        if self.polling {
+            &self.commands
+        } else {
+            &[]
+        }
+    }
+
+    /// Whether the daemon would sit idle: no polled commands and no bridge
+    ///
+    /// Fails with `ConfigError::NothingEnabled` instead under `STRICT=true`.
+    pub fn check_idle(&self) -> Result<bool, ConfigError> {
+        check_idle(self.polled_commands(), self.mqtt_subscribe, self.strict)
+    }
+
+    /// Polled commands that do not fit into a batch of `MAX_LENGTH` on their own
+    ///
+    /// Fails with `ConfigError::CommandTooLong` instead under
+    /// `MAX_LENGTH_STRICT=true`. Always empty with `ORDERED=true`, which
+    /// ignores `MAX_LENGTH`.
+    pub fn oversized_commands(&self) -> Result<Vec<&str>, ConfigError> {
+        if self.ordered {
+            return Ok(Vec::new());
+        }
+        oversized_commands(
+            self.polled_commands(),
+            self.max_length,
+            self.max_length_strict,
+        )
+    }
+
+    /// Client ID for the publisher
+    ///
+    /// `MQTT_CLIENT_ID` is used verbatim if set. Otherwise the ID is
+    /// `{prefix}-{hostname}` with `MQTT_CLIENT_ID_STABLE`, or made unique per
+    /// run by appending the pid and start timestamp.
     pub fn publisher_client_id(&self) -> String {
-        let hostname = hostname::get()
-            .map(|h| h.to_string_lossy().to_string())
-            .unwrap_or_else(|_| "unknown".to_string());
+        if let Some(client_id) = &self.mqtt.client_id {
+            return client_id.clone();
+        }
+        let hostname = local_hostname();
+        if self.mqtt.client_id_stable {
+            return format!("{}-{}", self.mqtt.client_id_prefix, hostname);
+        }
         let pid = std::process::id();
         let timestamp = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
@@ -152,42 +740,850 @@ impl Config {
     /// Generate a unique client ID for the subscriber (reserved for future use)
     #[allow(dead_code)]
     pub fn subscriber_client_id(&self) -> String {
-        let hostname = hostname::get()
-            .map(|h| h.to_string_lossy().to_string())
-            .unwrap_or_else(|_| "unknown".to_string());
+        let hostname = local_hostname();
         format!("{}-sub-{}", self.mqtt.client_id_prefix, hostname)
     }
 }
 
-fn parse_bool(name: &str, default: bool) -> bool {
-    env::var(name)
+/// Hostname of this machine/container (`unknown` if it cannot be determined)
+pub fn local_hostname() -> String {
+    hostname::get()
+        .map(|h| h.to_string_lossy().to_string())
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+/// Decide about a configuration that neither polls nor bridges requests
+fn check_idle(
+    polled_commands: &[String],
+    mqtt_subscribe: bool,
+    strict: bool,
+) -> Result<bool, ConfigError> {
+    let idle = polled_commands.is_empty() && !mqtt_subscribe;
+    if idle && strict {
+        return Err(ConfigError::NothingEnabled);
+    }
+    Ok(idle)
+}
+
+/// Commands longer than `max_length`, or an error for the first under `strict`
+fn oversized_commands(
+    commands: &[String],
+    max_length: usize,
+    strict: bool,
+) -> Result<Vec<&str>, ConfigError> {
+    let oversized: Vec<&str> = commands
+        .iter()
+        .map(String::as_str)
+        .filter(|command| command.len() > max_length)
+        .collect();
+    match oversized.first() {
+        Some(command) if strict => {
+            Err(ConfigError::CommandTooLong(command.to_string(), max_length))
+        }
+        _ => Ok(oversized),
+    }
+}
+
+/// Parse a comma-separated list, skipping empty entries
+fn parse_list(raw: &str) -> Vec<String> {
+    raw.split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Normalize `MQTT_TOPIC`: strip a single trailing slash (`home/heat/`)
+///
+/// All topics are built as `{base}/...`, so a leading slash, an empty level,
+/// or a wildcard would produce topics that cannot be published to.
+fn parse_base_topic(raw: String) -> Result<String, ConfigError> {
+    let topic = raw.strip_suffix('/').unwrap_or(&raw);
+    if topic.is_empty()
+        || topic.starts_with('/')
+        || topic.split('/').any(str::is_empty)
+        || topic.contains(['+', '#'])
+    {
+        return Err(ConfigError::InvalidValue("MQTT_TOPIC", raw));
+    }
+    Ok(topic.to_string())
+}
+
+/// Parse `MQTT_REQUEST_SUFFIX` / `MQTT_RESPONSE_SUFFIX`, falling back to `default`
+///
+/// The suffix may span several levels (`cmd/in`) but, like `MQTT_TOPIC`, must
+/// not contain wildcards, empty levels or leading/trailing slashes.
+fn parse_topic_suffix(
+    name: &'static str,
+    raw: Option<String>,
+    default: &str,
+) -> Result<String, ConfigError> {
+    let Some(raw) = raw.filter(|s| !s.trim().is_empty()) else {
+        return Ok(default.to_string());
+    };
+    let suffix = raw.trim();
+    if suffix.split('/').any(str::is_empty) || suffix.contains(['+', '#']) {
+        return Err(ConfigError::InvalidValue(name, raw));
+    }
+    Ok(suffix.to_string())
+}
+
+/// Parse the request and response topic suffixes, which must differ so
+/// responses are not read back as requests
+fn parse_topic_suffixes(vars: &Vars) -> Result<(String, String), ConfigError> {
+    let request = parse_topic_suffix(
+        "MQTT_REQUEST_SUFFIX",
+        vars.var("MQTT_REQUEST_SUFFIX").ok(),
+        "request",
+    )?;
+    let response = parse_topic_suffix(
+        "MQTT_RESPONSE_SUFFIX",
+        vars.var("MQTT_RESPONSE_SUFFIX").ok(),
+        "response",
+    )?;
+    if request == response {
+        return Err(ConfigError::InvalidValue("MQTT_RESPONSE_SUFFIX", response));
+    }
+    Ok((request, response))
+}
+
+/// Parse `COMMANDS`, rejecting blank entries (`a,,b`, `" , "`)
+///
+/// A single trailing comma (`a,b,`) is ignored.
+fn parse_commands(raw: &str) -> Result<Vec<String>, ConfigError> {
+    let raw = raw.trim();
+    if raw.is_empty() {
+        return Ok(Vec::new());
+    }
+    let raw = raw.strip_suffix(',').unwrap_or(raw);
+    let commands: Vec<String> = raw.split(',').map(|s| s.trim().to_string()).collect();
+    if commands.iter().any(String::is_empty) {
+        return Err(ConfigError::InvalidValue(
+            "COMMANDS",
+            format!("empty command name in {:?}", raw),
+        ));
+    }
+    Ok(commands)
+}
+
+/// Drop repeated commands (keeping the first), and reject different commands
+/// that only differ in case or punctuation (`getTempA`, `gettempa`), since
+/// they would share one object id
+fn dedup_commands(commands: Vec<String>) -> Result<Vec<String>, ConfigError> {
+    let mut seen: HashMap<String, String> = HashMap::new();
+    let mut unique = Vec::with_capacity(commands.len());
+    for command in commands {
+        match seen.get(&sanitize_name(&command)) {
+            Some(other) if *other == command => {}
+            Some(other) => {
+                return Err(ConfigError::InvalidValue(
+                    "COMMANDS",
+                    format!("{} and {} map to the same object id", other, command),
+                ));
+            }
+            None => {
+                seen.insert(sanitize_name(&command), command.clone());
+                unique.push(command);
+            }
+        }
+    }
+    Ok(unique)
+}
+
+/// Parse the contents of `COMMANDS_FILE`: one command per line, `#` starts a
+/// comment, blank lines are skipped
+fn parse_commands_file(contents: &str) -> Result<Vec<String>, ConfigError> {
+    let commands: Vec<String> = contents
+        .lines()
+        .map(|line| line.split_once('#').map_or(line, |(command, _)| command))
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect();
+    // Batches are sent to vcontrold as one comma-joined line, so a name
+    // containing a comma would be split into different commands
+    if let Some(command) = commands.iter().find(|command| command.contains(',')) {
+        return Err(ConfigError::InvalidValue(
+            "COMMANDS_FILE",
+            format!("command name contains a comma: {}", command),
+        ));
+    }
+    Ok(commands)
+}
+
+/// Parse a `key:value,key:value` map, rejecting entries without a key or value
+fn parse_map(name: &'static str, raw: &str) -> Result<HashMap<String, String>, ConfigError> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| match entry.split_once(':') {
+            Some((key, value)) if !key.trim().is_empty() && !value.trim().is_empty() => {
+                Ok((key.trim().to_string(), value.trim().to_string()))
+            }
+            _ => Err(ConfigError::InvalidValue(name, entry.to_string())),
+        })
+        .collect()
+}
+
+/// Parse `MQTT_USER_PROPS` (`source=boiler1,site=home`), keeping the order
+fn parse_user_props(raw: &str) -> Result<Vec<(String, String)>, ConfigError> {
+    parse_list(raw)
+        .into_iter()
+        .map(|entry| match entry.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => {
+                Ok((key.trim().to_string(), value.trim().to_string()))
+            }
+            _ => Err(ConfigError::InvalidValue("MQTT_USER_PROPS", entry)),
+        })
+        .collect()
+}
+
+/// Parse `SET_RANGES` (`setTempWWsoll:10..60,...`)
+fn parse_set_ranges(raw: &str) -> Result<HashMap<String, ArgRange>, ConfigError> {
+    parse_map("SET_RANGES", raw)?
+        .into_iter()
+        .map(|(command, range)| match ArgRange::parse(&range) {
+            Some(range) => Ok((command, range)),
+            None => Err(ConfigError::InvalidValue("SET_RANGES", range)),
+        })
+        .collect()
+}
+
+/// Parse `FORMAT` (`getTempWWObenIst:%.1f,...`)
+fn parse_formats(raw: &str) -> Result<HashMap<String, NumberFormat>, ConfigError> {
+    parse_map("FORMAT", raw)?
+        .into_iter()
+        .map(|(command, spec)| match NumberFormat::parse(&spec) {
+            Some(format) => Ok((command, format)),
+            None => Err(ConfigError::InvalidValue("FORMAT", spec)),
+        })
+        .collect()
+}
+
+/// Parse `MAP` (`command:code=label;code=label,...`)
+fn parse_value_maps(raw: &str) -> Result<HashMap<String, ValueMap>, ConfigError> {
+    parse_map("MAP", raw)?
+        .into_iter()
+        .map(|(command, spec)| match ValueMap::parse(&spec) {
+            Some(map) => Ok((command, map)),
+            None => Err(ConfigError::InvalidValue("MAP", spec)),
+        })
+        .collect()
+}
+
+/// Parse `VCONTROLD_MOCK` and `VCONTROLD_MOCK_RESPONSES` (`command:response,...`)
+///
+/// The mock listens on TCP, so it cannot be combined with `VCONTROLD_SOCKET`.
+fn parse_mock(vars: &Vars) -> Result<Option<HashMap<String, String>>, ConfigError> {
+    if !parse_bool(vars, "VCONTROLD_MOCK", false) {
+        return Ok(None);
+    }
+    if let Ok(socket) = vars.var("VCONTROLD_SOCKET") {
+        if !socket.is_empty() {
+            return Err(ConfigError::InvalidValue("VCONTROLD_SOCKET", socket));
+        }
+    }
+    let raw = vars.var("VCONTROLD_MOCK_RESPONSES").unwrap_or_default();
+    parse_map("VCONTROLD_MOCK_RESPONSES", &raw).map(Some)
+}
+
+/// Parse `EMA` (`command:alpha,...`), with alpha in `(0, 1]`
+fn parse_ema(raw: &str) -> Result<HashMap<String, f64>, ConfigError> {
+    parse_map("EMA", raw)?
+        .into_iter()
+        .map(|(command, alpha)| match alpha.parse::<f64>() {
+            Ok(a) if a > 0.0 && a <= 1.0 => Ok((command, a)),
+            _ => Err(ConfigError::InvalidValue("EMA", alpha)),
+        })
+        .collect()
+}
+
+/// Parse `MQTT_REQUEST_TOPICS` (`request/topic:response/topic,...`)
+///
+/// Pairs keep their configured order; a request topic may only appear once.
+fn parse_request_topics(raw: &str) -> Result<Vec<(String, String)>, ConfigError> {
+    let invalid = |entry: &str| ConfigError::InvalidValue("MQTT_REQUEST_TOPICS", entry.to_string());
+    let mut pairs: Vec<(String, String)> = Vec::new();
+    for entry in raw.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (request, response) = entry.split_once(':').ok_or_else(|| invalid(entry))?;
+        let (request, response) = (request.trim(), response.trim());
+        if request.is_empty() || response.is_empty() || pairs.iter().any(|(r, _)| r == request) {
+            return Err(invalid(entry));
+        }
+        pairs.push((request.to_string(), response.to_string()));
+    }
+    Ok(pairs)
+}
+
+/// Parse `READINESS_PROBE_INTERVAL` (milliseconds, must be positive)
+fn parse_probe_interval(vars: &Vars) -> Result<Duration, ConfigError> {
+    let millis = parse_u64(
+        vars,
+        "READINESS_PROBE_INTERVAL",
+        crate::process::DEFAULT_READINESS_PROBE_INTERVAL.as_millis() as u64,
+    )?;
+    if millis == 0 {
+        return Err(ConfigError::InvalidValue(
+            "READINESS_PROBE_INTERVAL",
+            "0".to_string(),
+        ));
+    }
+    Ok(Duration::from_millis(millis))
+}
+
+/// Read a secret from `name`, or from the file named by `file_name` if unset
+///
+/// A trailing newline in the file is ignored.
+fn parse_secret(
+    vars: &Vars,
+    name: &'static str,
+    file_name: &'static str,
+) -> Result<Option<String>, ConfigError> {
+    if let Some(secret) = vars.var(name).ok().filter(|s| !s.is_empty()) {
+        return Ok(Some(secret));
+    }
+    match vars.var(file_name) {
+        Ok(path) if !path.is_empty() => std::fs::read_to_string(&path)
+            .map(|s| Some(s.trim_end_matches(['\r', '\n']).to_string()))
+            .map_err(|e| ConfigError::InvalidValue(file_name, format!("{}: {}", path, e))),
+        _ => Ok(None),
+    }
+}
+
+/// Parse the InfluxDB output settings (`INFLUX_BUCKET` is required with `INFLUX_URL`)
+fn parse_influx(vars: &Vars) -> Result<Option<InfluxConfig>, ConfigError> {
+    let Some(url) = vars.var("INFLUX_URL").ok().filter(|s| !s.is_empty()) else {
+        return Ok(None);
+    };
+    Ok(Some(InfluxConfig {
+        url,
+        bucket: vars
+            .var("INFLUX_BUCKET")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .ok_or(ConfigError::MissingRequired("INFLUX_BUCKET"))?,
+        org: vars.var("INFLUX_ORG").ok().filter(|s| !s.is_empty()),
+        token: vars.var("INFLUX_TOKEN").ok().filter(|s| !s.is_empty()),
+        measurement: vars
+            .var("INFLUX_MEASUREMENT")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .unwrap_or_else(|| "heating".to_string()),
+    }))
+}
+
+fn parse_transport(vars: &Vars) -> Result<MqttTransport, ConfigError> {
+    match vars.var("MQTT_TRANSPORT") {
+        Ok(v) if !v.is_empty() => {
+            MqttTransport::parse(&v).ok_or(ConfigError::InvalidValue("MQTT_TRANSPORT", v))
+        }
+        _ => Ok(MqttTransport::Tcp),
+    }
+}
+
+fn parse_topic_template(vars: &Vars) -> Result<String, ConfigError> {
+    match vars.var("TOPIC_TEMPLATE") {
+        Ok(v) if !v.is_empty() => {
+            if v.contains("{cmd}") {
+                Ok(v)
+            } else {
+                Err(ConfigError::InvalidValue("TOPIC_TEMPLATE", v))
+            }
+        }
+        _ => Ok(DEFAULT_TOPIC_TEMPLATE.to_string()),
+    }
+}
+
+/// Parse `INTERVAL_JITTER` as seconds (`5`) or a percentage of the interval (`10%`)
+///
+/// The jitter must stay below the interval so cycles are never skipped.
+fn parse_jitter(raw: &str, interval: Duration) -> Result<Duration, ConfigError> {
+    let raw = raw.trim();
+    if raw.is_empty() {
+        return Ok(Duration::ZERO);
+    }
+    let invalid = || ConfigError::InvalidValue("INTERVAL_JITTER", raw.to_string());
+    let jitter = match raw.strip_suffix('%') {
+        Some(percent) => {
+            let percent: f64 = percent.trim().parse().map_err(|_| invalid())?;
+            if !(0.0..100.0).contains(&percent) {
+                return Err(invalid());
+            }
+            interval.mul_f64(percent / 100.0)
+        }
+        None => Duration::from_secs(raw.parse().map_err(|_| invalid())?),
+    };
+    if !jitter.is_zero() && jitter >= interval {
+        return Err(invalid());
+    }
+    Ok(jitter)
+}
+
+/// Parse a QoS level; unset or empty means QoS 1, used for all other publishes
+fn parse_qos(name: &'static str, raw: Option<String>) -> Result<u8, ConfigError> {
+    match raw.as_deref().map(str::trim) {
+        None | Some("") => Ok(1),
+        Some("0") => Ok(0),
+        Some("1") => Ok(1),
+        Some("2") => Ok(2),
+        Some(_) => Err(ConfigError::InvalidValue(name, raw.unwrap_or_default())),
+    }
+}
+
+/// Parse a listen address (`HEALTH_BIND`); unset or empty means all interfaces
+///
+/// Either an IP address, combined with `port`, or a full `ip:port`.
+pub fn parse_bind(
+    name: &'static str,
+    raw: Option<String>,
+    port: u16,
+) -> Result<SocketAddr, ConfigError> {
+    let Some(raw) = raw.map(|r| r.trim().to_string()).filter(|r| !r.is_empty()) else {
+        return Ok(SocketAddr::from(([0, 0, 0, 0], port)));
+    };
+    if let Ok(ip) = raw.parse::<IpAddr>() {
+        return Ok(SocketAddr::new(ip, port));
+    }
+    raw.parse::<SocketAddr>()
+        .map_err(|_| ConfigError::InvalidValue(name, raw))
+}
+
+fn parse_keep_alive(vars: &Vars) -> Result<u16, ConfigError> {
+    match parse_u16(vars, "MQTT_KEEPALIVE", 30)? {
+        0 => Err(ConfigError::InvalidValue("MQTT_KEEPALIVE", "0".to_string())),
+        secs => Ok(secs),
+    }
+}
+
+/// Parse `VCONTROLD_PROMPT`; unset means the standard `vctrld>`
+fn parse_prompt(raw: Option<String>) -> Result<String, ConfigError> {
+    match raw {
+        None => Ok(crate::vcontrold::PROMPT.to_string()),
+        Some(prompt) if prompt.trim().is_empty() => {
+            Err(ConfigError::InvalidValue("VCONTROLD_PROMPT", prompt))
+        }
+        Some(prompt) => Ok(prompt),
+    }
+}
+
+fn parse_request_timeout(vars: &Vars) -> Result<Duration, ConfigError> {
+    match parse_u64(
+        vars,
+        "REQUEST_TIMEOUT",
+        crate::mqtt::DEFAULT_REQUEST_TIMEOUT.as_secs(),
+    )? {
+        0 => Err(ConfigError::InvalidValue(
+            "REQUEST_TIMEOUT",
+            "0".to_string(),
+        )),
+        secs => Ok(Duration::from_secs(secs)),
+    }
+}
+
+fn parse_max_response(vars: &Vars) -> Result<usize, ConfigError> {
+    match parse_usize(
+        vars,
+        "VCONTROLD_MAX_RESPONSE",
+        crate::vcontrold::DEFAULT_MAX_RESPONSE,
+    )? {
+        0 => Err(ConfigError::InvalidValue(
+            "VCONTROLD_MAX_RESPONSE",
+            "0".to_string(),
+        )),
+        bytes => Ok(bytes),
+    }
+}
+
+fn parse_bool(vars: &Vars, name: &str, default: bool) -> bool {
+    vars.var(name)
         .map(|v| matches!(v.to_lowercase().as_str(), "true" | "1" | "yes"))
         .unwrap_or(default)
 }
 
-fn parse_u16(name: &'static str, default: u16) -> Result<u16, ConfigError> {
-    match env::var(name) {
-        Ok(v) if !v.is_empty() => v
-            .parse()
-            .map_err(|_| ConfigError::InvalidValue(name, v)),
+fn parse_u16(vars: &Vars, name: &'static str, default: u16) -> Result<u16, ConfigError> {
+    match vars.var(name) {
+        Ok(v) if !v.is_empty() => v.parse().map_err(|_| ConfigError::InvalidValue(name, v)),
         _ => Ok(default),
     }
 }
 
-fn parse_u64(name: &'static str, default: u64) -> Result<u64, ConfigError> {
-    match env::var(name) {
-        Ok(v) if !v.is_empty() => v
-            .parse()
-            .map_err(|_| ConfigError::InvalidValue(name, v)),
+fn parse_u32(vars: &Vars, name: &'static str, default: u32) -> Result<u32, ConfigError> {
+    match vars.var(name) {
+        Ok(v) if !v.is_empty() => v.parse().map_err(|_| ConfigError::InvalidValue(name, v)),
         _ => Ok(default),
     }
 }
 
-fn parse_usize(name: &'static str, default: usize) -> Result<usize, ConfigError> {
-    match env::var(name) {
-        Ok(v) if !v.is_empty() => v
-            .parse()
-            .map_err(|_| ConfigError::InvalidValue(name, v)),
+fn parse_u64(vars: &Vars, name: &'static str, default: u64) -> Result<u64, ConfigError> {
+    match vars.var(name) {
+        Ok(v) if !v.is_empty() => v.parse().map_err(|_| ConfigError::InvalidValue(name, v)),
         _ => Ok(default),
     }
 }
+
+fn parse_usize(vars: &Vars, name: &'static str, default: usize) -> Result<usize, ConfigError> {
+    match vars.var(name) {
+        Ok(v) if !v.is_empty() => v.parse().map_err(|_| ConfigError::InvalidValue(name, v)),
+        _ => Ok(default),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_map() {
+        let map = parse_map("TEST", " a:1 , b:x/y ,").unwrap();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["a"], "1");
+        assert_eq!(map["b"], "x/y");
+        assert!(parse_map("TEST", "").unwrap().is_empty());
+        assert!(parse_map("TEST", "novalue").is_err());
+        assert!(parse_map("TEST", "a:").is_err());
+    }
+
+    #[test]
+    fn test_mqtt_transport_parse() {
+        assert_eq!(MqttTransport::parse("tcp"), Some(MqttTransport::Tcp));
+        assert_eq!(MqttTransport::parse("WS"), Some(MqttTransport::Ws));
+        assert_eq!(MqttTransport::parse("wss"), Some(MqttTransport::Wss));
+        assert_eq!(MqttTransport::parse("quic"), None);
+    }
+
+    #[test]
+    fn test_publish_retain_per_command() {
+        let config = PublishConfig {
+            no_retain_commands: HashSet::from(["getError".to_string()]),
+            ..PublishConfig::default()
+        };
+        assert!(config.retain("getTempA"));
+        assert!(!config.retain("getError"));
+
+        let config = PublishConfig {
+            retain: false,
+            ..PublishConfig::default()
+        };
+        assert!(!config.retain("getTempA"));
+    }
+
+    #[test]
+    fn test_parse_request_topics() {
+        let pairs =
+            parse_request_topics("home/heat/zone1/request:home/heat/zone1/response, a/req:a/resp")
+                .unwrap();
+        assert_eq!(
+            pairs,
+            vec![
+                (
+                    "home/heat/zone1/request".to_string(),
+                    "home/heat/zone1/response".to_string()
+                ),
+                ("a/req".to_string(), "a/resp".to_string()),
+            ]
+        );
+        assert!(parse_request_topics("").unwrap().is_empty());
+        assert!(parse_request_topics("a/req").is_err());
+        assert!(parse_request_topics("a/req:x,a/req:y").is_err());
+    }
+
+    #[test]
+    fn test_parse_formats() {
+        let formats = parse_formats("getTempWWObenIst:%.1f,getPumpe:%d").unwrap();
+        assert_eq!(formats["getTempWWObenIst"].format(48.0), "48.0");
+        assert_eq!(formats["getTempWWObenIst"].format(48.06), "48.1");
+        assert_eq!(formats["getPumpe"].format(1.0), "1");
+        assert_eq!(NumberFormat::parse("%f").unwrap().format(0.5), "0.500000");
+
+        assert!(parse_formats("getTempA:%s").is_err());
+        assert!(parse_formats("getTempA:%.xf").is_err());
+        assert!(parse_formats("getTempA:1f").is_err());
+    }
+
+    #[test]
+    fn test_parse_value_maps() {
+        let maps =
+            parse_value_maps("getStatus:0=Standby; 1=Heating;2=Error,getPumpe:1=on").unwrap();
+        assert_eq!(maps["getStatus"].label(0.0), Some("Standby"));
+        assert_eq!(maps["getStatus"].label(2.0), Some("Error"));
+        assert_eq!(maps["getStatus"].label(3.0), None);
+        assert_eq!(maps["getPumpe"].label(1.0), Some("on"));
+
+        assert!(parse_value_maps("getStatus:0").is_err());
+        assert!(parse_value_maps("getStatus:x=Standby").is_err());
+        assert!(parse_value_maps("getStatus:0=").is_err());
+        assert!(parse_value_maps("getStatus:;").is_err());
+    }
+
+    #[test]
+    fn test_parse_ema() {
+        let ema = parse_ema("getTempReturn:0.3, getTempA:1").unwrap();
+        assert_eq!(ema["getTempReturn"], 0.3);
+        assert_eq!(ema["getTempA"], 1.0);
+        assert!(parse_ema("").unwrap().is_empty());
+        assert!(parse_ema("getTempA:0").is_err());
+        assert!(parse_ema("getTempA:1.5").is_err());
+        assert!(parse_ema("getTempA:x").is_err());
+    }
+
+    #[test]
+    fn test_parse_prompt() {
+        assert_eq!(parse_prompt(None).unwrap(), "vctrld>");
+        assert_eq!(parse_prompt(Some("vcontrol>".into())).unwrap(), "vcontrol>");
+        assert!(parse_prompt(Some(String::new())).is_err());
+        assert!(parse_prompt(Some("  ".into())).is_err());
+    }
+
+    #[test]
+    fn test_parse_jitter() {
+        let interval = Duration::from_secs(60);
+        assert_eq!(parse_jitter("", interval).unwrap(), Duration::ZERO);
+        assert_eq!(parse_jitter("5", interval).unwrap(), Duration::from_secs(5));
+        assert_eq!(
+            parse_jitter("10%", interval).unwrap(),
+            Duration::from_secs(6)
+        );
+        assert!(parse_jitter("60", interval).is_err());
+        assert!(parse_jitter("100%", interval).is_err());
+        assert!(parse_jitter("-5%", interval).is_err());
+        assert!(parse_jitter("soon", interval).is_err());
+    }
+
+    #[test]
+    fn test_parse_set_ranges() {
+        let ranges = parse_set_ranges("setTempWWsoll:10..60,setPumpe:0..1").unwrap();
+        assert_eq!(
+            ranges["setTempWWsoll"],
+            ArgRange {
+                min: 10.0,
+                max: 60.0
+            }
+        );
+        assert!(ranges["setTempWWsoll"].contains(60.0));
+        assert!(!ranges["setTempWWsoll"].contains(90.0));
+        assert!(parse_set_ranges("setTempWWsoll:60..10").is_err());
+        assert!(parse_set_ranges("setTempWWsoll:ten..60").is_err());
+    }
+
+    #[test]
+    fn test_parse_base_topic_trailing_slash() {
+        assert_eq!(parse_base_topic("home/heat/".into()).unwrap(), "home/heat");
+        assert_eq!(parse_base_topic("home/heat".into()).unwrap(), "home/heat");
+        assert_eq!(parse_base_topic("vcontrold".into()).unwrap(), "vcontrold");
+        assert!(parse_base_topic("home/heat//".into()).is_err());
+        assert!(parse_base_topic("/".into()).is_err());
+        assert!(parse_base_topic(String::new()).is_err());
+    }
+
+    #[test]
+    fn test_parse_base_topic_rejects_wildcards_and_leading_slash() {
+        assert!(parse_base_topic("home/+/heat".into()).is_err());
+        assert!(parse_base_topic("home/#".into()).is_err());
+        assert!(parse_base_topic("/home/heat".into()).is_err());
+        assert!(parse_base_topic("home//heat".into()).is_err());
+    }
+
+    #[test]
+    fn test_parse_topic_suffix() {
+        let parse =
+            |raw: &str| parse_topic_suffix("MQTT_REQUEST_SUFFIX", Some(raw.into()), "request");
+        assert_eq!(parse("cmd").unwrap(), "cmd");
+        assert_eq!(parse(" cmd/in ").unwrap(), "cmd/in");
+        assert_eq!(parse("").unwrap(), "request");
+        assert_eq!(
+            parse_topic_suffix("MQTT_REQUEST_SUFFIX", None, "request").unwrap(),
+            "request"
+        );
+        assert!(parse("/cmd").is_err());
+        assert!(parse("cmd/").is_err());
+        assert!(parse("cmd/+").is_err());
+        assert!(parse("#").is_err());
+    }
+
+    #[test]
+    fn test_dedup_commands() {
+        let commands = |list: &[&str]| list.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            dedup_commands(commands(&["getTempA", "getTempB"])).unwrap(),
+            vec!["getTempA", "getTempB"]
+        );
+        assert_eq!(
+            dedup_commands(commands(&["getTempA", "getTempB", "getTempA"])).unwrap(),
+            vec!["getTempA", "getTempB"]
+        );
+        assert!(dedup_commands(commands(&["getTempA", "gettempa"])).is_err());
+        assert!(dedup_commands(commands(&["dev1.getTempA", "dev1_getTempA"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(
+            parse_commands(" getTempA, getTempB ").unwrap(),
+            vec!["getTempA", "getTempB"]
+        );
+        assert!(parse_commands("").unwrap().is_empty());
+        assert!(parse_commands("getTempA,,getTempB").is_err());
+        assert!(parse_commands(",getTempA").is_err());
+        assert_eq!(parse_commands("getTempA, ").unwrap(), vec!["getTempA"]);
+    }
+
+    #[test]
+    fn test_publisher_client_id_override() {
+        let mut config = Config::from_lookup(|name| match name {
+            "MQTT_HOST" => Some("localhost".to_string()),
+            "MQTT_TOPIC" => Some("test".to_string()),
+            _ => None,
+        })
+        .unwrap();
+        config.mqtt.client_id_prefix = "vcontrold".to_string();
+
+        config.mqtt.client_id = Some("heating-test-1".to_string());
+        assert_eq!(config.publisher_client_id(), "heating-test-1");
+
+        config.mqtt.client_id = None;
+        let generated = config.publisher_client_id();
+        assert!(generated.starts_with("vcontrold-pub-"));
+        assert!(generated.contains(&local_hostname()));
+    }
+
+    #[test]
+    fn test_parse_commands_file() {
+        let contents = "# Temperatures\ngetTempA\n\n  getTempWWist  # hot water\n#getTempB\n";
+        assert_eq!(
+            parse_commands_file(contents).unwrap(),
+            vec!["getTempA", "getTempWWist"]
+        );
+        assert!(parse_commands_file("").unwrap().is_empty());
+        let err = parse_commands_file("getTempA\ngetTempB,getTempC\n").unwrap_err();
+        assert!(err.to_string().contains("getTempB,getTempC"));
+    }
+
+    #[test]
+    fn test_parse_commands_all_blank() {
+        assert!(matches!(
+            parse_commands(" , ,"),
+            Err(ConfigError::InvalidValue("COMMANDS", _))
+        ));
+        assert!(matches!(
+            parse_commands(" , "),
+            Err(ConfigError::InvalidValue("COMMANDS", _))
+        ));
+        assert!(matches!(
+            parse_commands(","),
+            Err(ConfigError::InvalidValue("COMMANDS", _))
+        ));
+    }
+
+    #[test]
+    fn test_from_lookup_commands() {
+        let load = |commands: &str| {
+            let commands = commands.to_string();
+            Config::from_lookup(move |name| match name {
+                "MQTT_HOST" => Some("localhost".to_string()),
+                "MQTT_TOPIC" => Some("test".to_string()),
+                "COMMANDS" => Some(commands.clone()),
+                _ => None,
+            })
+        };
+        assert_eq!(
+            load("getTempA, getTempB,").unwrap().commands,
+            vec!["getTempA", "getTempB"]
+        );
+        assert!(matches!(
+            load("getTempA,,getTempB"),
+            Err(ConfigError::InvalidValue("COMMANDS", _))
+        ));
+    }
+
+    #[test]
+    fn test_start_options_require_polling() {
+        let load = |polling: &'static str| {
+            Config::from_lookup(move |name| {
+                let value = match name {
+                    "MQTT_HOST" => "localhost",
+                    "MQTT_TOPIC" => "test",
+                    "COMMANDS" => "getTempA",
+                    "STATE_FILE" => "/tmp/state.json",
+                    "CLEAR_STALE_ON_START" => "true",
+                    "POLLING" => polling,
+                    _ => return None,
+                };
+                Some(value.to_string())
+            })
+        };
+        assert!(load("true").unwrap().clear_stale_on_start);
+        assert!(matches!(
+            load("false"),
+            Err(ConfigError::InvalidValue("CLEAR_STALE_ON_START", _))
+        ));
+    }
+
+    #[test]
+    fn test_parse_qos() {
+        let qos = |raw: Option<&str>| parse_qos("MQTT_RESPONSE_QOS", raw.map(str::to_string));
+        assert_eq!(qos(None).unwrap(), 1);
+        assert_eq!(qos(Some("")).unwrap(), 1);
+        assert_eq!(qos(Some("2")).unwrap(), 2);
+        assert_eq!(qos(Some("0")).unwrap(), 0);
+        assert!(matches!(
+            qos(Some("3")),
+            Err(ConfigError::InvalidValue("MQTT_RESPONSE_QOS", v)) if v == "3"
+        ));
+    }
+
+    #[test]
+    fn test_parse_user_props() {
+        assert_eq!(
+            parse_user_props("source=boiler1, site = home,").unwrap(),
+            vec![
+                ("source".to_string(), "boiler1".to_string()),
+                ("site".to_string(), "home".to_string()),
+            ]
+        );
+        assert!(parse_user_props("").unwrap().is_empty());
+        assert!(parse_user_props("source").is_err());
+        assert!(parse_user_props("=boiler1").is_err());
+    }
+
+    #[test]
+    fn test_check_idle() {
+        let commands = vec!["getTempA".to_string()];
+        assert!(!check_idle(&commands, false, true).unwrap());
+        assert!(!check_idle(&[], true, true).unwrap());
+        assert!(check_idle(&[], false, false).unwrap());
+        assert!(matches!(
+            check_idle(&[], false, true),
+            Err(ConfigError::NothingEnabled)
+        ));
+    }
+
+    #[test]
+    fn test_oversized_commands() {
+        let commands = vec!["getTempA".to_string(), "getTempWWObenIst".to_string()];
+        // A command exactly at the limit fits into a batch of its own
+        assert!(oversized_commands(&commands, 16, true).unwrap().is_empty());
+        assert_eq!(
+            oversized_commands(&commands, 15, false).unwrap(),
+            vec!["getTempWWObenIst"]
+        );
+        assert!(matches!(
+            oversized_commands(&commands, 15, true),
+            Err(ConfigError::CommandTooLong(command, 15)) if command == "getTempWWObenIst"
+        ));
+    }
+
+    #[test]
+    fn test_parse_bind() {
+        let bind = |raw: Option<&str>| {
+            parse_bind("HEALTH_BIND", raw.map(str::to_string), 8080).map(|a| a.to_string())
+        };
+        assert_eq!(bind(None).unwrap(), "0.0.0.0:8080");
+        assert_eq!(bind(Some("")).unwrap(), "0.0.0.0:8080");
+        assert_eq!(bind(Some("127.0.0.1")).unwrap(), "127.0.0.1:8080");
+        assert_eq!(bind(Some("::1")).unwrap(), "[::1]:8080");
+        assert_eq!(bind(Some("10.0.0.5:9000")).unwrap(), "10.0.0.5:9000");
+        assert!(matches!(
+            bind(Some("localhost")),
+            Err(ConfigError::InvalidValue("HEALTH_BIND", v)) if v == "localhost"
+        ));
+    }
+}
@@ -17,6 +17,12 @@ pub enum Error {
     #[error("process error: {0}")]
     Process(#[from] ProcessError),
 
+    #[error("InfluxDB error: {0}")]
+    Influx(#[from] InfluxError),
+
+    #[error("polling loop made no progress for {0} seconds")]
+    PollingStalled(u64),
+
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
 }
@@ -31,7 +37,6 @@ pub enum VcontroldError {
     ConnectionLost,
 
     #[error("protocol error: {0}")]
-    #[allow(dead_code)]
     Protocol(String),
 
     #[error("command error: {0}")]
@@ -40,10 +45,43 @@ pub enum VcontroldError {
     #[error("timeout waiting for response")]
     Timeout,
 
+    #[error("skipped after a previous command failed")]
+    Skipped,
+
+    #[error("not started because another command of the atomic request was rejected")]
+    NotStarted,
+
+    #[error("request deadline exceeded")]
+    DeadlineExceeded,
+
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
 }
 
+impl VcontroldError {
+    /// Whether the error is a connection-level failure that a retry on a
+    /// fresh connection may resolve (as opposed to a deterministic `ERR:`
+    /// response or an invalid command)
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            VcontroldError::ConnectionLost | VcontroldError::Timeout
+        )
+    }
+
+    /// Whether the connection broke while sending the command or reading
+    /// its response (the peer closed it or the socket failed)
+    pub fn is_connection_lost(&self) -> bool {
+        matches!(self, VcontroldError::ConnectionLost | VcontroldError::Io(_))
+    }
+
+    /// Whether vcontrold itself is unreachable (no connection could be made
+    /// or it broke), as opposed to a failure of the individual command
+    pub fn is_connection_error(&self) -> bool {
+        matches!(self, VcontroldError::ConnectionFailed(_)) || self.is_connection_lost()
+    }
+}
+
 /// Errors related to MQTT operations
 #[derive(Error, Debug)]
 pub enum MqttError {
@@ -53,9 +91,42 @@ pub enum MqttError {
     #[error("publish failed: {0}")]
     PublishFailed(String),
 
+    #[error("request channel full")]
+    ChannelFull,
+
+    #[error("no private key found in {0}")]
+    NoPrivateKey(String),
+
+    #[error("wrong passphrase for encrypted private key")]
+    WrongKeyPassphrase,
+
+    #[error("cannot decrypt private key: {0}")]
+    KeyDecryption(String),
+
     #[error("client error: {0}")]
-    #[allow(dead_code)]
     Client(String),
+
+    #[error("broker rejected the login: {0}")]
+    AuthFailed(String),
+
+    #[error("could not connect to the MQTT broker after {0} attempts: {1}")]
+    InitialConnectFailed(u32, String),
+}
+
+/// Errors related to the InfluxDB output
+#[derive(Error, Debug)]
+pub enum InfluxError {
+    #[error("invalid URL: {0}")]
+    InvalidUrl(String),
+
+    #[error("write failed: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("write timeout")]
+    Timeout,
+
+    #[error("unexpected response: {0}")]
+    Status(String),
 }
 
 /// Errors related to process management
@@ -75,6 +146,9 @@ pub enum ProcessError {
 
     #[error("config file not found: {0}")]
     ConfigNotFound(String),
+
+    #[error("serial device {0} did not appear within {1} seconds")]
+    DeviceNotFound(String, u64),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
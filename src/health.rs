@@ -5,6 +5,7 @@
 //! Also provides a `check_health` client function used by the `--healthcheck`
 //! CLI flag for Docker's `HEALTHCHECK CMD`.
 
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
@@ -20,6 +21,8 @@ pub struct HealthState {
     pub vcontrold_connected: Arc<AtomicBool>,
     /// MQTT broker connection is active
     pub mqtt_connected: Arc<AtomicBool>,
+    /// Serial device still exists (`USB_CHECK_INTERVAL`)
+    pub usb_device: Arc<AtomicBool>,
 }
 
 impl HealthState {
@@ -28,6 +31,7 @@ impl HealthState {
         self.vcontrold_running.load(Ordering::Relaxed)
             && self.vcontrold_connected.load(Ordering::Relaxed)
             && self.mqtt_connected.load(Ordering::Relaxed)
+            && self.usb_device.load(Ordering::Relaxed)
     }
 
     /// Build a JSON response body
@@ -35,23 +39,23 @@ impl HealthState {
         let vcontrold_running = self.vcontrold_running.load(Ordering::Relaxed);
         let vcontrold_connected = self.vcontrold_connected.load(Ordering::Relaxed);
         let mqtt_connected = self.mqtt_connected.load(Ordering::Relaxed);
-        let healthy = vcontrold_running && vcontrold_connected && mqtt_connected;
+        let usb_device = self.usb_device.load(Ordering::Relaxed);
+        let healthy = vcontrold_running && vcontrold_connected && mqtt_connected && usb_device;
 
         format!(
-            r#"{{"healthy":{},"vcontrold_process":{},"vcontrold_connection":{},"mqtt_connected":{}}}"#,
-            healthy, vcontrold_running, vcontrold_connected, mqtt_connected,
+            r#"{{"healthy":{},"vcontrold_process":{},"vcontrold_connection":{},"mqtt_connected":{},"usb_device":{}}}"#,
+            healthy, vcontrold_running, vcontrold_connected, mqtt_connected, usb_device,
         )
     }
 }
 
 /// Run the health check HTTP server
 ///
-/// Listens on `0.0.0.0:<port>` and responds to every request with the current
-/// health status as JSON. No HTTP parsing beyond reading enough to drain the
-/// request — any TCP connection gets a response.
-pub async fn run_health_server(port: u16, state: Arc<HealthState>) {
-    let addr = format!("0.0.0.0:{}", port);
-    let listener = match TcpListener::bind(&addr).await {
+/// Listens on `addr` (`HEALTH_BIND`:`HEALTHCHECK_PORT`) and responds to every
+/// request with the current health status as JSON. No HTTP parsing beyond
+/// reading enough to drain the request — any TCP connection gets a response.
+pub async fn run_health_server(addr: SocketAddr, state: Arc<HealthState>) {
+    let listener = match TcpListener::bind(addr).await {
         Ok(l) => {
             info!("Health endpoint listening on {}", addr);
             l
@@ -104,17 +108,16 @@ async fn serve_health(listener: TcpListener, state: Arc<HealthState>) {
 
 /// Synchronous health check client used by `--healthcheck` CLI flag.
 ///
-/// Connects to the health endpoint, sends a minimal HTTP GET, and returns
-/// `true` if the server responds with 200.
-pub fn check_health(port: u16) -> bool {
+/// Connects to the health endpoint bound to `addr` (see `probe_addr`), sends
+/// a minimal HTTP GET, and returns `true` if the server responds with 200.
+pub fn check_health(addr: SocketAddr) -> bool {
     use std::io::{Read, Write};
     use std::net::TcpStream;
     use std::time::Duration;
 
-    let addr = format!("127.0.0.1:{}", port);
     let timeout = Duration::from_secs(5);
 
-    let mut stream = match TcpStream::connect_timeout(&addr.parse().unwrap(), timeout) {
+    let mut stream = match TcpStream::connect_timeout(&probe_addr(addr), timeout) {
         Ok(s) => s,
         Err(_) => return false,
     };
@@ -135,6 +138,17 @@ pub fn check_health(port: u16) -> bool {
     response.starts_with("HTTP/1.1 200")
 }
 
+/// Address the `--healthcheck` probe connects to for a server bound to `bind`
+///
+/// A wildcard bind is reached via loopback; a specific address only on itself.
+pub fn probe_addr(bind: SocketAddr) -> SocketAddr {
+    match bind.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => (Ipv4Addr::LOCALHOST, bind.port()).into(),
+        IpAddr::V6(ip) if ip.is_unspecified() => (Ipv6Addr::LOCALHOST, bind.port()).into(),
+        _ => bind,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -145,6 +159,7 @@ mod tests {
             vcontrold_running: Arc::new(AtomicBool::new(true)),
             vcontrold_connected: Arc::new(AtomicBool::new(true)),
             mqtt_connected: Arc::new(AtomicBool::new(true)),
+            usb_device: Arc::new(AtomicBool::new(true)),
         };
         assert!(state.is_healthy());
         let json = state.to_json();
@@ -160,6 +175,7 @@ mod tests {
             vcontrold_running: Arc::new(AtomicBool::new(true)),
             vcontrold_connected: Arc::new(AtomicBool::new(true)),
             mqtt_connected: Arc::new(AtomicBool::new(false)),
+            usb_device: Arc::new(AtomicBool::new(true)),
         };
         assert!(!state.is_healthy());
         let json = state.to_json();
@@ -173,6 +189,7 @@ mod tests {
             vcontrold_running: Arc::new(AtomicBool::new(false)),
             vcontrold_connected: Arc::new(AtomicBool::new(true)),
             mqtt_connected: Arc::new(AtomicBool::new(true)),
+            usb_device: Arc::new(AtomicBool::new(true)),
         };
         assert!(!state.is_healthy());
         let json = state.to_json();
@@ -186,6 +203,7 @@ mod tests {
             vcontrold_running: Arc::new(AtomicBool::new(true)),
             vcontrold_connected: Arc::new(AtomicBool::new(false)),
             mqtt_connected: Arc::new(AtomicBool::new(true)),
+            usb_device: Arc::new(AtomicBool::new(true)),
         };
         assert!(!state.is_healthy());
         let json = state.to_json();
@@ -193,16 +211,31 @@ mod tests {
         assert!(json.contains(r#""vcontrold_connection":false"#));
     }
 
+    #[test]
+    fn health_state_usb_device_missing() {
+        let state = HealthState {
+            vcontrold_running: Arc::new(AtomicBool::new(true)),
+            vcontrold_connected: Arc::new(AtomicBool::new(true)),
+            mqtt_connected: Arc::new(AtomicBool::new(true)),
+            usb_device: Arc::new(AtomicBool::new(false)),
+        };
+        assert!(!state.is_healthy());
+        let json = state.to_json();
+        assert!(json.contains(r#""healthy":false"#));
+        assert!(json.contains(r#""usb_device":false"#));
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn health_server_returns_200_when_healthy() {
         let state = Arc::new(HealthState {
             vcontrold_running: Arc::new(AtomicBool::new(true)),
             vcontrold_connected: Arc::new(AtomicBool::new(true)),
             mqtt_connected: Arc::new(AtomicBool::new(true)),
+            usb_device: Arc::new(AtomicBool::new(true)),
         });
 
         let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
-        let port = listener.local_addr().unwrap().port();
+        let addr = listener.local_addr().unwrap();
 
         let state_clone = Arc::clone(&state);
         let server = tokio::spawn(async move {
@@ -212,7 +245,7 @@ mod tests {
         // Give the spawned task a chance to start polling accept()
         tokio::time::sleep(std::time::Duration::from_millis(50)).await;
 
-        assert!(check_health(port));
+        assert!(check_health(addr));
 
         server.abort();
     }
@@ -223,10 +256,11 @@ mod tests {
             vcontrold_running: Arc::new(AtomicBool::new(true)),
             vcontrold_connected: Arc::new(AtomicBool::new(false)),
             mqtt_connected: Arc::new(AtomicBool::new(true)),
+            usb_device: Arc::new(AtomicBool::new(true)),
         });
 
         let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
-        let port = listener.local_addr().unwrap().port();
+        let addr = listener.local_addr().unwrap();
 
         let state_clone = Arc::clone(&state);
         let server = tokio::spawn(async move {
@@ -235,8 +269,16 @@ mod tests {
 
         tokio::time::sleep(std::time::Duration::from_millis(50)).await;
 
-        assert!(!check_health(port));
+        assert!(!check_health(addr));
 
         server.abort();
     }
+
+    #[test]
+    fn probe_addr_uses_loopback_for_wildcard_binds() {
+        let addr = |s: &str| s.parse::<SocketAddr>().unwrap();
+        assert_eq!(probe_addr(addr("0.0.0.0:8080")), addr("127.0.0.1:8080"));
+        assert_eq!(probe_addr(addr("[::]:8080")), addr("[::1]:8080"));
+        assert_eq!(probe_addr(addr("10.0.0.5:9000")), addr("10.0.0.5:9000"));
+    }
 }
@@ -0,0 +1,460 @@
+//! InfluxDB output for polled values
+//!
+//! Writes each polling cycle as a batch of line-protocol points via the
+//! InfluxDB v2 HTTP write API, using a minimal HTTP/1.1 client over raw TCP
+//! (optionally TLS). Write failures are logged by the caller and never fatal.
+
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rustls::pki_types::ServerName;
+use rustls::ClientConfig;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tokio_rustls::TlsConnector;
+use tracing::{debug, warn};
+
+use crate::config::InfluxConfig;
+use crate::error::InfluxError;
+use crate::sink::{OutputSink, SinkFuture};
+use crate::vcontrold::{format_hex, CommandResult, Value};
+
+/// Timeout for a complete write request
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Field of numeric values
+const VALUE_FIELD: &str = "value";
+/// Field of string and hex byte values
+const STRING_FIELD: &str = "value_str";
+
+/// Parsed `http://` or `https://` base URL
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct BaseUrl {
+    tls: bool,
+    host: String,
+    port: u16,
+    /// Path prefix without trailing slash (empty for the root)
+    path: String,
+}
+
+impl BaseUrl {
+    fn parse(url: &str) -> Result<Self, InfluxError> {
+        let invalid = || InfluxError::InvalidUrl(url.to_string());
+        let (tls, rest) = if let Some(rest) = url.strip_prefix("https://") {
+            (true, rest)
+        } else if let Some(rest) = url.strip_prefix("http://") {
+            (false, rest)
+        } else {
+            return Err(invalid());
+        };
+
+        let (authority, path) = match rest.find('/') {
+            Some(idx) => (&rest[..idx], rest[idx..].trim_end_matches('/')),
+            None => (rest, ""),
+        };
+        let default_port = if tls { 443 } else { 80 };
+        // IPv6 literals are bracketed (`[::1]:8086`); the brackets are not
+        // part of the address to connect to
+        let (host, port) = if let Some(rest) = authority.strip_prefix('[') {
+            let (host, after) = rest.split_once(']').ok_or_else(invalid)?;
+            match after {
+                "" => (host, default_port),
+                _ => {
+                    let port = after.strip_prefix(':').ok_or_else(invalid)?;
+                    (host, port.parse().map_err(|_| invalid())?)
+                }
+            }
+        } else {
+            match authority.rsplit_once(':') {
+                Some((host, port)) => (host, port.parse().map_err(|_| invalid())?),
+                None => (authority, default_port),
+            }
+        };
+        // An unbracketed IPv6 address would have been split at the wrong colon
+        if host.is_empty() || (host.contains(':') && !authority.starts_with('[')) {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            tls,
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+
+    /// Host as written in the `Host` header (IPv6 addresses bracketed)
+    fn host_header(&self) -> String {
+        if self.host.contains(':') {
+            format!("[{}]", self.host)
+        } else {
+            self.host.clone()
+        }
+    }
+}
+
+/// Writes polled values to InfluxDB
+pub struct InfluxSink {
+    base: BaseUrl,
+    /// Request target of the write endpoint, including the query string
+    write_target: String,
+    token: Option<String>,
+    measurement: String,
+    tls: Option<TlsConnector>,
+}
+
+impl InfluxSink {
+    /// Create a sink from configuration (does not connect)
+    pub fn new(config: &InfluxConfig) -> Result<Self, InfluxError> {
+        let base = BaseUrl::parse(&config.url)?;
+
+        let mut write_target = format!(
+            "{}/api/v2/write?bucket={}&precision=s",
+            base.path,
+            percent_encode(&config.bucket)
+        );
+        if let Some(org) = &config.org {
+            write_target.push_str(&format!("&org={}", percent_encode(org)));
+        }
+
+        let tls = base.tls.then(|| {
+            let mut roots = rustls::RootCertStore::empty();
+            roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+            let config = ClientConfig::builder()
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+            TlsConnector::from(Arc::new(config))
+        });
+
+        Ok(Self {
+            base,
+            write_target,
+            token: config.token.clone(),
+            measurement: config.measurement.clone(),
+            tls,
+        })
+    }
+
+    /// Write one polling cycle as a single batch
+    ///
+    /// Failed results are skipped; nothing is sent if no point remains.
+    pub async fn write(
+        &self,
+        results: &[CommandResult],
+        time: SystemTime,
+    ) -> Result<(), InfluxError> {
+        let timestamp = time
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let body: Vec<String> = results
+            .iter()
+            .filter_map(|result| format_point(&self.measurement, result, timestamp))
+            .collect();
+        if body.is_empty() {
+            return Ok(());
+        }
+
+        debug!("Writing {} points to InfluxDB", body.len());
+        timeout(WRITE_TIMEOUT, self.post(&body.join("\n")))
+            .await
+            .map_err(|_| InfluxError::Timeout)?
+    }
+
+    /// POST the body to the write endpoint and check the status code
+    async fn post(&self, body: &str) -> Result<(), InfluxError> {
+        let stream = TcpStream::connect((self.base.host.as_str(), self.base.port)).await?;
+        match &self.tls {
+            Some(connector) => {
+                let server_name = ServerName::try_from(self.base.host.clone())
+                    .map_err(|_| InfluxError::InvalidUrl(self.base.host.clone()))?;
+                let stream = connector.connect(server_name, stream).await?;
+                self.send_request(stream, body).await
+            }
+            None => self.send_request(stream, body).await,
+        }
+    }
+
+    async fn send_request<S>(&self, mut stream: S, body: &str) -> Result<(), InfluxError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut request = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n",
+            self.write_target,
+            self.base.host_header(),
+            body.len()
+        );
+        if let Some(token) = &self.token {
+            request.push_str(&format!("Authorization: Token {}\r\n", token));
+        }
+        request.push_str("\r\n");
+        request.push_str(body);
+
+        stream.write_all(request.as_bytes()).await?;
+        stream.flush().await?;
+
+        // Connection: close, so the server ends the response
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await?;
+        let response = String::from_utf8_lossy(&response);
+        let status_line = response.lines().next().unwrap_or_default();
+        match status_line.split_whitespace().nth(1) {
+            Some(code) if code.starts_with('2') => Ok(()),
+            _ => Err(InfluxError::Status(status_line.to_string())),
+        }
+    }
+}
+
+/// Each cycle becomes one write request; failures are logged, never fatal
+impl OutputSink for InfluxSink {
+    fn publish_cycle<'a>(
+        &'a self,
+        results: &'a [CommandResult],
+        time: SystemTime,
+    ) -> SinkFuture<'a> {
+        Box::pin(async move {
+            if let Err(e) = self.write(results, time).await {
+                warn!("Failed to write to InfluxDB: {}", e);
+            }
+        })
+    }
+}
+
+/// Format a result as a line-protocol point: `heating,command=getTempA value=21.5 1700000000`
+///
+/// Numbers go to the float field `value`, strings and hex bytes to the string
+/// field `value_str`: InfluxDB rejects a field whose type differs between
+/// points of a measurement. Returns `None` for failed results, results
+/// without a value and non-finite numbers, which line protocol cannot express.
+fn format_point(measurement: &str, result: &CommandResult, timestamp: u64) -> Option<String> {
+    if result.error.is_some() {
+        return None;
+    }
+    let field = match &result.value {
+        Value::Number(n) if n.is_finite() => format!("{}={}", VALUE_FIELD, n),
+        Value::Number(_) => return None,
+        Value::String(s) => format!("{}={}", STRING_FIELD, quote(s)),
+        Value::Bytes(bytes) => format!("{}={}", STRING_FIELD, quote(&format_hex(bytes))),
+        Value::None => return None,
+    };
+    Some(format!(
+        "{},command={} {} {}",
+        escape(measurement, &[',', ' ']),
+        escape(&result.command, &[',', '=', ' ']),
+        field,
+        timestamp
+    ))
+}
+
+/// Quote a string field value
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Backslash-escape the given characters
+fn escape(s: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if special.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Percent-encode a query parameter value
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_base_url_parse() {
+        assert_eq!(
+            BaseUrl::parse("https://influx.example.com").unwrap(),
+            BaseUrl {
+                tls: true,
+                host: "influx.example.com".to_string(),
+                port: 443,
+                path: String::new(),
+            }
+        );
+        assert_eq!(
+            BaseUrl::parse("http://localhost:8086/influx/").unwrap(),
+            BaseUrl {
+                tls: false,
+                host: "localhost".to_string(),
+                port: 8086,
+                path: "/influx".to_string(),
+            }
+        );
+        assert_eq!(
+            BaseUrl::parse("http://[::1]:8086").unwrap(),
+            BaseUrl {
+                tls: false,
+                host: "::1".to_string(),
+                port: 8086,
+                path: String::new(),
+            }
+        );
+        assert_eq!(BaseUrl::parse("https://[fd00::5]/").unwrap().port, 443);
+        assert_eq!(
+            BaseUrl::parse("http://[::1]:8086").unwrap().host_header(),
+            "[::1]"
+        );
+        assert!(BaseUrl::parse("http://::1:8086").is_err());
+        assert!(BaseUrl::parse("http://[::1:8086").is_err());
+        assert!(BaseUrl::parse("http://[::1]8086").is_err());
+        assert!(BaseUrl::parse("localhost:8086").is_err());
+        assert!(BaseUrl::parse("http://:8086").is_err());
+        assert!(BaseUrl::parse("http://localhost:port").is_err());
+    }
+
+    #[test]
+    fn test_format_point() {
+        assert_eq!(
+            format_point(
+                "heating",
+                &CommandResult::new("getTempA", Value::Number(21.5)),
+                1700000000
+            )
+            .unwrap(),
+            "heating,command=getTempA value=21.5 1700000000"
+        );
+        assert_eq!(
+            format_point(
+                "heating",
+                &CommandResult::new("getStatus", Value::String("a \"b\"".into())),
+                1
+            )
+            .unwrap(),
+            r#"heating,command=getStatus value_str="a \"b\"" 1"#
+        );
+        assert_eq!(
+            format_point(
+                "heating",
+                &CommandResult::new("getRaw", Value::Bytes(vec![1, 0xA3])),
+                1
+            )
+            .unwrap(),
+            r#"heating,command=getRaw value_str="01A3" 1"#
+        );
+        assert_eq!(
+            format_point(
+                "my heating",
+                &CommandResult::new("set a=1", Value::Number(1.0)),
+                1
+            )
+            .unwrap(),
+            r"my\ heating,command=set\ a\=1 value=1 1"
+        );
+
+        assert!(format_point(
+            "heating",
+            &CommandResult::new("getTempA", Value::Number(f64::NAN)),
+            1
+        )
+        .is_none());
+        assert!(format_point(
+            "heating",
+            &CommandResult::new("getTempA", Value::Number(f64::INFINITY)),
+            1
+        )
+        .is_none());
+
+        let failed = CommandResult::failed("getTempA", "ERR: command unknown");
+        assert!(format_point("heating", &failed, 1).is_none());
+    }
+
+    #[tokio::test]
+    async fn write_posts_batch_to_write_endpoint() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            // Read until the full body (two points) has arrived
+            while !String::from_utf8_lossy(&request).ends_with(" 1700000000") {
+                let n = stream.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+                .await
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let sink = InfluxSink::new(&InfluxConfig {
+            url: format!("http://127.0.0.1:{}", port),
+            bucket: "home heating".to_string(),
+            org: Some("home".to_string()),
+            token: Some("secret".to_string()),
+            measurement: "heating".to_string(),
+        })
+        .unwrap();
+        let results = vec![
+            CommandResult::new("getTempA", Value::Number(21.5)),
+            CommandResult::new("getTempB", Value::Number(45.0)),
+        ];
+        let time = UNIX_EPOCH + Duration::from_secs(1700000000);
+        sink.write(&results, time).await.unwrap();
+
+        let request = server.await.unwrap();
+        assert!(request.starts_with(
+            "POST /api/v2/write?bucket=home%20heating&precision=s&org=home HTTP/1.1\r\n"
+        ));
+        assert!(request.contains("Authorization: Token secret\r\n"));
+        assert!(request.ends_with(
+            "\r\n\r\nheating,command=getTempA value=21.5 1700000000\nheating,command=getTempB value=45 1700000000"
+        ));
+    }
+
+    #[tokio::test]
+    async fn write_reports_error_status() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = stream.read(&mut buf).await;
+            stream
+                .write_all(b"HTTP/1.1 401 Unauthorized\r\n\r\n")
+                .await
+                .unwrap();
+        });
+
+        let sink = InfluxSink::new(&InfluxConfig {
+            url: format!("http://127.0.0.1:{}", port),
+            bucket: "heating".to_string(),
+            org: None,
+            token: None,
+            measurement: "heating".to_string(),
+        })
+        .unwrap();
+        let err = sink
+            .write(
+                &[CommandResult::new("getTempA", Value::Number(21.5))],
+                SystemTime::now(),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, InfluxError::Status(ref line) if line.contains("401")));
+    }
+}
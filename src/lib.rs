@@ -0,0 +1,52 @@
+//! vcontrold-mqttd library
+//!
+//! The daemon in `main.rs` is built from these modules. Applications that
+//! only need to talk to vcontrold can embed the client instead:
+//!
+//! ```no_run
+//! use vcontrold_mqttd::VcontroldClient;
+//!
+//! # async fn example() {
+//! let client = VcontroldClient::new("127.0.0.1", 3002);
+//! match client.query("getTempA").await {
+//!     Ok(result) => println!("{:?}", result.value),
+//!     Err(e) => eprintln!("{}", e),
+//! }
+//! for (command, result) in client.query_many(&["getTempA", "getTempWWist"]).await {
+//!     println!("{}: {:?}", command, result.map(|r| r.value));
+//! }
+//! # }
+//! ```
+//!
+//! The stable embedding API is [`VcontroldClient`] (`query`, `query_many`,
+//! `stats` and the `with_*` builders), [`CommandResult`], [`Value`],
+//! [`ErrorKind`], [`ClientStats`] and [`VcontroldError`], plus the typed
+//! accessors in [`vcontrold::typed`]. Applications that
+//! run the bridge tasks themselves stop them with [`Shutdown`]. The other
+//! modules serve the daemon and may change between releases.
+
+#[doc(hidden)]
+pub mod cache;
+#[doc(hidden)]
+pub mod config;
+pub mod error;
+#[doc(hidden)]
+pub mod health;
+#[doc(hidden)]
+pub mod influx;
+#[doc(hidden)]
+pub mod mqtt;
+#[doc(hidden)]
+pub mod polling;
+#[doc(hidden)]
+pub mod process;
+pub mod shutdown;
+#[doc(hidden)]
+pub mod sink;
+#[doc(hidden)]
+pub mod state;
+pub mod vcontrold;
+
+pub use error::VcontroldError;
+pub use shutdown::Shutdown;
+pub use vcontrold::{ClientStats, CommandResult, ErrorKind, Value, VcontroldClient};
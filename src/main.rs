@@ -6,27 +6,32 @@
 //! - MQTT bridge for remote query and control
 //! - JSON response formatting
 
-mod config;
-mod error;
-mod health;
-mod mqtt;
-mod polling;
-mod process;
-mod vcontrold;
-
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::mpsc;
-use tracing::{error, info};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
 use tracing_subscriber::EnvFilter;
 
-use crate::config::Config;
-use crate::error::{Error, Result};
-use crate::health::{run_health_server, HealthState};
-use crate::mqtt::{run_event_loop, run_subscriber, MqttClient, Subscriber};
-use crate::polling::run_polling_loop;
-use crate::process::VcontroldProcess;
-use crate::vcontrold::VcontroldClient;
+use vcontrold_mqttd::cache::ResponseCache;
+use vcontrold_mqttd::config::{parse_bind, Config, ConfigError};
+use vcontrold_mqttd::error::{Error, ProcessError, Result};
+use vcontrold_mqttd::health::{self, run_health_server, HealthState};
+use vcontrold_mqttd::influx::InfluxSink;
+use vcontrold_mqttd::mqtt::{
+    device_info, run_admin, run_event_loop, run_subscriber, AdminControl, BirthMessage,
+    CommandToggles, EventLoopOptions, MessageRoutes, MqttClient, PollTrigger, Subscriber,
+};
+use vcontrold_mqttd::polling::{run_polling_loop, run_stall_watchdog, Heartbeat, PollingControls};
+use vcontrold_mqttd::process::{monitor_device, wait_for_device, VcontroldProcess};
+use vcontrold_mqttd::shutdown::Shutdown;
+use vcontrold_mqttd::sink::{OutputSink, StdoutSink};
+use vcontrold_mqttd::state::StateFile;
+use vcontrold_mqttd::vcontrold::{
+    strip_version_label, MockServer, VcontroldClient, DEFAULT_PORT, DEVICE_TYPE_COMMAND,
+    VERSION_COMMAND,
+};
 
 #[tokio::main]
 async fn main() {
@@ -36,28 +41,41 @@ async fn main() {
             .ok()
             .and_then(|v| v.parse().ok())
             .unwrap_or(8080);
-        let ok = health::check_health(port);
+        let addr = parse_bind("HEALTH_BIND", std::env::var("HEALTH_BIND").ok(), port)
+            .unwrap_or_else(|_| ([0, 0, 0, 0], port).into());
+        let ok = health::check_health(addr);
         std::process::exit(if ok { 0 } else { 1 });
     }
 
+    // Load configuration first, since it decides where logs go; errors are
+    // reported once logging is initialized
+    let config = Config::from_env();
+
     // Initialize logging
     let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| {
-        if std::env::var("DEBUG")
-            .map(|v| v == "true" || v == "1")
-            .unwrap_or(false)
-        {
+        if config.as_ref().is_ok_and(|config| config.debug) {
             EnvFilter::new("debug")
         } else {
             EnvFilter::new("info")
         }
     });
 
-    tracing_subscriber::fmt()
+    // STDOUT_OUTPUT claims stdout for JSON lines, so logs move to stderr
+    let logs_to_stderr = config.as_ref().is_ok_and(|config| config.stdout_output);
+    let builder = tracing_subscriber::fmt()
         .with_env_filter(filter)
-        .with_target(false)
-        .init();
+        .with_target(false);
+    if logs_to_stderr {
+        builder.with_writer(std::io::stderr).init();
+    } else {
+        builder.init();
+    }
 
-    if let Err(e) = run().await {
+    let result = match config {
+        Ok(config) => run(config).await,
+        Err(e) => Err(e.into()),
+    };
+    if let Err(e) = result {
         error!("Fatal error: {}", e);
         std::process::exit(1);
     }
@@ -82,22 +100,202 @@ async fn shutdown_signal() {
     }
 }
 
-async fn run() -> Result<()> {
-    // Load configuration
-    let config = Config::from_env()?;
+/// Warn about polled commands vcontrold does not know
+///
+/// With `STRICT_COMMANDS=true`, unknown commands abort startup. If the command
+/// list cannot be queried, validation is skipped.
+async fn validate_commands(config: &Config, client: &VcontroldClient) -> Result<()> {
+    if config.polled_commands().is_empty() {
+        return Ok(());
+    }
+    let known = match client.list_commands().await {
+        Ok(known) => known,
+        Err(e) => {
+            warn!(
+                "Could not query vcontrold commands, skipping validation: {}",
+                e
+            );
+            return Ok(());
+        }
+    };
+
+    let unknown: Vec<&str> = config
+        .commands
+        .iter()
+        .map(|command| command.split_whitespace().next().unwrap_or_default())
+        .filter(|name| !known.iter().any(|k| k == name))
+        .collect();
+    if unknown.is_empty() {
+        return Ok(());
+    }
+
+    for name in &unknown {
+        warn!("Command {} is not known to vcontrold", name);
+    }
+    if config.strict_commands {
+        return Err(ConfigError::InvalidValue("COMMANDS", unknown.join(",")).into());
+    }
+    Ok(())
+}
+
+/// How long to wait for vcontrold to answer a device info query
+const DEVICE_INFO_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Query a device property, logging (not failing) if vcontrold cannot answer
+async fn query_device_property(client: &VcontroldClient, command: &str) -> Option<String> {
+    match client
+        .execute_with_timeout(command, DEVICE_INFO_TIMEOUT)
+        .await
+    {
+        Ok(result) if result.error.is_none() => Some(strip_version_label(&result.raw).to_string()),
+        Ok(result) => {
+            warn!(
+                "Could not query {}: {}",
+                command,
+                result.error.unwrap_or_default()
+            );
+            None
+        }
+        Err(e) => {
+            warn!("Could not query {}: {}", command, e);
+            None
+        }
+    }
+}
+
+/// Publish the device type and vcontrold version (retained) to `{base_topic}/device`
+async fn publish_device_info(client: &VcontroldClient, mqtt_client: &MqttClient) {
+    let device_type = query_device_property(client, DEVICE_TYPE_COMMAND).await;
+    let version = query_device_property(client, VERSION_COMMAND).await;
+    if device_type.is_none() && version.is_none() {
+        return;
+    }
+    let payload = device_info(device_type.as_deref(), version.as_deref());
+    info!("Device: {}", payload);
+    if let Err(e) = mqtt_client
+        .publish_retained(&mqtt_client.topic("device"), &payload, None)
+        .await
+    {
+        warn!("Failed to publish device info: {}", e);
+    }
+}
+
+/// Start vcontrold as configured
+async fn spawn_vcontrold(config: &Config) -> std::result::Result<VcontroldProcess, ProcessError> {
+    VcontroldProcess::spawn(
+        &config.vcontrold_bin,
+        &config.vcontrold_config,
+        &config.vcontrold_args,
+        config.debug,
+    )
+    .await
+}
 
+/// Kill vcontrold and start it again (after the polling watchdog tripped)
+///
+/// Waits for the serial device like at startup, so an unplugged adapter gets
+/// `USB_WAIT_TIMEOUT` to come back before giving up.
+async fn restart_vcontrold(
+    config: &Config,
+    process: &mut VcontroldProcess,
+    client: &VcontroldClient,
+) -> std::result::Result<(), ProcessError> {
+    process.stop(config.vcontrold_stop_grace).await;
+    // Drop the connection to the old instance; the client reconnects on demand
+    client.disconnect().await;
+    if !config.usb_wait_timeout.is_zero() {
+        wait_for_device(&config.usb_device, config.usb_wait_timeout).await?;
+    }
+    *process = spawn_vcontrold(config).await?;
+    process
+        .wait_ready(
+            client,
+            config.readiness_timeout,
+            config.readiness_probe_interval,
+        )
+        .await
+}
+
+async fn run(config: Config) -> Result<()> {
     if config.debug {
         info!("Debug mode enabled");
     }
 
-    // Start vcontrold process
-    let mut vcontrold_process = VcontroldProcess::spawn(None, config.debug).await?;
+    if config.check_idle()? {
+        warn!(
+            "Neither polling nor the request/response bridge is enabled: set COMMANDS (with POLLING=true) or MQTT_SUBSCRIBE=true, otherwise this daemon does nothing (STRICT=true turns this into an error)"
+        );
+    }
+
+    for command in config.oversized_commands()? {
+        warn!(
+            "Command {} is longer than MAX_LENGTH ({}): it is sent in a batch of its own and vcontrold may reject it (MAX_LENGTH_STRICT=true turns this into an error)",
+            command, config.max_length
+        );
+    }
 
-    // Wait for vcontrold to be ready
-    vcontrold_process.wait_ready().await?;
+    if config.dry_run {
+        warn!("Dry-run mode active: vcontrold is not started, all commands return a dummy value");
+    }
+
+    // Give dependencies started alongside us (broker, vcontrold) time to come up
+    if !config.startup_delay.is_zero() {
+        info!(
+            "Waiting {}s before starting (STARTUP_DELAY)",
+            config.startup_delay.as_secs()
+        );
+        tokio::time::sleep(config.startup_delay).await;
+    }
+
+    // Serve a mock vcontrold in place of the real one
+    if let Some(responses) = &config.vcontrold_mock {
+        warn!("Mock mode active: vcontrold is not started, commands are answered by a mock");
+        let addr = format!("127.0.0.1:{}", DEFAULT_PORT);
+        let mock = MockServer::bind(&addr, responses.clone())
+            .await?
+            .with_prompt(&config.vcontrold_prompt);
+        tokio::spawn(mock.run());
+    }
+
+    // Start vcontrold process (not in dry-run or mock mode)
+    let mut vcontrold_process = if config.dry_run || config.vcontrold_mock.is_some() {
+        None
+    } else {
+        if !config.usb_wait_timeout.is_zero() {
+            wait_for_device(&config.usb_device, config.usb_wait_timeout).await?;
+        }
+        Some(spawn_vcontrold(&config).await?)
+    };
 
     // Create vcontrold client
-    let vcontrold_client = Arc::new(VcontroldClient::localhost());
+    let vcontrold_client = match &config.vcontrold_socket {
+        Some(path) => VcontroldClient::unix(path),
+        None => VcontroldClient::localhost(),
+    };
+    let vcontrold_client = Arc::new(
+        vcontrold_client
+            .with_batch_retry(config.batch_retry)
+            .with_numeric_commands(config.numeric_commands.clone())
+            .with_hex_commands(config.hex_commands.clone())
+            .with_units(config.units.clone())
+            .with_command_delay(config.command_delay)
+            .with_prompt(&config.vcontrold_prompt)
+            .with_max_response(config.vcontrold_max_response)
+            .with_late_response_wait(config.vcontrold_late_response_wait)
+            .with_dry_run(config.dry_run),
+    );
+
+    // Wait for vcontrold to be ready
+    if let Some(process) = &vcontrold_process {
+        process
+            .wait_ready(
+                &vcontrold_client,
+                config.readiness_timeout,
+                config.readiness_probe_interval,
+            )
+            .await?;
+        validate_commands(&config, &vcontrold_client).await?;
+    }
 
     // Create MQTT client
     let publisher_client_id = config.publisher_client_id();
@@ -110,14 +308,16 @@ async fn run() -> Result<()> {
 
     // Health state: tracks all components for the health endpoint
     let vcontrold_running = Arc::new(AtomicBool::new(true));
+    let usb_device_present = Arc::new(AtomicBool::new(true));
     let health_state = Arc::new(HealthState {
         vcontrold_running: Arc::clone(&vcontrold_running),
         vcontrold_connected: vcontrold_client.connected_flag(),
         mqtt_connected: Arc::clone(&mqtt_connected),
+        usb_device: Arc::clone(&usb_device_present),
     });
 
     // Spawn health check HTTP server
-    let health_handle = tokio::spawn(run_health_server(config.healthcheck_port, health_state));
+    let mut health_handle = tokio::spawn(run_health_server(config.health_bind, health_state));
 
     // Channel for subscriber messages (if enabled)
     let (message_tx, message_rx) = if config.mqtt_subscribe {
@@ -127,109 +327,359 @@ async fn run() -> Result<()> {
         (None, None)
     };
 
+    // Recent reads shared by polling and the bridge (only useful with the bridge)
+    let cache = (config.mqtt_subscribe && !config.cache_ttl.is_zero())
+        .then(|| ResponseCache::new(config.cache_ttl));
+
     // Build subscriber and subscription topics (if enabled)
-    let (subscriber, subscribe_topics) = if config.mqtt_subscribe {
-        let sub = Subscriber::new(mqtt_client.base_topic());
-        let topics = vec![sub.request_topic()];
+    let (subscriber, mut subscribe_topics) = if config.mqtt_subscribe {
+        let mut sub = Subscriber::new(mqtt_client.base_topic())
+            .with_set_ranges(config.set_ranges.clone())
+            .with_suffixes(&config.request_suffix, &config.response_suffix)
+            .with_topic_pairs(config.request_topics.clone())
+            .with_aliases(config.publish.aliases.clone())
+            .with_separator(&config.request_separator)
+            .with_timeout(config.request_timeout)
+            .with_retries(config.command_retries)
+            .with_get_topics(config.mqtt_get_topics)
+            .with_omit_nulls(config.omit_nulls)
+            .with_raw(config.publish_raw)
+            .with_units(config.publish_units);
+        if let Some(deadline) = config.request_deadline {
+            sub = sub.with_deadline(deadline);
+        }
+        if let Some(cache) = &cache {
+            sub = sub.with_cache(cache.clone());
+            info!(
+                "Response cache enabled (CACHE_TTL={}s)",
+                config.cache_ttl.as_secs()
+            );
+        }
+        let topics = sub.request_topics();
         (Some(sub), topics)
     } else {
         (None, vec![])
     };
 
+    // On-demand polling via {base_topic}/poll (only when polling is enabled)
+    let (poll_trigger, poll_rx) = if !config.polled_commands().is_empty() {
+        let (trigger, rx) = PollTrigger::new(mqtt_client.base_topic());
+        subscribe_topics.push(trigger.topic.clone());
+        (Some(trigger), Some(rx))
+    } else {
+        (None, None)
+    };
+
+    // Per-command enable switches via {base_topic}/command/<cmd>/enabled
+    let command_toggles = if !config.polled_commands().is_empty() {
+        let toggles = CommandToggles::new(mqtt_client.base_topic());
+        subscribe_topics.push(toggles.filter());
+        Some(toggles)
+    } else {
+        None
+    };
+
+    // Admin control topics via {base_topic}/admin/... (only with ADMIN_ENABLED)
+    let (admin, admin_rx) = if config.admin_enabled {
+        let (control, rx) = AdminControl::new(mqtt_client.base_topic());
+        subscribe_topics.push(control.topic.clone());
+        (Some(control), Some(rx))
+    } else {
+        (None, None)
+    };
+
+    // Birth message announcing this instance, republished on every connect
+    let birth = BirthMessage::new(
+        mqtt_client.base_topic(),
+        config.polled_commands(),
+        config.interval,
+        config.mqtt_subscribe,
+    )
+    .with_user_properties(config.mqtt.user_properties.clone());
+    let shutdown_birth = birth.clone();
+
+    // Stops the event loop, polling loop and subscriber on shutdown
+    let cancel = CancellationToken::new();
+
     // Spawn MQTT event loop
-    let eventloop_handle = tokio::spawn(run_event_loop(
+    let mut eventloop_handle = tokio::spawn(run_event_loop(
         eventloop,
         mqtt_client.clone_client(),
-        subscribe_topics,
-        message_tx,
         Arc::clone(&mqtt_connected),
+        EventLoopOptions {
+            subscribe_topics,
+            birth,
+            failover: mqtt_client.broker_failover(),
+            auth_failure_limit: config.mqtt.auth_failure_limit,
+            connect_max_retries: config.mqtt.connect_max_retries,
+            subscribe_batch: config.mqtt.subscribe_batch,
+        },
+        MessageRoutes {
+            message_tx,
+            poll_trigger,
+            command_toggles: command_toggles.clone(),
+            admin,
+        },
+        cancel.clone(),
     ));
 
+    // Inventory of what this instance talks to, queried in the background so
+    // an unresponsive vcontrold does not hold up startup
+    if !config.dry_run {
+        let vcontrold_clone = Arc::clone(&vcontrold_client);
+        let mqtt_clone = Arc::clone(&mqtt_client);
+        tokio::spawn(async move { publish_device_info(&vcontrold_clone, &mqtt_clone).await });
+    }
+
+    // Additional output sinks for polled values, next to MQTT
+    let mut sinks: Vec<Box<dyn OutputSink>> = Vec::new();
+    if let Some(influx) = &config.influx {
+        sinks.push(Box::new(InfluxSink::new(influx)?));
+        info!("InfluxDB output enabled");
+    }
+    if config.stdout_output {
+        sinks.push(Box::new(
+            StdoutSink::default()
+                .with_omit_nulls(config.omit_nulls)
+                .with_raw(config.publish_raw)
+                .with_units(config.publish_units),
+        ));
+        info!("JSON-lines output to stdout enabled");
+    }
+    if let Some(cache) = cache {
+        sinks.push(Box::new(cache));
+    }
+    if let Some(path) = &config.state_file {
+        let state = StateFile::new(path);
+        // Stale commands are cleared at startup; forget them on the next write
+        let state = if config.clear_stale_on_start {
+            state.with_commands(&config.commands)
+        } else {
+            state
+        };
+        sinks.push(Box::new(state));
+        info!("Last values are saved to {}", path.display());
+    }
+
+    // Polling watchdog: restarts vcontrold after WATCHDOG_FAILURES cycles
+    // without a single successful read
+    let (watchdog_tx, mut watchdog_rx) =
+        if config.watchdog_failures > 0 && vcontrold_process.is_some() {
+            let (tx, rx) = mpsc::channel(1);
+            (Some(tx), Some(rx))
+        } else {
+            (None, None)
+        };
+
+    // Stall watchdog: exits when the polling loop stops waking up for
+    // STALL_MULTIPLIER intervals (a hang, not a failing vcontrold)
+    let heartbeat = (config.stall_multiplier > 0 && !config.polled_commands().is_empty())
+        .then(|| Arc::new(Heartbeat::new()));
+    let stall_timeout = config.interval * config.stall_multiplier + config.interval_jitter;
+    let mut stall_handle = heartbeat.clone().map(|heartbeat| {
+        info!(
+            "Exiting if the polling loop makes no progress for {} seconds",
+            stall_timeout.as_secs()
+        );
+        tokio::spawn(run_stall_watchdog(
+            heartbeat,
+            stall_timeout,
+            config.interval,
+            cancel.clone(),
+        ))
+    });
+
     // Spawn polling loop (if commands are configured)
-    let polling_handle = if !config.commands.is_empty() {
+    let mut polling_handle = if !config.polled_commands().is_empty() {
         let config_clone = config.clone();
         let vcontrold_clone = Arc::clone(&vcontrold_client);
         let mqtt_clone = Arc::clone(&mqtt_client);
         let connected = Arc::clone(&mqtt_connected);
+        let cancel = cancel.clone();
         Some(tokio::spawn(async move {
-            run_polling_loop(&config_clone, vcontrold_clone, mqtt_clone, connected).await;
+            run_polling_loop(
+                &config_clone,
+                vcontrold_clone,
+                mqtt_clone,
+                connected,
+                sinks,
+                PollingControls {
+                    poll_trigger: poll_rx,
+                    command_toggles,
+                    watchdog_tx,
+                    heartbeat,
+                },
+                cancel,
+            )
+            .await;
         }))
     } else {
-        info!("No commands configured, polling disabled");
+        if config.polling {
+            info!("No commands configured, polling disabled");
+        } else {
+            info!("Polling disabled (POLLING=false), running as request/response bridge only");
+        }
         None
     };
 
     // Spawn subscriber (if enabled)
-    let subscriber_handle = if let Some(sub) = subscriber {
+    let mut subscriber_handle = if let Some(sub) = subscriber {
         let mqtt_clone = Arc::clone(&mqtt_client);
         let vcontrold_clone = Arc::clone(&vcontrold_client);
         let rx = message_rx.unwrap();
+        let cancel = cancel.clone();
         info!("Request/response bridge enabled");
         Some(tokio::spawn(async move {
-            run_subscriber(sub, mqtt_clone, vcontrold_clone, rx).await;
+            run_subscriber(sub, mqtt_clone, vcontrold_clone, rx, cancel).await;
         }))
     } else {
         None
     };
 
+    // Spawn admin task (if enabled)
+    let admin_handle = admin_rx.map(|rx| {
+        let mqtt_clone = Arc::clone(&mqtt_client);
+        let vcontrold_clone = Arc::clone(&vcontrold_client);
+        let cancel = cancel.clone();
+        info!("Admin control topics enabled");
+        tokio::spawn(run_admin(mqtt_clone, vcontrold_clone, rx, cancel))
+    });
+
+    // Watch for the Optolink adapter being unplugged (only when we run vcontrold)
+    let device_monitor_handle =
+        if vcontrold_process.is_some() && !config.usb_check_interval.is_zero() {
+            Some(tokio::spawn(monitor_device(
+                config.usb_device.clone(),
+                config.usb_check_interval,
+                usb_device_present,
+                Arc::clone(&vcontrold_client),
+                cancel.clone(),
+            )))
+        } else {
+            None
+        };
+
     info!("vcontrold-mqttd started");
 
     // Wait for any task to complete or shutdown signal
-    let exit_error = tokio::select! {
-        result = vcontrold_process.wait() => {
-            vcontrold_running.store(false, Ordering::Relaxed);
-            match result {
-                Ok(code) => {
-                    error!("vcontrold exited with code: {:?}", code);
-                    Some(Error::Process(crate::error::ProcessError::UnexpectedExit(code)))
+    let exit_error = loop {
+        break tokio::select! {
+            result = async {
+                match vcontrold_process.as_mut() {
+                    Some(process) => process.wait().await,
+                    None => std::future::pending().await,
                 }
-                Err(e) => {
-                    error!("Error waiting for vcontrold: {}", e);
-                    Some(Error::Process(e))
+            } => {
+                vcontrold_running.store(false, Ordering::Relaxed);
+                match result {
+                    Ok(code) => {
+                        error!("vcontrold exited with code: {:?}", code);
+                        Some(Error::Process(ProcessError::UnexpectedExit(code)))
+                    }
+                    Err(e) => {
+                        error!("Error waiting for vcontrold: {}", e);
+                        Some(Error::Process(e))
+                    }
                 }
             }
-        }
-        _ = eventloop_handle => {
-            error!("MQTT event loop exited unexpectedly");
-            None
-        }
-        _ = async {
-            if let Some(handle) = polling_handle {
-                handle.await
-            } else {
-                std::future::pending::<()>().await;
-                Ok(())
+            result = &mut eventloop_handle => {
+                match result {
+                    Ok(Err(e)) => Some(Error::Mqtt(e)),
+                    _ => {
+                        error!("MQTT event loop exited unexpectedly");
+                        None
+                    }
+                }
             }
-        } => {
-            error!("Polling loop exited unexpectedly");
-            None
-        }
-        _ = async {
-            if let Some(handle) = subscriber_handle {
-                handle.await
-            } else {
-                std::future::pending::<()>().await;
-                Ok(())
+            _ = async {
+                if let Some(handle) = polling_handle.as_mut() {
+                    handle.await
+                } else {
+                    std::future::pending::<()>().await;
+                    Ok(())
+                }
+            } => {
+                error!("Polling loop exited unexpectedly");
+                None
             }
-        } => {
-            error!("Subscriber exited unexpectedly");
-            None
-        }
-        _ = health_handle => {
-            error!("Health server exited unexpectedly");
-            None
-        }
-        _ = shutdown_signal() => {
-            None
-        }
+            _ = async {
+                if let Some(handle) = subscriber_handle.as_mut() {
+                    handle.await
+                } else {
+                    std::future::pending::<()>().await;
+                    Ok(())
+                }
+            } => {
+                error!("Subscriber exited unexpectedly");
+                None
+            }
+            _ = async {
+                if let Some(handle) = stall_handle.as_mut() {
+                    handle.await
+                } else {
+                    std::future::pending::<()>().await;
+                    Ok(())
+                }
+            } => {
+                error!("Polling loop stalled, exiting");
+                Some(Error::PollingStalled(stall_timeout.as_secs()))
+            }
+            _ = &mut health_handle => {
+                error!("Health server exited unexpectedly");
+                None
+            }
+            Some(()) = async {
+                match watchdog_rx.as_mut() {
+                    Some(rx) => rx.recv().await,
+                    None => std::future::pending().await,
+                }
+            } => {
+                let Some(process) = vcontrold_process.as_mut() else {
+                    continue;
+                };
+                warn!("Polling watchdog tripped, restarting vcontrold");
+                vcontrold_running.store(false, Ordering::Relaxed);
+                if let Err(e) = restart_vcontrold(&config, process, &vcontrold_client).await {
+                    error!("Failed to restart vcontrold: {}", e);
+                    Some(Error::Process(e))
+                } else {
+                    vcontrold_running.store(true, Ordering::Relaxed);
+                    info!("vcontrold restarted");
+                    continue;
+                }
+            }
+            _ = shutdown_signal() => {
+                None
+            }
+        };
     };
 
-    // Cleanup: kill vcontrold process
-    info!("Shutting down vcontrold...");
-    vcontrold_process.kill().await;
+    // Stop producing new publishes, announce the instance offline and drain
+    // the queued publishes before disconnecting
+    let mut shutdown = Shutdown::new(
+        cancel,
+        Arc::clone(&mqtt_client),
+        Arc::clone(&vcontrold_client),
+        eventloop_handle,
+    )
+    .with_birth(shutdown_birth)
+    .with_grace(config.shutdown_grace);
+    for handle in polling_handle
+        .into_iter()
+        .chain(subscriber_handle)
+        .chain(admin_handle)
+        .chain(device_monitor_handle)
+        .chain(stall_handle)
+    {
+        shutdown = shutdown.with_task(handle);
+    }
+    shutdown.shutdown().await;
 
-    // Disconnect TCP client
-    vcontrold_client.disconnect().await;
+    // Cleanup: kill vcontrold process
+    if let Some(process) = vcontrold_process.as_mut() {
+        info!("Shutting down vcontrold...");
+        process.stop(config.vcontrold_stop_grace).await;
+    }
 
     if let Some(e) = exit_error {
         return Err(e);
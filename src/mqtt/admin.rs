@@ -0,0 +1,138 @@
+//! Admin control topics (`ADMIN_ENABLED`)
+//!
+//! Maintenance actions that are not vcontrold reads or writes and therefore
+//! bypass the request/response bridge. `{base_topic}/admin/reload` makes
+//! vcontrold reload its XML configuration; the outcome is published to
+//! `{base_topic}/admin/reload/result`.
+
+use std::sync::Arc;
+
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+
+use crate::mqtt::MqttClient;
+use crate::vcontrold::{VcontroldClient, RELOAD_COMMAND};
+
+/// Reload topic suffix (relative to the base topic)
+const RELOAD_SUFFIX: &str = "admin/reload";
+
+/// Forwards messages on `{base_topic}/admin/reload` to the admin task
+#[derive(Debug, Clone)]
+pub struct AdminControl {
+    pub topic: String,
+    tx: mpsc::Sender<()>,
+}
+
+impl AdminControl {
+    /// Create the control for `{base_topic}/admin/reload` and the receiver
+    /// for [`run_admin`]
+    ///
+    /// Like the poll trigger, a single pending reload is kept; further
+    /// messages arriving before it is executed are coalesced.
+    pub fn new(base_topic: &str) -> (Self, mpsc::Receiver<()>) {
+        let (tx, rx) = mpsc::channel(1);
+        let control = Self {
+            topic: format!("{}/{}", base_topic, RELOAD_SUFFIX),
+            tx,
+        };
+        (control, rx)
+    }
+
+    /// Signal the admin task without blocking; returns whether a reload was queued
+    pub(super) fn fire(&self) -> bool {
+        self.tx.try_send(()).is_ok()
+    }
+}
+
+/// Result payload of a reload, e.g. `{"ok":true,"response":""}` or
+/// `{"ok":false,"error":"ERR: ..."}`
+fn reload_result(outcome: Result<String, String>) -> String {
+    match outcome {
+        Ok(response) => serde_json::json!({"ok": true, "response": response}),
+        Err(error) => serde_json::json!({"ok": false, "error": error}),
+    }
+    .to_string()
+}
+
+/// Run the admin task
+///
+/// Executes `reload` on vcontrold for every message on the reload topic and
+/// publishes the outcome (not retained) to `{base_topic}/admin/reload/result`.
+/// Returns once `cancel` is triggered; a reload in progress is answered first.
+pub async fn run_admin(
+    mqtt_client: Arc<MqttClient>,
+    vcontrold: Arc<VcontroldClient>,
+    mut reload_rx: mpsc::Receiver<()>,
+    cancel: CancellationToken,
+) {
+    let result_topic = mqtt_client.topic(&format!("{}/result", RELOAD_SUFFIX));
+    loop {
+        tokio::select! {
+            request = reload_rx.recv() => {
+                if request.is_none() {
+                    break;
+                }
+            }
+            _ = cancel.cancelled() => {
+                debug!("Admin task stopped");
+                return;
+            }
+        }
+
+        info!("Reloading vcontrold configuration via MQTT");
+        let outcome = match vcontrold.execute(RELOAD_COMMAND).await {
+            Ok(result) => match result.error_with_detail() {
+                Some(e) => Err(e),
+                None => Ok(result.raw.trim().to_string()),
+            },
+            Err(e) => Err(e.to_string()),
+        };
+        match &outcome {
+            Ok(_) => info!("vcontrold configuration reloaded"),
+            Err(e) => warn!("vcontrold reload failed: {}", e),
+        }
+
+        if let Err(e) = mqtt_client
+            .publish(
+                &result_topic,
+                &reload_result(outcome),
+                mqtt_client.response_qos(),
+                None,
+            )
+            .await
+        {
+            error!("Failed to publish reload result: {}", e);
+        }
+    }
+
+    warn!("Admin channel closed");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_admin_control_topic_and_coalescing() {
+        let (control, mut rx) = AdminControl::new("heating");
+        assert_eq!(control.topic, "heating/admin/reload");
+        assert!(control.fire());
+        assert!(!control.fire());
+        assert!(rx.try_recv().is_ok());
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_reload_result_payload() {
+        let parse = |payload: String| serde_json::from_str::<serde_json::Value>(&payload).unwrap();
+        assert_eq!(
+            parse(reload_result(Ok(String::new()))),
+            serde_json::json!({"ok": true, "response": ""})
+        );
+        assert_eq!(
+            parse(reload_result(Err("ERR: reload failed".to_string()))),
+            serde_json::json!({"ok": false, "error": "ERR: reload failed"})
+        );
+    }
+}
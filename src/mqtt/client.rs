@@ -3,21 +3,80 @@
 //! Provides a simplified interface for MQTT v5 operations with TLS support.
 
 use rumqttc::mqttbytes::QoS;
-use rumqttc::{AsyncClient, Event, EventLoop, MqttOptions, Packet, TlsConfiguration, Transport};
-use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rumqttc::{
+    AsyncClient, Broker, ClientError, ConnectReturnCode, ConnectionError, Event, EventLoop, Filter,
+    MqttOptions, Outgoing, Packet, PublishProperties, TlsConfiguration, Transport,
+};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName};
 use rustls::ClientConfig;
+use std::collections::HashSet;
 use std::fs::File;
 use std::io::BufReader;
 use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, warn};
 
-use crate::config::{MqttConfig, TlsConfig};
+use crate::config::{local_hostname, MqttConfig, MqttTransport, TlsConfig};
 use crate::error::MqttError;
 
+use super::admin::AdminControl;
+use super::encrypted_key::{decrypt_pkcs8, find_encrypted_key};
+
+/// Birth message suffix (relative to the base topic)
+const INFO_SUFFIX: &str = "info";
+
+/// Retained message announcing this instance, republished on every ConnAck
+#[derive(Debug, Clone)]
+pub struct BirthMessage {
+    pub topic: String,
+    pub payload: String,
+    /// `MQTT_USER_PROPS` attached to the message
+    pub user_properties: Vec<(String, String)>,
+}
+
+impl BirthMessage {
+    /// Build the birth message published to `{base_topic}/info`
+    ///
+    /// Payload: `{"version":..,"hostname":..,"commands":[..],"interval":..,"subscribe":..}`
+    pub fn new(base_topic: &str, commands: &[String], interval: Duration, subscribe: bool) -> Self {
+        let payload = serde_json::json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "hostname": local_hostname(),
+            "commands": commands,
+            "interval": interval.as_secs(),
+            "subscribe": subscribe,
+            "online": true,
+        });
+        Self {
+            topic: format!("{}/{}", base_topic, INFO_SUFFIX),
+            payload: payload.to_string(),
+            user_properties: Vec::new(),
+        }
+    }
+
+    /// Attach static user properties (`MQTT_USER_PROPS`)
+    pub fn with_user_properties(mut self, user_properties: Vec<(String, String)>) -> Self {
+        self.user_properties = user_properties;
+        self
+    }
+
+    /// The same message with `"online":false`, published on shutdown
+    pub fn offline(&self) -> Self {
+        let mut payload: serde_json::Value =
+            serde_json::from_str(&self.payload).unwrap_or_else(|_| serde_json::json!({}));
+        payload["online"] = serde_json::Value::Bool(false);
+        Self {
+            topic: self.topic.clone(),
+            payload: payload.to_string(),
+            user_properties: self.user_properties.clone(),
+        }
+    }
+}
+
 /// Message received from MQTT subscription
 #[derive(Debug, Clone)]
 pub struct IncomingMessage {
@@ -25,36 +84,218 @@ pub struct IncomingMessage {
     pub payload: String,
 }
 
+/// User property naming the command a value was published for
+const COMMAND_PROPERTY: &str = "command";
+
+/// Poll trigger topic suffix (relative to the base topic)
+const POLL_SUFFIX: &str = "poll";
+
+/// Forwards messages on `{base_topic}/poll` to the polling loop
+#[derive(Debug, Clone)]
+pub struct PollTrigger {
+    pub topic: String,
+    tx: mpsc::Sender<()>,
+}
+
+impl PollTrigger {
+    /// Create a trigger for `{base_topic}/poll` and the receiver for the polling loop
+    ///
+    /// The channel holds a single pending trigger; further messages arriving
+    /// before the polling loop picks it up are coalesced.
+    pub fn new(base_topic: &str) -> (Self, mpsc::Receiver<()>) {
+        let (tx, rx) = mpsc::channel(1);
+        let trigger = Self {
+            topic: format!("{}/{}", base_topic, POLL_SUFFIX),
+            tx,
+        };
+        (trigger, rx)
+    }
+
+    /// Signal the polling loop without blocking; returns whether a trigger was queued
+    fn fire(&self) -> bool {
+        self.tx.try_send(()).is_ok()
+    }
+}
+
+/// Suffix of the per-command enable topic `{base_topic}/command/{cmd}/enabled`
+const ENABLED_SUFFIX: &str = "enabled";
+
+/// Commands disabled via `{base_topic}/command/{cmd}/enabled`
+///
+/// Updated by the event loop, consulted by the polling loop before each
+/// command. Retained `false` messages disable a command again after a restart.
+#[derive(Debug, Clone)]
+pub struct CommandToggles {
+    /// `{base_topic}/command/`
+    prefix: String,
+    disabled: Arc<Mutex<HashSet<String>>>,
+}
+
+impl CommandToggles {
+    /// Create the toggles for commands below `{base_topic}/command/`
+    pub fn new(base_topic: &str) -> Self {
+        Self {
+            prefix: format!("{}/command/", base_topic),
+            disabled: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    /// Subscription filter matching every command's enable topic
+    pub fn filter(&self) -> String {
+        format!("{}+/{}", self.prefix, ENABLED_SUFFIX)
+    }
+
+    /// Whether the polling loop should execute `command`
+    pub fn is_enabled(&self, command: &str) -> bool {
+        !self.disabled.lock().unwrap().contains(command)
+    }
+
+    /// Apply a message if `topic` is an enable topic; returns whether it was one
+    ///
+    /// `false`/`0` disables the command, `true`/`1` or an empty payload
+    /// (cleared retained message) enables it again.
+    fn handle(&self, topic: &str, payload: &str) -> bool {
+        let Some(command) = topic
+            .strip_prefix(self.prefix.as_str())
+            .and_then(|rest| rest.strip_suffix(ENABLED_SUFFIX))
+            .and_then(|rest| rest.strip_suffix('/'))
+            .filter(|command| !command.is_empty() && !command.contains('/'))
+        else {
+            return false;
+        };
+
+        let enabled = match payload.trim().to_lowercase().as_str() {
+            "" | "true" | "1" => true,
+            "false" | "0" => false,
+            other => {
+                warn!("Ignoring invalid payload on {}: {}", topic, other);
+                return true;
+            }
+        };
+        let mut disabled = self.disabled.lock().unwrap();
+        let changed = if enabled {
+            disabled.remove(command)
+        } else {
+            disabled.insert(command.to_string())
+        };
+        if changed {
+            info!(
+                "Polling of {} {} via MQTT",
+                command,
+                if enabled { "enabled" } else { "disabled" }
+            );
+        }
+        true
+    }
+}
+
+/// Consecutive failed connection attempts before switching to the next broker
+const FAILOVER_AFTER: u32 = 3;
+
+/// Rotates the event loop through the brokers of `MQTT_HOST=primary,backup`
+///
+/// rumqttc connects to the broker in `EventLoop::options`, so failing over
+/// swaps those options; queued requests and the client handle stay valid.
+pub struct BrokerFailover {
+    /// Host and connection options per broker
+    brokers: Vec<(String, MqttOptions)>,
+    current: usize,
+    failures: u32,
+}
+
+impl BrokerFailover {
+    fn new(brokers: Vec<(String, MqttOptions)>) -> Self {
+        Self {
+            brokers,
+            current: 0,
+            failures: 0,
+        }
+    }
+
+    /// Host of the broker the event loop currently connects to
+    pub fn current_host(&self) -> &str {
+        &self.brokers[self.current].0
+    }
+
+    /// Start counting failures afresh after a successful connect
+    fn connected(&mut self) {
+        self.failures = 0;
+    }
+
+    /// Record a connection error; returns true if the event loop was switched
+    /// to the next broker
+    fn connection_failed(&mut self, eventloop: &mut EventLoop) -> bool {
+        self.failures += 1;
+        if self.failures < FAILOVER_AFTER {
+            return false;
+        }
+        self.failures = 0;
+        self.current = (self.current + 1) % self.brokers.len();
+        warn!("Failing over to MQTT broker {}", self.current_host());
+        eventloop.options = self.brokers[self.current].1.clone();
+        true
+    }
+}
+
+/// First delay before reconnecting after an event loop error
+const RECONNECT_BACKOFF_MIN: Duration = Duration::from_secs(1);
+
+/// Upper bound for the reconnect delay
+const RECONNECT_BACKOFF_MAX: Duration = Duration::from_secs(60);
+
+/// Session expiry interval requested for persistent sessions (1 hour)
+const PERSISTENT_SESSION_EXPIRY_SECS: u32 = 3600;
+
 /// MQTT client wrapper
 pub struct MqttClient {
     client: AsyncClient,
     base_topic: String,
+    /// Request channel capacity (`MQTT_CAP`)
+    capacity: usize,
+    /// QoS of request/response bridge responses (`MQTT_RESPONSE_QOS`)
+    response_qos: QoS,
+    /// Connection options per configured broker host, primary first
+    brokers: Vec<(String, MqttOptions)>,
+    /// Static user properties attached to every publish (`MQTT_USER_PROPS`)
+    user_properties: Vec<(String, String)>,
 }
 
 impl MqttClient {
     /// Create a new MQTT client from configuration
+    ///
+    /// The event loop connects to the first of `config.hosts`; the others are
+    /// kept for `broker_failover`.
     pub fn new(config: &MqttConfig, client_id: &str) -> Result<(Self, EventLoop), MqttError> {
-        let mut options = MqttOptions::new(client_id, (config.host.clone(), config.port));
-        options.set_keep_alive(30);
-
-        // Set credentials if provided
-        if let (Some(user), Some(pass)) = (&config.user, &config.password) {
-            options.set_credentials(user, pass.clone());
+        if !config.clean_start {
+            info!("MQTT persistent session enabled");
         }
-
-        // Configure TLS if enabled
-        if let Some(tls_config) = &config.tls {
-            let transport = build_tls_transport(&config.host, tls_config)?;
-            options.set_transport(transport);
-            info!("MQTT TLS enabled");
+        match config.transport {
+            MqttTransport::Tcp if config.tls.is_some() => info!("MQTT TLS enabled"),
+            MqttTransport::Tcp => {}
+            MqttTransport::Ws => info!("MQTT over WebSockets enabled"),
+            MqttTransport::Wss => info!("MQTT over secure WebSockets enabled"),
         }
 
-        let (client, eventloop) = AsyncClient::builder(options).capacity(100).build();
+        let brokers = config
+            .hosts
+            .iter()
+            .map(|host| Ok((host.clone(), build_options(config, client_id, host)?)))
+            .collect::<Result<Vec<_>, MqttError>>()?;
+        let options = brokers
+            .first()
+            .map(|(_, options)| options.clone())
+            .ok_or_else(|| MqttError::ConnectionFailed("no MQTT broker configured".to_string()))?;
+
+        let (client, eventloop) = AsyncClient::builder(options).capacity(config.cap).build();
 
         Ok((
             Self {
                 client,
                 base_topic: config.topic.clone(),
+                capacity: config.cap,
+                response_qos: qos_level(config.response_qos),
+                brokers,
+                user_properties: config.user_properties.clone(),
             },
             eventloop,
         ))
@@ -71,31 +312,204 @@ impl MqttClient {
     }
 
     /// Publish a message with retain flag
-    pub async fn publish_retained(&self, topic: &str, payload: &str) -> Result<(), MqttError> {
+    ///
+    /// `command` adds a `command` user property for a command's topics.
+    pub async fn publish_retained(
+        &self,
+        topic: &str,
+        payload: &str,
+        command: Option<&str>,
+    ) -> Result<(), MqttError> {
         debug!("Publishing to {}: {}", topic, payload);
         self.client
-            .publish(topic, QoS::AtLeastOnce, true, payload.as_bytes().to_vec())
+            .publish_with_properties(
+                topic,
+                QoS::AtLeastOnce,
+                true,
+                payload.as_bytes().to_vec(),
+                self.properties(command),
+            )
             .await
             .map_err(|e| MqttError::PublishFailed(e.to_string()))
     }
 
+    /// Queue a message without waiting for channel capacity
+    ///
+    /// Fails with `MqttError::ChannelFull` if the event loop is not draining
+    /// the request channel.
+    pub fn try_publish(
+        &self,
+        topic: &str,
+        payload: &str,
+        retain: bool,
+        command: Option<&str>,
+    ) -> Result<(), MqttError> {
+        debug!("Publishing to {}: {}", topic, payload);
+        self.client
+            .try_publish_with_properties(
+                topic,
+                QoS::AtLeastOnce,
+                retain,
+                payload.as_bytes().to_vec(),
+                self.properties(command),
+            )
+            .map_err(|e| match e {
+                ClientError::TryRequest(_) => MqttError::ChannelFull,
+                e => MqttError::PublishFailed(e.to_string()),
+            })
+    }
+
+    /// Publish properties with the static user properties (`MQTT_USER_PROPS`)
+    /// and, for a command's topics, a `command` user property
+    fn properties(&self, command: Option<&str>) -> PublishProperties {
+        let mut user_properties = self.user_properties.clone();
+        if let Some(command) = command {
+            user_properties.push((COMMAND_PROPERTY.to_string(), command.to_string()));
+        }
+        PublishProperties {
+            user_properties,
+            ..Default::default()
+        }
+    }
+
+    /// Request channel capacity
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Failover state for the event loop, if more than one broker is configured
+    pub fn broker_failover(&self) -> Option<BrokerFailover> {
+        (self.brokers.len() > 1).then(|| BrokerFailover::new(self.brokers.clone()))
+    }
+
+    /// QoS for request/response bridge responses
+    pub fn response_qos(&self) -> QoS {
+        self.response_qos
+    }
+
     /// Publish a message without retain flag
-    pub async fn publish(&self, topic: &str, payload: &str) -> Result<(), MqttError> {
+    pub async fn publish(
+        &self,
+        topic: &str,
+        payload: &str,
+        qos: QoS,
+        command: Option<&str>,
+    ) -> Result<(), MqttError> {
         debug!("Publishing to {}: {}", topic, payload);
         self.client
-            .publish(topic, QoS::AtLeastOnce, false, payload.as_bytes().to_vec())
+            .publish_with_properties(
+                topic,
+                qos,
+                false,
+                payload.as_bytes().to_vec(),
+                self.properties(command),
+            )
             .await
             .map_err(|e| MqttError::PublishFailed(e.to_string()))
     }
 
+    /// Disconnect gracefully, waiting up to `grace` for queued publishes to
+    /// be acknowledged first
+    ///
+    /// Only queues the request; `run_event_loop` returns once the disconnect
+    /// has been sent (or the drain timed out).
+    pub async fn disconnect(&self, grace: Duration) -> Result<(), MqttError> {
+        self.client
+            .disconnect_with_timeout(grace)
+            .await
+            .map_err(|e| MqttError::Client(e.to_string()))
+    }
+
+    /// Queue the birth message marked offline, without blocking
+    pub fn publish_offline(&self, birth: &BirthMessage) -> Result<(), MqttError> {
+        if queue_birth_message(&self.client, &birth.offline()) {
+            Ok(())
+        } else {
+            Err(MqttError::ChannelFull)
+        }
+    }
+
     /// Get a clone of the underlying client (for use in multiple tasks)
     pub fn clone_client(&self) -> AsyncClient {
         self.client.clone()
     }
 }
 
-/// Build TLS transport configuration
-fn build_tls_transport(host: &str, config: &TlsConfig) -> Result<Transport, MqttError> {
+/// Map a validated QoS level (0-2) from the configuration
+fn qos_level(level: u8) -> QoS {
+    match level {
+        0 => QoS::AtMostOnce,
+        2 => QoS::ExactlyOnce,
+        _ => QoS::AtLeastOnce,
+    }
+}
+
+/// Build the connection options for one broker host
+fn build_options(
+    config: &MqttConfig,
+    client_id: &str,
+    host: &str,
+) -> Result<MqttOptions, MqttError> {
+    let mut options = MqttOptions::new(client_id, build_broker(config, host)?);
+    options.set_keep_alive(config.keep_alive);
+
+    // A persistent session must outlive the connection, otherwise the
+    // broker discards it on disconnect even without clean start
+    options.set_clean_start(config.clean_start);
+    if !config.clean_start {
+        options.set_session_expiry_interval(Some(PERSISTENT_SESSION_EXPIRY_SECS));
+    }
+
+    // Set credentials if provided
+    if let (Some(user), Some(pass)) = (&config.user, &config.password) {
+        options.set_credentials(user, pass.clone());
+    }
+
+    match config.transport {
+        MqttTransport::Tcp => {
+            // Configure TLS if enabled
+            if let Some(tls_config) = &config.tls {
+                let tls = build_tls_configuration(host, tls_config)?;
+                options.set_transport(Transport::Tls(tls));
+            }
+        }
+        MqttTransport::Ws => {}
+        MqttTransport::Wss => {
+            // TLS options apply to secure WebSockets as well; without
+            // MQTT_TLS the default trust roots are used
+            let tls_config = config.tls.clone().unwrap_or_default();
+            let tls = build_tls_configuration(host, &tls_config)?;
+            options.set_transport(Transport::Wss(tls));
+        }
+    }
+    Ok(options)
+}
+
+/// Build the broker address for the configured transport
+fn build_broker(config: &MqttConfig, host: &str) -> Result<Broker, MqttError> {
+    match config.transport {
+        MqttTransport::Tcp => Ok(Broker::tcp(host.to_string(), config.port)),
+        MqttTransport::Ws | MqttTransport::Wss => {
+            let url = websocket_url(config.transport, host, config.port, &config.ws_path);
+            Broker::websocket(url.clone()).map_err(|e| {
+                MqttError::ConnectionFailed(format!("Invalid WebSocket URL {}: {}", url, e))
+            })
+        }
+    }
+}
+
+/// Format the WebSocket URL of the broker (`wss://` for secure WebSockets)
+fn websocket_url(transport: MqttTransport, host: &str, port: u16, path: &str) -> String {
+    let scheme = match transport {
+        MqttTransport::Wss => "wss",
+        MqttTransport::Tcp | MqttTransport::Ws => "ws",
+    };
+    let separator = if path.starts_with('/') { "" } else { "/" };
+    format!("{}://{}:{}{}{}", scheme, host, port, separator, path)
+}
+
+/// Build TLS configuration (shared by the TLS and secure WebSocket transports)
+fn build_tls_configuration(host: &str, config: &TlsConfig) -> Result<TlsConfiguration, MqttError> {
     let mut root_cert_store = rustls::RootCertStore::empty();
 
     // Load CA certificates
@@ -135,7 +549,7 @@ fn build_tls_transport(host: &str, config: &TlsConfig) -> Result<Transport, Mqtt
         if let (Some(cert_file), Some(key_file)) = (&config.cert_file, &config.key_file) {
             // Client certificate authentication
             let certs = load_certs(cert_file)?;
-            let key = load_private_key(key_file)?;
+            let key = load_private_key(key_file, config.key_password.as_deref())?;
             builder.with_client_auth_cert(certs, key).map_err(|e| {
                 MqttError::ConnectionFailed(format!("Failed to set client cert: {}", e))
             })?
@@ -163,9 +577,7 @@ fn build_tls_transport(host: &str, config: &TlsConfig) -> Result<Transport, Mqtt
         .try_into()
         .map_err(|_| MqttError::ConnectionFailed(format!("Invalid server name: {}", host)))?;
 
-    Ok(Transport::Tls(TlsConfiguration::Rustls(Arc::new(
-        tls_config,
-    ))))
+    Ok(TlsConfiguration::Rustls(Arc::new(tls_config)))
 }
 
 /// Load certificates from a PEM file
@@ -180,10 +592,15 @@ fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, MqttError> {
 }
 
 /// Load a private key from a PEM file
-fn load_private_key(path: &Path) -> Result<PrivateKeyDer<'static>, MqttError> {
-    let file = File::open(path)
+///
+/// Encrypted PKCS#8 keys are decrypted with `password`.
+fn load_private_key(
+    path: &Path,
+    password: Option<&str>,
+) -> Result<PrivateKeyDer<'static>, MqttError> {
+    let pem = std::fs::read(path)
         .map_err(|e| MqttError::ConnectionFailed(format!("Failed to open key file: {}", e)))?;
-    let mut reader = BufReader::new(file);
+    let mut reader = pem.as_slice();
 
     // Try to read PKCS#8 private key first, then RSA, then EC
     loop {
@@ -208,9 +625,17 @@ fn load_private_key(path: &Path) -> Result<PrivateKeyDer<'static>, MqttError> {
         }
     }
 
-    Err(MqttError::ConnectionFailed(
-        "No private key found in file".to_string(),
-    ))
+    // rustls_pemfile skips encrypted keys
+    let Some(encrypted) = find_encrypted_key(&pem) else {
+        return Err(MqttError::NoPrivateKey(path.display().to_string()));
+    };
+    let Some(password) = password else {
+        return Err(MqttError::KeyDecryption(
+            "key is encrypted but MQTT_KEYFILE_PASSWORD is not set".to_string(),
+        ));
+    };
+    let key = decrypt_pkcs8(&encrypted, password)?;
+    Ok(PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key)))
 }
 
 /// Insecure server certificate verifier (for testing/development)
@@ -269,11 +694,32 @@ enum SubscriptionQueueStatus {
     Pending,
 }
 
+/// Queue the subscriptions from `next_subscription` on without blocking
+///
+/// With `batch`, the remaining topics go out as a single SUBSCRIBE request
+/// (`MQTT_SUBSCRIBE_BATCH`) instead of one request per topic.
 fn queue_pending_subscriptions(
     client: &AsyncClient,
     subscribe_topics: &[String],
     next_subscription: &mut usize,
+    batch: bool,
 ) -> SubscriptionQueueStatus {
+    if batch && *next_subscription < subscribe_topics.len() {
+        let topics = &subscribe_topics[*next_subscription..];
+        let filters = topics
+            .iter()
+            .map(|topic| Filter::new(topic.as_str(), QoS::AtLeastOnce));
+        if client.try_subscribe_many(filters).is_err() {
+            return SubscriptionQueueStatus::Pending;
+        }
+
+        info!("Subscribing to {} topics in one request", topics.len());
+        for topic in topics {
+            debug!("Subscribing to {}", topic);
+        }
+        *next_subscription = subscribe_topics.len();
+    }
+
     while *next_subscription < subscribe_topics.len() {
         let topic = &subscribe_topics[*next_subscription];
         if client.try_subscribe(topic, QoS::AtLeastOnce).is_err() {
@@ -310,24 +756,185 @@ fn forward_incoming_message(
     }
 }
 
+/// Queue the birth message without blocking; returns whether it was queued
+fn queue_birth_message(client: &AsyncClient, birth: &BirthMessage) -> bool {
+    let properties = PublishProperties {
+        user_properties: birth.user_properties.clone(),
+        ..Default::default()
+    };
+    match client.try_publish_with_properties(
+        &birth.topic,
+        QoS::AtLeastOnce,
+        true,
+        birth.payload.as_bytes().to_vec(),
+        properties,
+    ) {
+        Ok(()) => {
+            debug!("Published birth message to {}", birth.topic);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Exponential reconnect delay: doubles per consecutive error, capped at
+/// `RECONNECT_BACKOFF_MAX`
+#[derive(Debug)]
+struct Backoff {
+    next: Duration,
+}
+
+impl Backoff {
+    fn new() -> Self {
+        Self {
+            next: RECONNECT_BACKOFF_MIN,
+        }
+    }
+
+    /// Delay to wait now; escalates the following one
+    fn next_delay(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = (self.next * 2).min(RECONNECT_BACKOFF_MAX);
+        delay
+    }
+
+    /// Start over after a successful connect
+    fn reset(&mut self) {
+        self.next = RECONNECT_BACKOFF_MIN;
+    }
+}
+
+/// Consecutive login rejections, for `MQTT_AUTH_FAILURES`
+///
+/// Any other connection error or a successful connect starts the count over,
+/// so only rejections in a row add up.
+#[derive(Debug, Default)]
+struct AuthFailures {
+    count: u32,
+}
+
+impl AuthFailures {
+    /// Record a connection error; returns the number of rejected logins in a
+    /// row, or 0 if `error` is not a login rejection
+    fn connection_failed(&mut self, error: &ConnectionError) -> u32 {
+        match error {
+            ConnectionError::ConnectionRefused(code) if is_auth_failure(*code) => self.count += 1,
+            _ => self.count = 0,
+        }
+        self.count
+    }
+
+    /// Start over after a successful connect
+    fn reset(&mut self) {
+        self.count = 0;
+    }
+}
+
+/// Whether a ConnAck refusal means the credentials (or the client's
+/// permissions) are wrong, so retrying cannot help
+fn is_auth_failure(code: ConnectReturnCode) -> bool {
+    matches!(
+        code,
+        ConnectReturnCode::BadUserNamePassword
+            | ConnectReturnCode::NotAuthorized
+            | ConnectReturnCode::BadAuthenticationMethod
+            | ConnectReturnCode::Banned
+    )
+}
+
+/// Connection settings of the MQTT event loop
+pub struct EventLoopOptions {
+    /// Topics restored on every connect without a resumed session
+    pub subscribe_topics: Vec<String>,
+    /// Retained message (re)published on every connect
+    pub birth: BirthMessage,
+    /// Other brokers to switch to after repeated connection errors
+    pub failover: Option<BrokerFailover>,
+    /// Rejected logins in a row before giving up (`MQTT_AUTH_FAILURES`, 0 = never)
+    pub auth_failure_limit: u32,
+    /// Failed attempts before the first connect before giving up (0 = never)
+    pub connect_max_retries: u32,
+    /// Restore the subscriptions in a single request
+    pub subscribe_batch: bool,
+}
+
+/// Where the MQTT event loop routes incoming messages; messages without a
+/// route are dropped
+#[derive(Debug, Default)]
+pub struct MessageRoutes {
+    /// Requests for the subscriber
+    pub message_tx: Option<mpsc::Sender<IncomingMessage>>,
+    /// Extra polling cycles
+    pub poll_trigger: Option<PollTrigger>,
+    /// Commands switched on and off at runtime
+    pub command_toggles: Option<CommandToggles>,
+    /// Admin reload requests
+    pub admin: Option<AdminControl>,
+}
+
 /// Run the MQTT event loop and forward incoming messages
 ///
 /// When the broker does not resume a previous session on ConnAck, subscriptions
 /// are re-queued with `try_subscribe` and retried across loop iterations so the
-/// rumqtt event loop never blocks waiting for channel capacity.
+/// rumqtt event loop never blocks waiting for channel capacity. The birth
+/// message is (re)published after every ConnAck in the same non-blocking way.
+/// Messages on the poll trigger topic are signalled to the polling loop
+/// instead of being forwarded to the subscriber, and messages on the admin
+/// reload topic to the admin task. With `failover`, repeated
+/// connection errors switch to the next configured broker.
+///
+/// With `subscribe_batch`, the subscriptions are restored in a single
+/// request instead of one per topic.
+///
+/// Until the first successful connect, failed attempts count towards
+/// `connect_max_retries` (0 = unlimited) and the loop gives up with an error
+/// once it is reached; after that, a lost connection is retried indefinitely.
+///
+/// Returns after a graceful disconnect requested via `MqttClient::disconnect`.
+/// Once `cancel` is triggered, it also returns as soon as the broker is not
+/// connected, since there is nothing left to drain.
 pub async fn run_event_loop(
     mut eventloop: EventLoop,
     client: AsyncClient,
-    subscribe_topics: Vec<String>,
-    message_tx: Option<mpsc::Sender<IncomingMessage>>,
     mqtt_connected: Arc<AtomicBool>,
-) {
+    options: EventLoopOptions,
+    routes: MessageRoutes,
+    cancel: CancellationToken,
+) -> Result<(), MqttError> {
+    let EventLoopOptions {
+        subscribe_topics,
+        birth,
+        mut failover,
+        auth_failure_limit,
+        connect_max_retries,
+        subscribe_batch,
+    } = options;
+    let MessageRoutes {
+        message_tx,
+        poll_trigger,
+        command_toggles,
+        admin,
+    } = routes;
     let mut pending_subscription_index: Option<usize> = None;
     let mut subscription_restore_stalled = false;
+    let mut pending_birth = false;
+    let mut backoff = Backoff::new();
+    let mut auth_failures = AuthFailures::default();
+    // Failed attempts before the first ConnAck; None once connected
+    let mut initial_failures: Option<u32> = Some(0);
 
     loop {
+        if pending_birth && queue_birth_message(&client, &birth) {
+            pending_birth = false;
+        }
+
         if let Some(next_subscription) = pending_subscription_index.as_mut() {
-            match queue_pending_subscriptions(&client, &subscribe_topics, next_subscription) {
+            match queue_pending_subscriptions(
+                &client,
+                &subscribe_topics,
+                next_subscription,
+                subscribe_batch,
+            ) {
                 SubscriptionQueueStatus::Complete => {
                     pending_subscription_index = None;
                     subscription_restore_stalled = false;
@@ -343,7 +950,21 @@ pub async fn run_event_loop(
             }
         }
 
-        match eventloop.poll().await {
+        // While connected, keep running after cancellation so queued
+        // publishes drain through the graceful disconnect
+        let event = tokio::select! {
+            event = eventloop.poll() => event,
+            _ = cancel.cancelled(), if !mqtt_connected.load(Ordering::Relaxed) => {
+                debug!("MQTT event loop stopped");
+                return Ok(());
+            }
+        };
+        match event {
+            Ok(Event::Outgoing(Outgoing::Disconnect)) => {
+                info!("Disconnected from MQTT broker");
+                mqtt_connected.store(false, Ordering::Relaxed);
+                return Ok(());
+            }
             Ok(event) => {
                 if let Event::Incoming(incoming) = event {
                     match incoming {
@@ -352,6 +973,30 @@ pub async fn run_event_loop(
                             let payload = String::from_utf8_lossy(&publish.payload).to_string();
                             debug!("Received message on {}: {}", topic, payload);
 
+                            if let Some(trigger) =
+                                poll_trigger.as_ref().filter(|t| t.topic == topic)
+                            {
+                                if !trigger.fire() {
+                                    debug!("Poll already pending, ignoring trigger");
+                                }
+                                continue;
+                            }
+                            if command_toggles
+                                .as_ref()
+                                .is_some_and(|toggles| toggles.handle(&topic, &payload))
+                            {
+                                continue;
+                            }
+                            if let Some(admin) = admin.as_ref().filter(|a| a.topic == topic) {
+                                // A retained request would reload on every connect
+                                if publish.retain {
+                                    warn!("Ignoring retained message on {}", topic);
+                                } else if !admin.fire() {
+                                    debug!("Reload already pending, ignoring request");
+                                }
+                                continue;
+                            }
+
                             let msg = IncomingMessage {
                                 topic: topic.clone(),
                                 payload,
@@ -372,7 +1017,14 @@ pub async fn run_event_loop(
                         Packet::ConnAck(connack) => {
                             info!("Connected to MQTT broker");
                             mqtt_connected.store(true, Ordering::Relaxed);
+                            backoff.reset();
+                            auth_failures.reset();
+                            initial_failures = None;
+                            if let Some(failover) = failover.as_mut() {
+                                failover.connected();
+                            }
                             subscription_restore_stalled = false;
+                            pending_birth = true;
 
                             if !subscribe_topics.is_empty() {
                                 if connack.session_present {
@@ -398,18 +1050,65 @@ pub async fn run_event_loop(
                             mqtt_connected.store(false, Ordering::Relaxed);
                             pending_subscription_index = None;
                             subscription_restore_stalled = false;
+                            pending_birth = false;
                         }
                         _ => {}
                     }
                 }
             }
+            Err(ConnectionError::DisconnectTimeout) => {
+                warn!("Timed out draining MQTT publishes before disconnect");
+                mqtt_connected.store(false, Ordering::Relaxed);
+                return Ok(());
+            }
             Err(e) => {
-                error!("MQTT event loop error: {}", e);
+                match initial_failures.as_mut() {
+                    Some(failures) => {
+                        *failures += 1;
+                        error!(
+                            "MQTT connection attempt {} failed (never connected): {}",
+                            failures, e
+                        );
+                        if connect_max_retries > 0 && *failures >= connect_max_retries {
+                            error!(
+                                "Giving up on the MQTT broker after {} failed connection attempts - check MQTT_HOST/MQTT_PORT",
+                                failures
+                            );
+                            return Err(MqttError::InitialConnectFailed(*failures, e.to_string()));
+                        }
+                    }
+                    None => error!("MQTT event loop error: {}", e),
+                }
                 mqtt_connected.store(false, Ordering::Relaxed);
+                // Wrong credentials never fix themselves: give up instead of
+                // retrying forever
+                let rejected = auth_failures.connection_failed(&e);
+                if let ConnectionError::ConnectionRefused(code) = e {
+                    if auth_failure_limit > 0 && rejected >= auth_failure_limit {
+                        error!(
+                            "MQTT broker rejected the login {} times in a row ({:?}) - check MQTT_USER/MQTT_PASSWORD",
+                            rejected, code
+                        );
+                        return Err(MqttError::AuthFailed(format!("{:?}", code)));
+                    }
+                }
                 pending_subscription_index = None;
                 subscription_restore_stalled = false;
-                // Wait before retrying
-                tokio::time::sleep(Duration::from_secs(10)).await;
+                pending_birth = false;
+                // A fresh broker deserves a quick first attempt
+                if failover
+                    .as_mut()
+                    .is_some_and(|failover| failover.connection_failed(&mut eventloop))
+                {
+                    backoff.reset();
+                }
+                // Wait before retrying, backing off while the error persists
+                let delay = backoff.next_delay();
+                debug!("Reconnecting to MQTT broker in {}s", delay.as_secs());
+                tokio::select! {
+                    _ = tokio::time::sleep(delay) => {}
+                    _ = cancel.cancelled() => return Ok(()),
+                }
             }
         }
     }
@@ -419,6 +1118,119 @@ pub async fn run_event_loop(
 mod tests {
     use super::*;
 
+    #[test]
+    fn auth_failure_codes() {
+        assert!(is_auth_failure(ConnectReturnCode::BadUserNamePassword));
+        assert!(is_auth_failure(ConnectReturnCode::NotAuthorized));
+        assert!(!is_auth_failure(ConnectReturnCode::ServerUnavailable));
+        assert!(!is_auth_failure(ConnectReturnCode::ServerBusy));
+    }
+
+    #[test]
+    fn auth_failures_count_only_rejections_in_a_row() {
+        let rejected = || ConnectionError::ConnectionRefused(ConnectReturnCode::NotAuthorized);
+        let network = || ConnectionError::Io(std::io::ErrorKind::ConnectionReset.into());
+        let mut failures = AuthFailures::default();
+
+        assert_eq!(failures.connection_failed(&rejected()), 1);
+        assert_eq!(failures.connection_failed(&rejected()), 2);
+        // A network drop in between starts the count over
+        assert_eq!(failures.connection_failed(&network()), 0);
+        assert_eq!(failures.connection_failed(&rejected()), 1);
+        assert_eq!(
+            failures.connection_failed(&ConnectionError::ConnectionRefused(
+                ConnectReturnCode::ServerUnavailable
+            )),
+            0
+        );
+        assert_eq!(failures.connection_failed(&rejected()), 1);
+        failures.reset();
+        assert_eq!(failures.connection_failed(&rejected()), 1);
+    }
+
+    #[test]
+    fn broker_failover_rotates_after_repeated_failures() {
+        let options = |host: &str| MqttOptions::new("test-client", (host, 1883));
+        let (_client, mut eventloop) = AsyncClient::builder(options("primary")).build();
+        let mut failover = BrokerFailover::new(vec![
+            ("primary".to_string(), options("primary")),
+            ("backup".to_string(), options("backup")),
+        ]);
+
+        for _ in 1..FAILOVER_AFTER {
+            assert!(!failover.connection_failed(&mut eventloop));
+        }
+        assert!(failover.connection_failed(&mut eventloop));
+        assert_eq!(failover.current_host(), "backup");
+        assert_eq!(eventloop.options.broker(), options("backup").broker());
+
+        // A successful connect restarts the count
+        failover.connection_failed(&mut eventloop);
+        failover.connected();
+        for _ in 1..FAILOVER_AFTER {
+            assert!(!failover.connection_failed(&mut eventloop));
+        }
+        assert!(failover.connection_failed(&mut eventloop));
+        assert_eq!(failover.current_host(), "primary");
+        assert_eq!(eventloop.options.broker(), options("primary").broker());
+    }
+
+    #[test]
+    fn publish_properties_carry_static_and_command_user_properties() {
+        let options = MqttOptions::new("test-client", ("localhost", 1883));
+        let (client, _eventloop) = AsyncClient::builder(options).build();
+        let client = MqttClient {
+            client,
+            base_topic: "heating".to_string(),
+            capacity: 10,
+            response_qos: QoS::AtLeastOnce,
+            brokers: Vec::new(),
+            user_properties: vec![("source".to_string(), "boiler1".to_string())],
+        };
+
+        assert_eq!(
+            client.properties(None).user_properties,
+            vec![("source".to_string(), "boiler1".to_string())]
+        );
+        assert_eq!(
+            client.properties(Some("getTempA")).user_properties,
+            vec![
+                ("source".to_string(), "boiler1".to_string()),
+                ("command".to_string(), "getTempA".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn try_publish_retained_reports_full_channel() {
+        let options = MqttOptions::new("test-client", ("localhost", 1883));
+        let (client, _eventloop) = AsyncClient::builder(options).capacity(1).build();
+        let client = MqttClient {
+            client,
+            base_topic: "heating".to_string(),
+            capacity: 1,
+            response_qos: QoS::AtLeastOnce,
+            brokers: Vec::new(),
+            user_properties: Vec::new(),
+        };
+
+        assert!(client.try_publish("heating/a", "1", true, None).is_ok());
+        assert!(matches!(
+            client.try_publish("heating/b", "2", true, None),
+            Err(MqttError::ChannelFull)
+        ));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_cap_and_resets() {
+        let mut backoff = Backoff::new();
+        let delays: Vec<u64> = (0..8).map(|_| backoff.next_delay().as_secs()).collect();
+        assert_eq!(delays, vec![1, 2, 4, 8, 16, 32, 60, 60]);
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Duration::from_secs(1));
+    }
+
     #[test]
     fn queue_pending_subscriptions_completes_when_capacity_is_available() {
         let options = MqttOptions::new("test-client", ("localhost", 1883));
@@ -429,7 +1241,25 @@ mod tests {
         ];
         let mut next_subscription = 0;
 
-        let status = queue_pending_subscriptions(&client, &topics, &mut next_subscription);
+        let status = queue_pending_subscriptions(&client, &topics, &mut next_subscription, false);
+
+        assert_eq!(status, SubscriptionQueueStatus::Complete);
+        assert_eq!(next_subscription, topics.len());
+    }
+
+    #[test]
+    fn queue_pending_subscriptions_batches_into_one_request() {
+        let options = MqttOptions::new("test-client", ("localhost", 1883));
+        let (client, _eventloop) = AsyncClient::builder(options).capacity(1).build();
+        let topics = vec![
+            "heating/request".to_string(),
+            "heating/poll".to_string(),
+            "heating/command/+/enabled".to_string(),
+        ];
+        let mut next_subscription = 0;
+
+        // One request for all topics fits a channel with room for one
+        let status = queue_pending_subscriptions(&client, &topics, &mut next_subscription, true);
 
         assert_eq!(status, SubscriptionQueueStatus::Complete);
         assert_eq!(next_subscription, topics.len());
@@ -446,12 +1276,94 @@ mod tests {
             .try_subscribe("heating/existing", QoS::AtLeastOnce)
             .expect("request channel should accept the first queued subscription");
 
-        let status = queue_pending_subscriptions(&client, &topics, &mut next_subscription);
+        let status = queue_pending_subscriptions(&client, &topics, &mut next_subscription, false);
 
         assert_eq!(status, SubscriptionQueueStatus::Pending);
         assert_eq!(next_subscription, 0);
     }
 
+    #[test]
+    fn websocket_url_joins_host_port_and_path() {
+        assert_eq!(
+            websocket_url(MqttTransport::Wss, "broker.example.com", 443, "/mqtt"),
+            "wss://broker.example.com:443/mqtt"
+        );
+        assert_eq!(
+            websocket_url(MqttTransport::Ws, "localhost", 8080, "ws"),
+            "ws://localhost:8080/ws"
+        );
+    }
+
+    #[test]
+    fn birth_message_announces_configuration() {
+        let commands = vec!["getTempA".to_string(), "getTempWWsoll".to_string()];
+        let birth = BirthMessage::new("heating", &commands, Duration::from_secs(60), true);
+
+        assert_eq!(birth.topic, "heating/info");
+        let payload: serde_json::Value = serde_json::from_str(&birth.payload).unwrap();
+        assert_eq!(payload["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(
+            payload["commands"],
+            serde_json::json!(["getTempA", "getTempWWsoll"])
+        );
+        assert_eq!(payload["interval"], 60);
+        assert_eq!(payload["subscribe"], true);
+        assert_eq!(payload["online"], true);
+        assert!(payload["hostname"].is_string());
+    }
+
+    #[test]
+    fn offline_birth_message_keeps_the_announcement() {
+        let commands = vec!["getTempA".to_string()];
+        let birth = BirthMessage::new("heating", &commands, Duration::from_secs(60), false)
+            .with_user_properties(vec![("site".to_string(), "home".to_string())]);
+        let offline = birth.offline();
+
+        assert_eq!(offline.topic, "heating/info");
+        assert_eq!(offline.user_properties, birth.user_properties);
+        let payload: serde_json::Value = serde_json::from_str(&offline.payload).unwrap();
+        assert_eq!(payload["online"], false);
+        assert_eq!(payload["commands"], serde_json::json!(["getTempA"]));
+    }
+
+    #[test]
+    fn poll_trigger_coalesces_pending_triggers() {
+        let (trigger, mut rx) = PollTrigger::new("heating");
+        assert_eq!(trigger.topic, "heating/poll");
+
+        assert!(trigger.fire());
+        assert!(!trigger.fire(), "second trigger should be coalesced");
+        assert!(rx.try_recv().is_ok());
+        assert!(rx.try_recv().is_err());
+
+        assert!(trigger.fire());
+    }
+
+    #[test]
+    fn command_toggles_follow_enabled_topics() {
+        let toggles = CommandToggles::new("heating");
+        assert_eq!(toggles.filter(), "heating/command/+/enabled");
+        assert!(toggles.is_enabled("getTempA"));
+
+        assert!(toggles.handle("heating/command/getTempA/enabled", "false"));
+        assert!(!toggles.is_enabled("getTempA"));
+        assert!(toggles.is_enabled("getTempB"));
+
+        // Invalid payloads are consumed but change nothing
+        assert!(toggles.handle("heating/command/getTempA/enabled", "maybe"));
+        assert!(!toggles.is_enabled("getTempA"));
+
+        assert!(toggles.handle("heating/command/getTempA/enabled", "TRUE"));
+        assert!(toggles.is_enabled("getTempA"));
+        assert!(toggles.handle("heating/command/getTempB/enabled", "0"));
+        assert!(toggles.handle("heating/command/getTempB/enabled", ""));
+        assert!(toggles.is_enabled("getTempB"));
+
+        assert!(!toggles.handle("heating/command/getTempA", "false"));
+        assert!(!toggles.handle("heating/command/enabled", "false"));
+        assert!(!toggles.handle("heating/request", "false"));
+    }
+
     #[tokio::test]
     async fn forward_incoming_message_drops_when_subscriber_queue_is_full() {
         let (tx, mut rx) = mpsc::channel(1);
@@ -1,9 +1,15 @@
 //! MQTT module - client, publisher, and subscriber
 
+mod admin;
 mod client;
+mod encrypted_key;
 mod publisher;
 mod subscriber;
 
-pub use client::{run_event_loop, MqttClient};
-pub use publisher::Publisher;
-pub use subscriber::{run_subscriber, Subscriber};
+pub use admin::{run_admin, AdminControl};
+pub use client::{
+    run_event_loop, BirthMessage, BrokerFailover, CommandToggles, EventLoopOptions, MessageRoutes,
+    MqttClient, PollTrigger,
+};
+pub use publisher::{device_info, format_rfc3339, sanitize_name, Publisher};
+pub use subscriber::{run_subscriber, Subscriber, DEFAULT_REQUEST_TIMEOUT};
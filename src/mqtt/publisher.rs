@@ -2,13 +2,18 @@
 //!
 //! Publishes vcontrold command results to MQTT topics.
 
-use std::time::Duration;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use rumqttc::mqttbytes::QoS;
 use tokio::time::timeout;
-use tracing::{debug, error, warn};
+use tracing::{debug, error, info, warn};
 
+use crate::config::PublishConfig;
 use crate::error::MqttError;
-use crate::vcontrold::{CommandResult, Value};
+use crate::sink::{OutputSink, SinkFuture};
+use crate::vcontrold::{format_hex, CommandResult, Value};
 
 /// Timeout for individual MQTT publish operations.
 ///
@@ -21,43 +26,338 @@ use super::client::MqttClient;
 /// Publisher for vcontrold polling results
 pub struct Publisher<'a> {
     client: &'a MqttClient,
+    config: &'a PublishConfig,
+    /// Current moving average per `EMA` command, kept across cycles
+    smoothed: Mutex<HashMap<String, f64>>,
+    /// Last publish per command, for `MAX_AGE`
+    freshness: Mutex<Freshness>,
+    /// Commands that published a value in this run, for `PUBLISH_ONLINE`
+    online: Mutex<HashSet<String>>,
+}
+
+/// When each command's value was last published, and which are stale
+#[derive(Debug, Default)]
+struct Freshness {
+    last: HashMap<String, SystemTime>,
+    stale: HashSet<String>,
+}
+
+impl Freshness {
+    /// Record a publish; returns whether `{topic}/stale` must be (re)set to
+    /// `false` (first value since start, or the command was stale)
+    fn refresh(&mut self, command: &str, time: SystemTime) -> bool {
+        let first = self.last.insert(command.to_string(), time).is_none();
+        let recovered = self.stale.remove(command);
+        first || recovered
+    }
+
+    /// Commands whose last publish is at least `max_age` before `time` and
+    /// that were not already reported stale
+    fn expire(&mut self, time: SystemTime, max_age: Duration) -> Vec<String> {
+        let mut expired: Vec<String> = self
+            .last
+            .iter()
+            .filter(|(_, last)| time.duration_since(**last).unwrap_or_default() >= max_age)
+            .map(|(command, _)| command.clone())
+            .filter(|command| !self.stale.contains(command))
+            .collect();
+        expired.sort();
+        self.stale.extend(expired.iter().cloned());
+        expired
+    }
 }
 
 impl<'a> Publisher<'a> {
     /// Create a new publisher
-    pub fn new(client: &'a MqttClient) -> Self {
-        Self { client }
+    pub fn new(client: &'a MqttClient, config: &'a PublishConfig) -> Self {
+        Self {
+            client,
+            config,
+            smoothed: Mutex::new(HashMap::new()),
+            freshness: Mutex::new(Freshness::default()),
+            online: Mutex::new(HashSet::new()),
+        }
     }
 
     /// Publish a single command result
     ///
-    /// Topic: {base_topic}/command/{command_name}
-    /// Payload: numeric or string value only
-    /// Retained: yes
-    pub async fn publish_result(&self, result: &CommandResult) -> Result<(), MqttError> {
+    /// Topic: {base_topic}/command/{command_name} (see `resolve_topic`)
+    /// Payload: numeric or string value only (numbers per `FORMAT` if set,
+    /// or their `MAP` label)
+    /// Retained: per `PublishConfig::retain` (yes by default)
+    ///
+    /// Numbers of `EMA` commands are replaced by their moving average; with
+    /// `EMA_RAW` the unsmoothed number also goes to `{topic}/raw`. With
+    /// timestamps enabled, `timestamp` is additionally published (retained)
+    /// to `{topic}/ts`.
+    ///
+    /// Returns whether a value was published; failed results, results without
+    /// a value and non-finite numbers are skipped.
+    pub async fn publish_result(
+        &self,
+        result: &CommandResult,
+        timestamp: &str,
+    ) -> Result<bool, MqttError> {
         // Skip if there was an error
         if result.error.is_some() {
             warn!(
                 "Skipping publish for {} due to error: {:?}",
-                result.command, result.error
+                result.command,
+                result.error_with_detail()
             );
-            return Ok(());
+            return Ok(false);
         }
 
         // Skip if value is None
+        let mut raw_payload = None;
         let payload = match &result.value {
-            Value::Number(n) => format_number(*n),
+            Value::Number(n) => {
+                let Some(payload) = number_payload(self.config, &result.command, *n) else {
+                    warn!(
+                        "Skipping publish for {} - non-finite value {}",
+                        result.command, n
+                    );
+                    return Ok(false);
+                };
+                let smoothed = self
+                    .smooth(&result.command, *n)
+                    .and_then(|avg| number_payload(self.config, &result.command, avg));
+                match smoothed {
+                    Some(smoothed) => {
+                        raw_payload = Some(payload);
+                        smoothed
+                    }
+                    None => payload,
+                }
+            }
             Value::String(s) => s.clone(),
+            Value::Bytes(bytes) => format_hex(bytes),
             Value::None => {
                 debug!("Skipping publish for {} - no value", result.command);
-                return Ok(());
+                return Ok(false);
             }
         };
 
-        let topic = self.client.topic(&format!("command/{}", result.command));
+        let topic = resolve_topic(self.config, self.client.base_topic(), &result.command);
+        let retain = self.config.retain(&result.command);
         debug!("Publishing to {}: {}", topic, payload);
+        let command = Some(result.command.as_str());
+        self.publish_with_timeout(&topic, &payload, retain, command)
+            .await?;
+
+        if let Some(raw) = raw_payload.filter(|_| self.config.ema_raw) {
+            let raw_topic = format!("{}/raw", topic);
+            self.publish_with_timeout(&raw_topic, &raw, retain, command)
+                .await?;
+        }
+
+        if self.config.timestamp {
+            let ts_topic = format!("{}/ts", topic);
+            self.publish_with_timeout(&ts_topic, timestamp, retain, command)
+                .await?;
+        }
+
+        Ok(true)
+    }
+
+    /// Fold `value` into the command's moving average (`None` without `EMA`)
+    fn smooth(&self, command: &str, value: f64) -> Option<f64> {
+        let alpha = *self.config.ema.get(command)?;
+        let mut smoothed = self.smoothed.lock().unwrap();
+        let avg = ema_step(smoothed.get(command).copied(), alpha, value);
+        smoothed.insert(command.to_string(), avg);
+        Some(avg)
+    }
+
+    /// Track a published value for `MAX_AGE`, clearing `{topic}/stale`
+    /// on its first value and when it recovers
+    async fn refresh(&self, command: &str, time: SystemTime) {
+        if self.config.max_age.is_none() {
+            return;
+        }
+        let recovered = {
+            let mut freshness = self.freshness.lock().unwrap();
+            let was_stale = freshness.stale.contains(command);
+            if !freshness.refresh(command, time) {
+                return;
+            }
+            was_stale
+        };
+        if recovered {
+            info!("{} is publishing again, no longer stale", command);
+        }
+        self.publish_stale_flag(command, false).await;
+    }
+
+    /// Flag commands not published within `MAX_AGE` as stale
+    async fn expire(&self, time: SystemTime) {
+        let Some(max_age) = self.config.max_age else {
+            return;
+        };
+        let expired = self.freshness.lock().unwrap().expire(time, max_age);
+        for command in expired {
+            warn!(
+                "No value for {} within MAX_AGE ({}s), marking it stale",
+                command,
+                max_age.as_secs()
+            );
+            self.publish_stale_flag(&command, true).await;
+        }
+    }
+
+    /// Publish `true`/`false` to `{topic}/stale`, retained like the value
+    async fn publish_stale_flag(&self, command: &str, stale: bool) {
+        let topic = format!(
+            "{}/stale",
+            resolve_topic(self.config, self.client.base_topic(), command)
+        );
+        let retain = self.config.retain(command);
+        if let Err(e) = self
+            .publish_with_timeout(&topic, &stale.to_string(), retain, Some(command))
+            .await
+        {
+            error!("Failed to publish stale flag for {}: {}", command, e);
+        }
+    }
+
+    /// Publish `true` to `{topic}/online` the first time a command
+    /// publishes a value in this run (`PUBLISH_ONLINE`)
+    async fn mark_online(&self, command: &str) {
+        if !self.config.online_flags || !self.online.lock().unwrap().insert(command.to_string()) {
+            return;
+        }
+        info!("First value of {} received", command);
+        self.publish_online_flag(command, true).await;
+    }
+
+    /// Reset `{topic}/online` to `false` for `commands` at startup
+    ///
+    /// Clears the flags retained by a previous run, so they only turn `true`
+    /// once this run has read a value.
+    pub async fn reset_online_flags(&self, commands: &[String]) {
+        if !self.config.online_flags {
+            return;
+        }
+        for command in commands {
+            self.publish_online_flag(command, false).await;
+        }
+    }
+
+    /// Publish `true`/`false` (retained) to `{topic}/online`
+    async fn publish_online_flag(&self, command: &str, online: bool) {
+        let topic = format!(
+            "{}/online",
+            resolve_topic(self.config, self.client.base_topic(), command)
+        );
+        if let Err(e) = self
+            .publish_with_timeout(&topic, &online.to_string(), true, Some(command))
+            .await
+        {
+            error!("Failed to publish online flag for {}: {}", command, e);
+        }
+    }
+
+    /// Publish a command's window aggregate to `{topic}/agg`
+    ///
+    /// Retained like the command's value.
+    pub async fn publish_aggregate(&self, command: &str, payload: &str) {
+        let topic = format!(
+            "{}/agg",
+            resolve_topic(self.config, self.client.base_topic(), command)
+        );
+        let retain = self.config.retain(command);
+        if let Err(e) = self
+            .publish_with_timeout(&topic, payload, retain, Some(command))
+            .await
+        {
+            error!("Failed to publish aggregate for {}: {}", command, e);
+        }
+    }
+
+    /// Publish the per-command stats object (retained) to `{base_topic}/stats`
+    pub async fn publish_stats(&self, payload: &str) {
+        let topic = self.client.topic("stats");
+        if let Err(e) = self.publish_with_timeout(&topic, payload, true, None).await {
+            error!("Failed to publish stats: {}", e);
+        }
+    }
+
+    /// Publish the vcontrold link stats (retained) to `{base_topic}/stats/vcontrold`
+    pub async fn publish_link_stats(&self, payload: &str) {
+        let topic = self.client.topic("stats/vcontrold");
+        if let Err(e) = self.publish_with_timeout(&topic, payload, true, None).await {
+            error!("Failed to publish vcontrold link stats: {}", e);
+        }
+    }
+
+    /// Publish whether vcontrold is reachable (retained) to `{base_topic}/status/vcontrold`
+    pub async fn publish_vcontrold_status(&self, payload: &str) {
+        let topic = self.client.topic("status/vcontrold");
+        if let Err(e) = self.publish_with_timeout(&topic, payload, true, None).await {
+            error!("Failed to publish vcontrold status: {}", e);
+        }
+    }
+
+    /// Publish the topic catalog (retained) to `{base_topic}/topics`
+    pub async fn publish_topic_catalog(&self, commands: &[String]) {
+        let topic = self.client.topic("topics");
+        let payload = topic_catalog(self.config, self.client.base_topic(), commands);
+        if let Err(e) = self
+            .publish_with_timeout(&topic, &payload, true, None)
+            .await
+        {
+            error!("Failed to publish topic catalog: {}", e);
+        }
+    }
+
+    /// Clear the retained topics of a command that is no longer polled
+    ///
+    /// Publishes an empty retained payload to its value topic and the
+    /// `/ts`, `/raw`, `/agg`, `/stale` and `/online` topics derived from it.
+    pub async fn clear_retained(&self, command: &str) {
+        let topic = resolve_topic(self.config, self.client.base_topic(), command);
+        for suffix in ["", "/ts", "/raw", "/agg", "/stale", "/online"] {
+            let topic = format!("{}{}", topic, suffix);
+            if let Err(e) = self
+                .publish_with_timeout(&topic, "", true, Some(command))
+                .await
+            {
+                error!("Failed to clear {}: {}", topic, e);
+            }
+        }
+    }
 
-        match timeout(PUBLISH_TIMEOUT, self.client.publish_retained(&topic, &payload)).await {
+    /// Publish a message, giving up after `PUBLISH_TIMEOUT`
+    ///
+    /// A full request channel is logged separately from the timeout, so a
+    /// stalled broker can be told apart from a too small `MQTT_CAP`. `command`
+    /// is attached as a user property to a command's topics.
+    async fn publish_with_timeout(
+        &self,
+        topic: &str,
+        payload: &str,
+        retain: bool,
+        command: Option<&str>,
+    ) -> Result<(), MqttError> {
+        match self.client.try_publish(topic, payload, retain, command) {
+            Err(MqttError::ChannelFull) => warn!(
+                "MQTT request channel full (MQTT_CAP={}) while publishing {} - waiting for the event loop",
+                self.client.capacity(),
+                topic
+            ),
+            result => return result,
+        }
+
+        let publish = async {
+            if retain {
+                self.client.publish_retained(topic, payload, command).await
+            } else {
+                self.client
+                    .publish(topic, payload, QoS::AtLeastOnce, command)
+                    .await
+            }
+        };
+        match timeout(PUBLISH_TIMEOUT, publish).await {
             Ok(result) => result,
             Err(_) => {
                 warn!(
@@ -69,48 +369,461 @@ impl<'a> Publisher<'a> {
             }
         }
     }
+}
 
-    /// Publish multiple command results
-    pub async fn publish_results(&self, results: &[CommandResult]) {
-        for result in results {
-            if let Err(e) = self.publish_result(result).await {
-                error!("Failed to publish {}: {}", result.command, e);
+/// MQTT is the default sink: each value goes to its command topic as soon as
+/// its batch completes
+impl OutputSink for Publisher<'_> {
+    fn publish<'a>(&'a self, result: &'a CommandResult, time: SystemTime) -> SinkFuture<'a> {
+        Box::pin(async move {
+            match self.publish_result(result, &format_rfc3339(time)).await {
+                Ok(true) => {
+                    self.refresh(&result.command, time).await;
+                    self.mark_online(&result.command).await;
+                }
+                Ok(false) => {}
+                Err(e) => error!("Failed to publish {}: {}", result.command, e),
             }
-        }
+        })
+    }
+
+    fn publish_cycle<'a>(
+        &'a self,
+        _results: &'a [CommandResult],
+        time: SystemTime,
+    ) -> SinkFuture<'a> {
+        Box::pin(self.expire(time))
+    }
+}
+
+/// Resolve the topic a command's value is published to
+///
+/// A `TOPIC_MAP` entry is used verbatim; otherwise `{base}` and `{cmd}` in
+/// the topic template are substituted, with `{cmd}` being the command's alias
+/// if one is configured. With `DEVICE_SEPARATOR`, the device segment of a
+/// command is appended to `{base}` and `{cmd}` is the rest of the name.
+pub fn resolve_topic(config: &PublishConfig, base_topic: &str, command: &str) -> String {
+    if let Some(topic) = config.topic_map.get(command) {
+        return topic.clone();
+    }
+    let (device, name) = config.split_device(command);
+    let name = config.aliases.get(command).map_or(name, String::as_str);
+    let base = match device {
+        Some(device) => format!("{}/{}", base_topic, device),
+        None => base_topic.to_string(),
+    };
+    config
+        .topic_template
+        .replace("{base}", &base)
+        .replace("{cmd}", name)
+}
+
+/// Object id of a command, e.g. for Home Assistant `unique_id`s
+///
+/// Only used for ids; topics keep the real command name.
+/// Lowercases ASCII letters and replaces every character other than an
+/// ASCII letter or digit with `_` (`getTempWWObenIst` -> `gettempwwobenist`,
+/// `dev1.getTempA` -> `dev1_gettempa`).
+pub fn sanitize_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Build the topic catalog listing where each command's value is published
+///
+/// Format: `[{"command":"getTempA","object_id":"gettempa","topic":"heating/command/getTempA","alias":null,"retain":true}]`
+pub fn topic_catalog(config: &PublishConfig, base_topic: &str, commands: &[String]) -> String {
+    let entries: Vec<serde_json::Value> = commands
+        .iter()
+        .map(|command| {
+            serde_json::json!({
+                "command": command,
+                "object_id": sanitize_name(command),
+                "topic": resolve_topic(config, base_topic, command),
+                "alias": config.aliases.get(command),
+                "retain": config.retain(command),
+            })
+        })
+        .collect();
+    serde_json::Value::Array(entries).to_string()
+}
+
+/// One exponential moving average step; the first value seeds the average
+fn ema_step(previous: Option<f64>, alpha: f64, value: f64) -> f64 {
+    match previous {
+        Some(avg) if avg.is_finite() => avg + alpha * (value - avg),
+        _ => value,
+    }
+}
+
+/// Build the device inventory payload published to `{base_topic}/device`
+///
+/// Format: `{"device_type":"V200KW2","vcontrold_version":"0.98.12"}` (null when unknown)
+pub fn device_info(device_type: Option<&str>, vcontrold_version: Option<&str>) -> String {
+    serde_json::json!({
+        "device_type": device_type,
+        "vcontrold_version": vcontrold_version,
+    })
+    .to_string()
+}
+
+/// Format a point in time as an RFC3339 UTC timestamp with second precision
+///
+/// Example: `2026-01-21T12:53:20Z`
+pub fn format_rfc3339(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+
+    // Civil date from days since epoch (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3_600,
+        (rem % 3_600) / 60,
+        rem % 60
+    )
+}
+
+/// Format a polled number as a bare-value payload
+///
+/// Applies the command's `FORMAT` and, with `OUTPUT_DECIMAL_COMMA`, a decimal
+/// comma. JSON payloads never use this, so their numbers stay valid. `None`
+/// for NaN and infinity, which are not published.
+fn number_payload(config: &PublishConfig, command: &str, n: f64) -> Option<String> {
+    if let Some(label) = config.value_maps.get(command).and_then(|map| map.label(n)) {
+        return Some(label.to_string());
+    }
+    let payload = match config.formats.get(command) {
+        Some(format) if n.is_finite() => format.format(n),
+        _ => format_number(n)?,
+    };
+    if config.decimal_comma {
+        Some(payload.replace('.', ","))
+    } else {
+        Some(payload)
     }
 }
 
 /// Format a number for MQTT payload
 ///
-/// Outputs integers without decimal places, floats with minimal precision
-fn format_number(n: f64) -> String {
-    if n.fract() == 0.0 && n.abs() < i64::MAX as f64 {
-        format!("{}", n as i64)
+/// Outputs integers without decimal places, floats with minimal precision;
+/// `None` for NaN and infinity.
+fn format_number(n: f64) -> Option<String> {
+    if !n.is_finite() {
+        None
+    } else if n.fract() == 0.0 && n.abs() < i64::MAX as f64 {
+        Some(format!("{}", n as i64))
     } else {
         // Remove trailing zeros
         let s = format!("{:.6}", n);
         let s = s.trim_end_matches('0');
         let s = s.trim_end_matches('.');
-        s.to_string()
+        Some(s.to_string())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::{NumberFormat, ValueMap};
+
+    #[test]
+    fn test_device_info() {
+        let json: serde_json::Value =
+            serde_json::from_str(&device_info(Some("V200KW2"), None)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"device_type": "V200KW2", "vcontrold_version": null})
+        );
+    }
 
     #[test]
     fn test_format_number_integer() {
-        assert_eq!(format_number(42.0), "42");
-        assert_eq!(format_number(-10.0), "-10");
-        assert_eq!(format_number(0.0), "0");
+        assert_eq!(format_number(42.0).as_deref(), Some("42"));
+        assert_eq!(format_number(-10.0).as_deref(), Some("-10"));
+        assert_eq!(format_number(0.0).as_deref(), Some("0"));
     }
 
     #[test]
     fn test_format_number_float() {
-        assert_eq!(format_number(48.1), "48.1");
-        assert_eq!(format_number(3.14159), "3.14159");
-        assert_eq!(format_number(0.5), "0.5");
+        assert_eq!(format_number(48.1).as_deref(), Some("48.1"));
+        assert_eq!(format_number(3.14159).as_deref(), Some("3.14159"));
+        assert_eq!(format_number(0.5).as_deref(), Some("0.5"));
+    }
+
+    #[test]
+    fn test_format_number_non_finite() {
+        assert_eq!(format_number(f64::NAN), None);
+        assert_eq!(format_number(f64::INFINITY), None);
+        assert_eq!(format_number(f64::NEG_INFINITY), None);
+
+        let config = PublishConfig {
+            formats: [("getTempA".to_string(), NumberFormat { decimals: 1 })].into(),
+            ..PublishConfig::default()
+        };
+        assert_eq!(number_payload(&config, "getTempA", f64::NAN), None);
+        assert_eq!(number_payload(&config, "getTempB", f64::INFINITY), None);
+    }
+
+    #[test]
+    fn test_number_payload_decimal_comma() {
+        let config = PublishConfig {
+            decimal_comma: true,
+            formats: [("getTempWWsoll".to_string(), NumberFormat { decimals: 2 })].into(),
+            ..PublishConfig::default()
+        };
+        assert_eq!(
+            number_payload(&config, "getTempA", 48.1).as_deref(),
+            Some("48,1")
+        );
+        assert_eq!(
+            number_payload(&config, "getTempA", -0.5).as_deref(),
+            Some("-0,5")
+        );
+        assert_eq!(
+            number_payload(&config, "getTempA", 42.0).as_deref(),
+            Some("42")
+        );
+        assert_eq!(
+            number_payload(&config, "getTempWWsoll", 50.0).as_deref(),
+            Some("50,00")
+        );
+
+        let config = PublishConfig::default();
+        assert_eq!(
+            number_payload(&config, "getTempA", 48.1).as_deref(),
+            Some("48.1")
+        );
+    }
+
+    #[test]
+    fn test_number_payload_value_map() {
+        let status = ValueMap(vec![
+            (0.0, "Standby".to_string()),
+            (1.0, "Heating".to_string()),
+        ]);
+        let config = PublishConfig {
+            value_maps: [("getStatus".to_string(), status)].into(),
+            decimal_comma: true,
+            ..PublishConfig::default()
+        };
+        // Mapped codes
+        assert_eq!(
+            number_payload(&config, "getStatus", 1.0).as_deref(),
+            Some("Heating")
+        );
+        assert_eq!(
+            number_payload(&config, "getStatus", 0.0).as_deref(),
+            Some("Standby")
+        );
+        // Unmapped codes and other commands pass through
+        assert_eq!(
+            number_payload(&config, "getStatus", 7.0).as_deref(),
+            Some("7")
+        );
+        assert_eq!(
+            number_payload(&config, "getStatus", 1.5).as_deref(),
+            Some("1,5")
+        );
+        assert_eq!(
+            number_payload(&config, "getTempA", 1.0).as_deref(),
+            Some("1")
+        );
+    }
+
+    #[test]
+    fn test_freshness_marks_stale_once_and_recovers() {
+        let start = UNIX_EPOCH + Duration::from_secs(1_000);
+        let max_age = Duration::from_secs(300);
+        let mut freshness = Freshness::default();
+
+        // The first value clears a stale flag left by a previous run
+        assert!(freshness.refresh("getTempA", start));
+        assert!(freshness.refresh("getTempB", start));
+        assert!(!freshness.refresh("getTempA", start + Duration::from_secs(60)));
+
+        assert!(freshness
+            .expire(start + Duration::from_secs(299), max_age)
+            .is_empty());
+        assert_eq!(
+            freshness.expire(start + Duration::from_secs(300), max_age),
+            vec!["getTempB"]
+        );
+        // Reported only once while it stays stale
+        assert!(freshness
+            .expire(start + Duration::from_secs(330), max_age)
+            .is_empty());
+
+        assert!(freshness.refresh("getTempB", start + Duration::from_secs(400)));
+        assert_eq!(
+            freshness.expire(start + Duration::from_secs(400), max_age),
+            vec!["getTempA"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_non_finite_value_is_not_marked_online() {
+        let config = crate::config::Config::from_lookup(|name| match name {
+            "MQTT_HOST" => Some("localhost".to_string()),
+            "MQTT_TOPIC" => Some("test".to_string()),
+            "MAX_AGE" => Some("300".to_string()),
+            "PUBLISH_ONLINE" => Some("true".to_string()),
+            _ => None,
+        })
+        .unwrap();
+        let (client, _eventloop) = MqttClient::new(&config.mqtt, "test").unwrap();
+        let publisher = Publisher::new(&client, &config.publish);
+
+        let nan = CommandResult::new("getTempA", Value::Number(f64::NAN));
+        assert!(!publisher.publish_result(&nan, "ts").await.unwrap());
+        OutputSink::publish(&publisher, &nan, SystemTime::now()).await;
+        assert!(publisher.online.lock().unwrap().is_empty());
+        assert!(publisher.freshness.lock().unwrap().last.is_empty());
+    }
+
+    #[test]
+    fn test_ema_step() {
+        assert_eq!(ema_step(None, 0.3, 30.0), 30.0);
+        assert!((ema_step(Some(30.0), 0.3, 31.0) - 30.3).abs() < 1e-9);
+        assert_eq!(ema_step(Some(30.0), 1.0, 31.0), 31.0);
+        assert_eq!(ema_step(Some(f64::NAN), 0.3, 31.0), 31.0);
+
+        let avg = [30.5, 29.5, 30.5, 29.5]
+            .iter()
+            .fold(30.0, |avg, &v| ema_step(Some(avg), 0.3, v));
+        assert!((avg - 30.0).abs() < 0.3);
+    }
+
+    #[test]
+    fn test_resolve_topic_default_layout() {
+        let config = PublishConfig::default();
+        assert_eq!(
+            resolve_topic(&config, "heating", "getTempA"),
+            "heating/command/getTempA"
+        );
+    }
+
+    #[test]
+    fn test_resolve_topic_map_and_template() {
+        let config = PublishConfig {
+            topic_template: "{base}/sensors/{cmd}/value".to_string(),
+            topic_map: [(
+                "getTempWWObenIst".to_string(),
+                "home/heating/warmwater/temp".to_string(),
+            )]
+            .into(),
+            ..PublishConfig::default()
+        };
+        assert_eq!(
+            resolve_topic(&config, "heating", "getTempWWObenIst"),
+            "home/heating/warmwater/temp"
+        );
+        assert_eq!(
+            resolve_topic(&config, "heating", "getTempA"),
+            "heating/sensors/getTempA/value"
+        );
+    }
+
+    #[test]
+    fn test_resolve_topic_uses_alias() {
+        let config = PublishConfig {
+            aliases: [("getTempWWObenIst".to_string(), "warmwater_top".to_string())].into(),
+            ..PublishConfig::default()
+        };
+        assert_eq!(
+            resolve_topic(&config, "heating", "getTempWWObenIst"),
+            "heating/command/warmwater_top"
+        );
+        assert_eq!(
+            resolve_topic(&config, "heating", "getTempA"),
+            "heating/command/getTempA"
+        );
+    }
+
+    #[test]
+    fn test_resolve_topic_device_separator() {
+        let config = PublishConfig {
+            device_separator: Some(".".to_string()),
+            aliases: [("dev2.getTempA".to_string(), "outside".to_string())].into(),
+            ..PublishConfig::default()
+        };
+        assert_eq!(
+            resolve_topic(&config, "heating", "dev1.getTempA"),
+            "heating/dev1/command/getTempA"
+        );
+        assert_eq!(
+            resolve_topic(&config, "heating", "dev2.getTempA"),
+            "heating/dev2/command/outside"
+        );
+        // No separator (or nothing before it) publishes flat
+        assert_eq!(
+            resolve_topic(&config, "heating", "getTempA"),
+            "heating/command/getTempA"
+        );
+        assert_eq!(
+            resolve_topic(&config, "heating", ".getTempA"),
+            "heating/command/.getTempA"
+        );
+    }
+
+    #[test]
+    fn test_sanitize_name() {
+        assert_eq!(sanitize_name("getTempWWObenIst"), "gettempwwobenist");
+        assert_eq!(sanitize_name("dev1.getTempA"), "dev1_gettempa");
+        assert_eq!(sanitize_name("get Temp-A°"), "get_temp_a_");
+    }
+
+    #[test]
+    fn test_topic_catalog() {
+        let config = PublishConfig {
+            aliases: [("getTempA".to_string(), "outside".to_string())].into(),
+            topic_map: [("getTempB".to_string(), "home/boiler".to_string())].into(),
+            no_retain_commands: ["getTempB".to_string()].into(),
+            ..PublishConfig::default()
+        };
+        let commands = vec!["getTempA".to_string(), "getTempB".to_string()];
+
+        let catalog: serde_json::Value =
+            serde_json::from_str(&topic_catalog(&config, "heating", &commands)).unwrap();
+        assert_eq!(
+            catalog,
+            serde_json::json!([
+                {"command": "getTempA", "object_id": "gettempa", "topic": "heating/command/outside", "alias": "outside", "retain": true},
+                {"command": "getTempB", "object_id": "gettempb", "topic": "home/boiler", "alias": null, "retain": false},
+            ])
+        );
+    }
+
+    #[test]
+    fn test_format_rfc3339() {
+        let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+        assert_eq!(format_rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(format_rfc3339(at(951_782_400)), "2000-02-29T00:00:00Z");
+        assert_eq!(format_rfc3339(at(1_769_000_000)), "2026-01-21T12:53:20Z");
+        assert_eq!(format_rfc3339(at(4_102_444_799)), "2099-12-31T23:59:59Z");
     }
 
     #[test]
@@ -2,21 +2,75 @@
 //!
 //! Handles incoming MQTT requests and forwards them to vcontrold.
 
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Deserialize;
 use tokio::sync::mpsc;
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, warn};
-use crate::vcontrold::{build_json_response, VcontroldClient};
+
+use crate::cache::{is_write_command, ResponseCache};
+use crate::config::ArgRange;
+use crate::error::VcontroldError;
+use crate::vcontrold::{
+    build_json_object_with_errors, CommandResult, JsonOptions, VcontroldClient,
+};
 
 use super::client::{IncomingMessage, MqttClient};
 
-/// Request topic suffix
-const REQUEST_SUFFIX: &str = "request";
-/// Response topic suffix
-const RESPONSE_SUFFIX: &str = "response";
+/// Default request topic suffix
+const DEFAULT_REQUEST_SUFFIX: &str = "request";
+/// Default response topic suffix
+const DEFAULT_RESPONSE_SUFFIX: &str = "response";
+/// Topic level of topic-addressed requests (`{base}/get/<command>`)
+const GET_PREFIX: &str = "get";
+/// Default response timeout per command (`REQUEST_TIMEOUT`), shorter than
+/// the polling timeout so interactive requests fail fast
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(8);
+/// Response key flagging a request cut short by its deadline
+const TIMEOUT_KEY: &str = "timeout";
+
+/// A parsed request
+#[derive(Debug, Default, PartialEq, Deserialize)]
+pub struct Request {
+    /// Commands in request order
+    pub commands: Vec<String>,
+    /// Execute as one uninterrupted sequence that stops at the first failure
+    #[serde(default)]
+    pub atomic: bool,
+}
 
 /// Subscriber for request/response bridge
 pub struct Subscriber {
     base_topic: String,
+    /// Suffix of the default request topic (`{base}/request`)
+    request_suffix: String,
+    /// Suffix of the default response topic and of get responses
+    response_suffix: String,
+    /// Allowed numeric argument range per write command
+    set_ranges: HashMap<String, ArgRange>,
+    /// Additional (request topic, response topic) pairs
+    topic_pairs: Vec<(String, String)>,
+    /// Response key per command (commands without alias use their name)
+    aliases: HashMap<String, String>,
+    /// Separator between commands in a plain-text payload
+    separator: String,
+    /// Time each command may take to respond
+    timeout: Duration,
+    /// Time a whole (non-atomic) request may take before the response is
+    /// published with what finished so far
+    deadline: Option<Duration>,
+    /// Retries of commands whose vcontrold connection broke
+    retries: u32,
+    /// Serve topic-addressed requests on `{base}/get/<command>`
+    get_topics: bool,
+    /// Recent reads answered without querying vcontrold (`CACHE_TTL`)
+    cache: Option<ResponseCache>,
+    /// Null handling and raw responses in the response JSON
+    json_options: JsonOptions,
 }
 
 impl Subscriber {
@@ -24,22 +78,184 @@ impl Subscriber {
     pub fn new(base_topic: &str) -> Self {
         Self {
             base_topic: base_topic.to_string(),
+            request_suffix: DEFAULT_REQUEST_SUFFIX.to_string(),
+            response_suffix: DEFAULT_RESPONSE_SUFFIX.to_string(),
+            set_ranges: HashMap::new(),
+            topic_pairs: Vec::new(),
+            aliases: HashMap::new(),
+            separator: ",".to_string(),
+            timeout: DEFAULT_REQUEST_TIMEOUT,
+            deadline: None,
+            retries: 0,
+            get_topics: false,
+            cache: None,
+            json_options: JsonOptions::default(),
         }
     }
 
+    /// Serve `{base}/<request_suffix>` and answer on `{base}/<response_suffix>`
+    /// (and `{base}/get/<command>/<response_suffix>`) instead of
+    /// `request`/`response`
+    pub fn with_suffixes(mut self, request_suffix: &str, response_suffix: &str) -> Self {
+        self.request_suffix = request_suffix.to_string();
+        self.response_suffix = response_suffix.to_string();
+        self
+    }
+
+    /// Answer reads from `cache` while fresh and store executed reads in it
+    pub fn with_cache(mut self, cache: ResponseCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Leave commands without a value out of responses instead of sending `null`
+    pub fn with_omit_nulls(mut self, omit_nulls: bool) -> Self {
+        self.json_options.omit_nulls = omit_nulls;
+        self
+    }
+
+    /// Add the raw vcontrold responses under a `raw` object to responses
+    pub fn with_raw(mut self, raw: bool) -> Self {
+        self.json_options.raw = raw;
+        self
+    }
+
+    /// Add the units of the values under a `units` object to responses
+    pub fn with_units(mut self, units: bool) -> Self {
+        self.json_options.units = units;
+        self
+    }
+
+    /// Also serve `{base}/get/<command>`, answering on `{base}/get/<command>/response`
+    /// (or the suffix set with [`with_suffixes`](Self::with_suffixes))
+    pub fn with_get_topics(mut self, enabled: bool) -> Self {
+        self.get_topics = enabled;
+        self
+    }
+
+    /// Retry commands up to `retries` times on a fresh connection when the
+    /// connection to vcontrold broke (e.g. after a vcontrold restart)
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Fail a command after `timeout` without response instead of the default
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Answer each request within `deadline`, reporting unfinished commands
+    /// as timed out instead of waiting for them
+    pub fn with_deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Split plain-text payloads on the given separator instead of a comma
+    pub fn with_separator(mut self, separator: &str) -> Self {
+        self.separator = separator.to_string();
+        self
+    }
+
+    /// Use the given aliases as response keys
+    pub fn with_aliases(mut self, aliases: HashMap<String, String>) -> Self {
+        self.aliases = aliases;
+        self
+    }
+
+    /// Also serve requests on the given (request topic, response topic) pairs
+    pub fn with_topic_pairs(mut self, topic_pairs: Vec<(String, String)>) -> Self {
+        self.topic_pairs = topic_pairs;
+        self
+    }
+
+    /// Restrict the arguments of the given commands to numeric ranges
+    pub fn with_set_ranges(mut self, set_ranges: HashMap<String, ArgRange>) -> Self {
+        self.set_ranges = set_ranges;
+        self
+    }
+
     /// Get the request topic
     pub fn request_topic(&self) -> String {
-        format!("{}/{}", self.base_topic, REQUEST_SUFFIX)
+        format!("{}/{}", self.base_topic, self.request_suffix)
     }
 
     /// Get the response topic
     pub fn response_topic(&self) -> String {
-        format!("{}/{}", self.base_topic, RESPONSE_SUFFIX)
+        format!("{}/{}", self.base_topic, self.response_suffix)
+    }
+
+    /// Get all request topics to subscribe to (default topic first)
+    pub fn request_topics(&self) -> Vec<String> {
+        let get_filter = format!("{}/{}/+", self.base_topic, GET_PREFIX);
+        std::iter::once(self.request_topic())
+            .chain(self.topic_pairs.iter().map(|(request, _)| request.clone()))
+            .chain(self.get_topics.then_some(get_filter))
+            .collect()
+    }
+
+    /// Get the command addressed by a `{base}/get/<command>` topic
+    ///
+    /// Returns `None` if get topics are disabled or `topic` is not one.
+    pub fn get_command_for(&self, topic: &str) -> Option<String> {
+        if !self.get_topics {
+            return None;
+        }
+        let command = topic
+            .strip_prefix(self.base_topic.as_str())?
+            .strip_prefix('/')?
+            .strip_prefix(GET_PREFIX)?
+            .strip_prefix('/')?;
+        if command.is_empty() || command.contains('/') {
+            return None;
+        }
+        Some(command.to_string())
+    }
+
+    /// Get the response topic for a message received on `topic`
+    ///
+    /// Returns `None` if `topic` is not a request topic.
+    pub fn response_topic_for(&self, topic: &str) -> Option<String> {
+        if topic == self.request_topic() {
+            return Some(self.response_topic());
+        }
+        self.topic_pairs
+            .iter()
+            .find(|(request, _)| request == topic)
+            .map(|(_, response)| response.clone())
+    }
+
+    /// Resolve a message to its response topic and request
+    ///
+    /// `{base}/get/<command>` carries the command in the topic and ignores the
+    /// payload; the other request topics parse the payload. Returns `None`
+    /// for other topics and for empty request payloads.
+    fn route(&self, topic: &str, payload: &str) -> Option<(String, Request)> {
+        if let Some(command) = self.get_command_for(topic) {
+            debug!("Received get request: {}", command);
+            let request = Request {
+                commands: vec![command],
+                atomic: false,
+            };
+            return Some((format!("{}/{}", topic, self.response_suffix), request));
+        }
+
+        let response_topic = self.response_topic_for(topic)?;
+        // Skip empty payloads
+        if payload.trim().is_empty() {
+            debug!("Skipping empty request payload");
+            return None;
+        }
+        debug!("Received request: {}", payload);
+        Some((response_topic, self.parse_request(payload)))
     }
 
     /// Check if a message is a request
+    #[allow(dead_code)]
     pub fn is_request(&self, topic: &str) -> bool {
-        topic == self.request_topic()
+        self.response_topic_for(topic).is_some()
     }
 
     /// Parse commands from request payload
@@ -49,74 +265,557 @@ impl Subscriber {
     /// - Multiple commands: "getTempWWObenIst,getTempWWsoll"
     /// - Write command: "setTempWWsoll 50"
     /// - Mixed: "set1xWW 2,setTempWWsoll 50,getTempA"
-    pub fn parse_commands(payload: &str) -> Vec<String> {
-        payload
-            .split(',')
+    /// - JSON array: `["getTempA","setTempWWsoll 50"]`
+    ///
+    /// Plain-text payloads are split on the configured separator (default
+    /// comma). A payload starting with `[` is parsed as a JSON array of
+    /// strings, which allows separators inside arguments; an invalid array
+    /// yields no commands.
+    pub fn parse_commands(&self, payload: &str) -> Vec<String> {
+        let payload = payload.trim();
+        let commands: Vec<String> = if payload.starts_with('[') {
+            serde_json::from_str(payload).unwrap_or_else(|e| {
+                warn!("Invalid JSON array request: {}", e);
+                Vec::new()
+            })
+        } else {
+            payload
+                .split(self.separator.as_str())
+                .map(str::to_string)
+                .collect()
+        };
+        commands
+            .into_iter()
             .map(|s| s.trim().to_string())
             .filter(|s| !s.is_empty())
             .collect()
     }
+
+    /// Parse a request payload
+    ///
+    /// A payload starting with `{` is a request object
+    /// `{"commands":["setBetriebsart 1","setTempRaumNorSoll 21"],"atomic":true}`;
+    /// an invalid object yields no commands. Any other payload is parsed as
+    /// a plain command list (see [`Self::parse_commands`]).
+    pub fn parse_request(&self, payload: &str) -> Request {
+        let payload = payload.trim();
+        if !payload.starts_with('{') {
+            return Request {
+                commands: self.parse_commands(payload),
+                atomic: false,
+            };
+        }
+        let mut request: Request = serde_json::from_str(payload).unwrap_or_else(|e| {
+            warn!("Invalid JSON request object: {}", e);
+            Request::default()
+        });
+        request.commands = request
+            .commands
+            .into_iter()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+        request
+    }
+
+    /// Check a command's arguments against its configured range
+    ///
+    /// Commands without a configured range are always accepted. For ranged
+    /// commands, every argument must be numeric and within the range, and at
+    /// least one argument must be given.
+    pub fn validate_arguments(&self, command: &str) -> Result<(), String> {
+        let mut parts = command.split_whitespace();
+        let Some(range) = parts.next().and_then(|name| self.set_ranges.get(name)) else {
+            return Ok(());
+        };
+
+        let args: Vec<&str> = parts.collect();
+        if args.is_empty() {
+            return Err(format!(
+                "ERR: missing argument (allowed {}..{})",
+                range.min, range.max
+            ));
+        }
+        for arg in args {
+            match arg.parse::<f64>() {
+                Ok(value) if range.contains(value) => {}
+                Ok(_) => {
+                    return Err(format!(
+                        "ERR: argument {} out of range {}..{}",
+                        arg, range.min, range.max
+                    ))
+                }
+                Err(_) => return Err(format!("ERR: argument {} is not numeric", arg)),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Build the JSON response for a request
+///
+/// Successful results are keyed by command (or its alias) in request order;
+/// failed commands are listed under an `errors` object (omitted when nothing
+/// failed): `{"getTempA":21.5,"errors":{"badCmd":"ERR: command unknown"}}`.
+/// Commands without a value are `null` unless `options` omits them.
+fn build_response(
+    results: &[CommandResult],
+    errors: &[(String, String)],
+    aliases: &HashMap<String, String>,
+    options: JsonOptions,
+) -> serde_json::Value {
+    build_json_object_with_errors(
+        results,
+        errors,
+        |command| {
+            aliases
+                .get(command)
+                .map_or(command, String::as_str)
+                .to_string()
+        },
+        options,
+    )
 }
 
 /// Run the subscriber task
 ///
 /// Listens for incoming MQTT messages, executes commands on vcontrold,
-/// and publishes responses.
+/// and publishes responses. Returns once `cancel` is triggered; a request in
+/// progress is answered first.
 pub async fn run_subscriber(
     subscriber: Subscriber,
     mqtt_client: Arc<MqttClient>,
     vcontrold: Arc<VcontroldClient>,
     mut message_rx: mpsc::Receiver<IncomingMessage>,
+    cancel: CancellationToken,
 ) {
-    let request_topic = subscriber.request_topic();
-    let response_topic = subscriber.response_topic();
+    info!(
+        "Subscriber ready, listening on {}",
+        subscriber.request_topics().join(", ")
+    );
 
-    info!("Subscriber ready, listening on {}", request_topic);
-
-    while let Some(msg) = message_rx.recv().await {
-        // Only process messages on the request topic
-        if !subscriber.is_request(&msg.topic) {
+    loop {
+        let msg = tokio::select! {
+            msg = message_rx.recv() => match msg {
+                Some(msg) => msg,
+                None => break,
+            },
+            _ = cancel.cancelled() => {
+                debug!("Subscriber stopped");
+                return;
+            }
+        };
+        // Only process messages on a request topic, replying on its paired response topic
+        let Some((response_topic, request)) = subscriber.route(&msg.topic, &msg.payload) else {
             continue;
-        }
-
-        // Skip empty payloads
-        if msg.payload.trim().is_empty() {
-            debug!("Skipping empty request payload");
-            continue;
-        }
-
-        debug!("Received request: {}", msg.payload);
+        };
 
         // Parse commands
-        let commands = Subscriber::parse_commands(&msg.payload);
+        let Request { commands, atomic } = request;
         if commands.is_empty() {
             warn!("No valid commands in request");
             continue;
         }
 
-        // Execute commands
-        let results = vcontrold.execute_batch(&commands).await;
+        // Reject commands with out-of-range arguments before they reach vcontrold
+        let mut errors = Vec::new();
+        let commands: Vec<String> = commands
+            .into_iter()
+            .filter(|cmd| match subscriber.validate_arguments(cmd) {
+                Ok(()) => true,
+                Err(reason) => {
+                    warn!("Rejecting {}: {}", cmd, reason);
+                    errors.push((cmd.clone(), reason));
+                    false
+                }
+            })
+            .collect();
+
+        // Reads still in the cache are not sent to vcontrold; atomic
+        // sequences and requests containing a write always run in full
+        let writes = commands.iter().any(|cmd| is_write_command(cmd));
+        let cache = subscriber.cache.as_ref().filter(|_| !atomic && !writes);
+        let cached: Vec<Option<CommandResult>> = commands
+            .iter()
+            .map(|cmd| cache.and_then(|cache| cache.get(cmd)))
+            .collect();
+        let to_execute: Vec<String> = commands
+            .iter()
+            .zip(&cached)
+            .filter(|(_, hit)| hit.is_none())
+            .map(|(cmd, _)| cmd.clone())
+            .collect();
+        if to_execute.len() < commands.len() {
+            debug!(
+                "Answering {} of {} command(s) from the cache",
+                commands.len() - to_execute.len(),
+                commands.len()
+            );
+        }
 
-        // Build response
-        let successful_results: Vec<_> = results
+        // Execute commands; an atomic sequence containing a rejected command
+        // is not started at all
+        let mut timed_out = false;
+        let results = if atomic && !errors.is_empty() {
+            to_execute
+                .iter()
+                .map(|_| Err(VcontroldError::NotStarted))
+                .collect()
+        } else if atomic {
+            vcontrold
+                .execute_atomic(&to_execute, subscriber.timeout)
+                .await
+        } else if to_execute.is_empty() {
+            Vec::new()
+        } else {
+            // Results are collected as commands finish, so a request cut
+            // short by the deadline still answers what completed
+            let mut results = Vec::with_capacity(to_execute.len());
+            let execute = async {
+                vcontrold
+                    .execute_batch_into(&to_execute, subscriber.timeout, &mut results)
+                    .await;
+                vcontrold
+                    .retry_connection_failures_in_place(
+                        &to_execute,
+                        &mut results,
+                        subscriber.retries,
+                        subscriber.timeout,
+                    )
+                    .await;
+            };
+            match subscriber.deadline {
+                Some(deadline) => {
+                    if timeout(deadline, execute).await.is_err() {
+                        warn!(
+                            "Request deadline of {:?} exceeded after {} of {} command(s)",
+                            deadline,
+                            results.len(),
+                            to_execute.len()
+                        );
+                        timed_out = true;
+                    }
+                }
+                None => execute.await,
+            }
+            results.resize_with(to_execute.len(), || Err(VcontroldError::DeadlineExceeded));
+            results
+        };
+
+        if let Some(cache) = subscriber.cache.as_ref() {
+            if writes {
+                cache.clear();
+            } else {
+                results
+                    .iter()
+                    .flatten()
+                    .for_each(|result| cache.insert(result));
+            }
+        }
+        let mut executed = results.into_iter();
+        let results: Vec<Result<CommandResult, VcontroldError>> = cached
             .into_iter()
-            .filter_map(|r| r.ok())
+            .map(|hit| match hit {
+                Some(result) => Ok(result),
+                None => executed.next().unwrap_or(Err(VcontroldError::Skipped)),
+            })
             .collect();
 
+        // Split into values and per-command errors
+        let mut successful_results = Vec::new();
+        for (command, result) in commands.iter().zip(results) {
+            match result {
+                Ok(cmd_result) => match &cmd_result.error {
+                    Some(e) => errors.push((command.clone(), e.clone())),
+                    None => successful_results.push(cmd_result),
+                },
+                Err(e) => errors.push((command.clone(), e.to_string())),
+            }
+        }
+
         if successful_results.is_empty() {
             warn!("All commands failed");
-            continue;
         }
 
-        let json_response = build_json_response(&successful_results);
+        let mut response = build_response(
+            &successful_results,
+            &errors,
+            &subscriber.aliases,
+            subscriber.json_options,
+        );
+        if timed_out {
+            response[TIMEOUT_KEY] = true.into();
+        }
+        let json_response = response.to_string();
         debug!("Sending response: {}", json_response);
 
         // Publish response (not retained: this is a point-in-time response
         // to a specific request, not a persistent state value)
-        if let Err(e) = mqtt_client.publish(&response_topic, &json_response).await {
+        if let Err(e) = mqtt_client
+            .publish(
+                &response_topic,
+                &json_response,
+                mqtt_client.response_qos(),
+                None,
+            )
+            .await
+        {
             error!("Failed to publish response: {}", e);
         }
     }
 
     warn!("Subscriber message channel closed");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subscriber_with_range() -> Subscriber {
+        let ranges = HashMap::from([(
+            "setTempWWsoll".to_string(),
+            ArgRange {
+                min: 10.0,
+                max: 60.0,
+            },
+        )]);
+        Subscriber::new("heating").with_set_ranges(ranges)
+    }
+
+    #[test]
+    fn test_get_command_for() {
+        let subscriber = Subscriber::new("heating").with_get_topics(true);
+        let get = |topic: &str| subscriber.get_command_for(topic);
+        assert_eq!(get("heating/get/getTempA").as_deref(), Some("getTempA"));
+        assert_eq!(get("heating/get/"), None);
+        assert_eq!(get("heating/get/getTempA/response"), None);
+        assert_eq!(subscriber.get_command_for("heating/getTempA"), None);
+        assert_eq!(subscriber.get_command_for("heatingx/get/getTempA"), None);
+        assert_eq!(subscriber.get_command_for("heating/request"), None);
+        assert_eq!(
+            subscriber.request_topics(),
+            vec!["heating/request", "heating/get/+"]
+        );
+
+        let subscriber = Subscriber::new("heating");
+        assert_eq!(subscriber.get_command_for("heating/get/getTempA"), None);
+        assert_eq!(subscriber.request_topics(), vec!["heating/request"]);
+    }
+
+    #[test]
+    fn test_route_get_and_payload_requests() {
+        let subscriber = Subscriber::new("heating").with_get_topics(true);
+
+        let (response_topic, request) = subscriber.route("heating/get/getTempA", "").unwrap();
+        assert_eq!(response_topic, "heating/get/getTempA/response");
+        assert_eq!(request.commands, vec!["getTempA"]);
+
+        let (response_topic, request) = subscriber
+            .route("heating/request", "getTempA,getTempB")
+            .unwrap();
+        assert_eq!(response_topic, "heating/response");
+        assert_eq!(request.commands, vec!["getTempA", "getTempB"]);
+
+        assert!(subscriber.route("heating/request", " ").is_none());
+        assert!(subscriber.route("heating/response", "x").is_none());
+    }
+
+    #[test]
+    fn test_custom_suffixes() {
+        let subscriber = Subscriber::new("heating")
+            .with_suffixes("cmd", "reply")
+            .with_get_topics(true);
+        assert_eq!(
+            subscriber.request_topics(),
+            vec!["heating/cmd", "heating/get/+"]
+        );
+
+        let (response_topic, _) = subscriber.route("heating/cmd", "getTempA").unwrap();
+        assert_eq!(response_topic, "heating/reply");
+        let (response_topic, _) = subscriber.route("heating/get/getTempA", "").unwrap();
+        assert_eq!(response_topic, "heating/get/getTempA/reply");
+        assert!(subscriber.route("heating/request", "getTempA").is_none());
+    }
+
+    #[test]
+    fn test_build_response_with_errors() {
+        let results = vec![CommandResult::new(
+            "getTempA",
+            crate::vcontrold::Value::Number(21.5),
+        )];
+        let errors = vec![("badCmd".to_string(), "ERR: command unknown".to_string())];
+
+        let response: serde_json::Value = serde_json::from_str(
+            &build_response(&results, &errors, &HashMap::new(), JsonOptions::default()).to_string(),
+        )
+        .unwrap();
+        assert_eq!(response["getTempA"], 21.5);
+        assert_eq!(response["errors"]["badCmd"], "ERR: command unknown");
+    }
+
+    #[test]
+    fn test_build_response_all_failed_has_only_errors() {
+        let errors = vec![("badCmd".to_string(), "ERR: command unknown".to_string())];
+        assert_eq!(
+            build_response(&[], &errors, &HashMap::new(), JsonOptions::default()).to_string(),
+            r#"{"errors":{"badCmd":"ERR: command unknown"}}"#
+        );
+    }
+
+    #[test]
+    fn test_build_response_without_errors_omits_errors_key() {
+        assert_eq!(
+            build_response(&[], &[], &HashMap::new(), JsonOptions::default()).to_string(),
+            "{}"
+        );
+    }
+
+    #[test]
+    fn test_build_response_null_values() {
+        let results = vec![CommandResult::new(
+            "getEmpty",
+            crate::vcontrold::Value::None,
+        )];
+        assert_eq!(
+            build_response(&results, &[], &HashMap::new(), JsonOptions::default()).to_string(),
+            r#"{"getEmpty":null}"#
+        );
+        let omit_nulls = JsonOptions {
+            omit_nulls: true,
+            ..JsonOptions::default()
+        };
+        assert_eq!(
+            build_response(&results, &[], &HashMap::new(), omit_nulls).to_string(),
+            "{}"
+        );
+    }
+
+    #[test]
+    fn test_build_response_uses_aliases() {
+        let results = vec![CommandResult::new(
+            "getTempWWObenIst",
+            crate::vcontrold::Value::Number(48.1),
+        )];
+        let errors = vec![("getTempA".to_string(), "ERR: timeout".to_string())];
+        let aliases = HashMap::from([
+            ("getTempWWObenIst".to_string(), "warmwater_top".to_string()),
+            ("getTempA".to_string(), "outside".to_string()),
+        ]);
+        let response: serde_json::Value = serde_json::from_str(
+            &build_response(&results, &errors, &aliases, JsonOptions::default()).to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            response,
+            serde_json::json!({"warmwater_top":48.1,"errors":{"outside":"ERR: timeout"}})
+        );
+    }
+
+    #[test]
+    fn test_response_topic_routing() {
+        let sub = Subscriber::new("heating").with_topic_pairs(vec![
+            (
+                "home/heat/zone1/request".to_string(),
+                "home/heat/zone1/response".to_string(),
+            ),
+            (
+                "home/heat/zone2/request".to_string(),
+                "home/heat/zone2/response".to_string(),
+            ),
+        ]);
+
+        assert_eq!(
+            sub.request_topics(),
+            vec![
+                "heating/request",
+                "home/heat/zone1/request",
+                "home/heat/zone2/request"
+            ]
+        );
+        assert_eq!(
+            sub.response_topic_for("heating/request").as_deref(),
+            Some("heating/response")
+        );
+        assert_eq!(
+            sub.response_topic_for("home/heat/zone2/request").as_deref(),
+            Some("home/heat/zone2/response")
+        );
+        assert_eq!(sub.response_topic_for("home/heat/zone1/response"), None);
+        assert!(!sub.is_request("heating/command/getTempA"));
+    }
+
+    #[test]
+    fn test_parse_commands_comma_separated() {
+        let subscriber = Subscriber::new("heating");
+        assert_eq!(
+            subscriber.parse_commands(" set1xWW 2, setTempWWsoll 50,,getTempA "),
+            vec!["set1xWW 2", "setTempWWsoll 50", "getTempA"]
+        );
+    }
+
+    #[test]
+    fn test_parse_commands_custom_separator() {
+        let subscriber = Subscriber::new("heating").with_separator(";");
+        assert_eq!(
+            subscriber.parse_commands("setTimer 06:00,22:00;getTempA"),
+            vec!["setTimer 06:00,22:00", "getTempA"]
+        );
+    }
+
+    #[test]
+    fn test_parse_commands_json_array() {
+        let subscriber = Subscriber::new("heating");
+        assert_eq!(
+            subscriber.parse_commands(r#"["getTempA", "setTimer 06:00,22:00", ""]"#),
+            vec!["getTempA", "setTimer 06:00,22:00"]
+        );
+        assert!(subscriber.parse_commands(r#"["getTempA""#).is_empty());
+    }
+
+    #[test]
+    fn test_parse_request_object() {
+        let subscriber = Subscriber::new("heating");
+        let payload = r#"{"commands":["setBetriebsart 1", " getTempA "],"atomic":true}"#;
+        assert_eq!(
+            subscriber.parse_request(payload),
+            Request {
+                commands: vec!["setBetriebsart 1".into(), "getTempA".into()],
+                atomic: true,
+            }
+        );
+        let request = subscriber.parse_request(r#"{"commands":["getTempA"]}"#);
+        assert!(!request.atomic);
+        assert_eq!(
+            subscriber.parse_request("getTempA,getTempB"),
+            Request {
+                commands: vec!["getTempA".into(), "getTempB".into()],
+                atomic: false,
+            }
+        );
+        let request = subscriber.parse_request(r#"{"atomic":true}"#);
+        assert!(request.commands.is_empty());
+    }
+
+    #[test]
+    fn test_validate_arguments_accepts_in_range() {
+        let sub = subscriber_with_range();
+        assert!(sub.validate_arguments("setTempWWsoll 50").is_ok());
+        assert!(sub.validate_arguments("setTempWWsoll 10").is_ok());
+        assert!(sub.validate_arguments("setTempWWsoll 60").is_ok());
+    }
+
+    #[test]
+    fn test_validate_arguments_rejects_out_of_range_and_non_numeric() {
+        let sub = subscriber_with_range();
+        assert!(sub.validate_arguments("setTempWWsoll 90").is_err());
+        assert!(sub.validate_arguments("setTempWWsoll abc").is_err());
+        assert!(sub.validate_arguments("setTempWWsoll").is_err());
+    }
+
+    #[test]
+    fn test_validate_arguments_ignores_unconfigured_commands() {
+        let sub = subscriber_with_range();
+        assert!(sub.validate_arguments("set1xWW 2").is_ok());
+        assert!(sub.validate_arguments("getTempA").is_ok());
+    }
+}
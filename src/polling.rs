@@ -2,28 +2,48 @@
 //!
 //! Handles command batching and periodic execution.
 
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{BuildHasher, Hasher};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::time::interval;
+use std::time::{Duration, SystemTime};
+use tokio::sync::mpsc;
+use tokio::time::{interval, Instant};
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, warn};
 
 use crate::config::Config;
-use crate::mqtt::{MqttClient, Publisher};
-use crate::vcontrold::VcontroldClient;
+use crate::mqtt::{CommandToggles, MqttClient, Publisher};
+use crate::sink::{OutputSink, Sinks};
+use crate::state;
+use crate::vcontrold::{CommandResult, ErrorKind, Value, VcontroldClient};
 
-/// Batch commands respecting the max length limit
+/// Minimum time between the start of a cycle and a triggered cycle
+const POLL_TRIGGER_DEBOUNCE: Duration = Duration::from_secs(10);
+
+/// How often the startup cycle checks whether MQTT is connected
+const MQTT_WAIT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Batch commands respecting the max length and (optional) count limits
 ///
-/// ```
+/// ```text
 /// batch = ""
 /// for each command in COMMANDS:
-///     if length(batch + "," + command) > MAX_LENGTH:
+///     if length(batch + "," + command) > MAX_LENGTH
+///             or count(batch) == MAX_BATCH_COUNT:
 ///         execute_batch(batch)
 ///         batch = command
 ///     else:
 ///         batch = batch + "," + command
 /// execute_batch(batch)
 /// ```
-pub fn batch_commands(commands: &[String], max_length: usize) -> Vec<Vec<String>> {
+pub fn batch_commands(
+    commands: &[String],
+    max_length: usize,
+    max_count: Option<usize>,
+) -> Vec<Vec<String>> {
     let mut batches: Vec<Vec<String>> = Vec::new();
     let mut current_batch: Vec<String> = Vec::new();
     let mut current_length = 0;
@@ -32,7 +52,9 @@ pub fn batch_commands(commands: &[String], max_length: usize) -> Vec<Vec<String>
         let cmd_len = cmd.len();
         let separator_len = if current_batch.is_empty() { 0 } else { 1 }; // comma
 
-        if current_length + separator_len + cmd_len > max_length && !current_batch.is_empty() {
+        let too_long = current_length + separator_len + cmd_len > max_length;
+        let too_many = max_count.is_some_and(|max| current_batch.len() >= max);
+        if (too_long || too_many) && !current_batch.is_empty() {
             // Current batch is full, start a new one
             batches.push(std::mem::take(&mut current_batch));
             current_length = 0;
@@ -54,31 +76,436 @@ pub fn batch_commands(commands: &[String], max_length: usize) -> Vec<Vec<String>
     batches
 }
 
+/// Plan the batches of a polling cycle
+///
+/// With `ordered` (`ORDERED=true`) every command is its own batch, so each
+/// command is read, retried and published before the next one is sent,
+/// strictly in `COMMANDS` order.
+pub fn plan_batches(
+    commands: &[String],
+    max_length: usize,
+    max_count: Option<usize>,
+    ordered: bool,
+) -> Vec<Vec<String>> {
+    if ordered {
+        commands
+            .iter()
+            .map(|command| vec![command.clone()])
+            .collect()
+    } else {
+        batch_commands(commands, max_length, max_count)
+    }
+}
+
+/// Per-command success/failure counters, published on the stats topic
+#[derive(Debug, Default)]
+struct CommandStats {
+    /// command -> (total successes, consecutive failures), sorted by command
+    counts: BTreeMap<String, (u64, u64)>,
+}
+
+impl CommandStats {
+    /// Record a successful read (resets the consecutive failure count)
+    fn record_success(&mut self, command: &str) {
+        let entry = self.counts.entry(command.to_string()).or_default();
+        entry.0 += 1;
+        entry.1 = 0;
+    }
+
+    /// Record a failed read
+    fn record_failure(&mut self, command: &str) {
+        self.counts.entry(command.to_string()).or_default().1 += 1;
+    }
+
+    /// Build the stats payload
+    ///
+    /// Format: {"command":{"successes":10,"consecutive_failures":0}}
+    fn to_json(&self) -> String {
+        let map: serde_json::Map<String, serde_json::Value> = self
+            .counts
+            .iter()
+            .map(|(command, (successes, failures))| {
+                (
+                    command.clone(),
+                    serde_json::json!({
+                        "successes": successes,
+                        "consecutive_failures": failures,
+                    }),
+                )
+            })
+            .collect();
+        serde_json::Value::Object(map).to_string()
+    }
+}
+
+/// Running min/max/sum of one command's values within a window
+#[derive(Debug, Clone, Copy)]
+struct Aggregate {
+    min: f64,
+    max: f64,
+    sum: f64,
+    count: u64,
+    last: f64,
+}
+
+impl Aggregate {
+    fn new(value: f64) -> Self {
+        Self {
+            min: value,
+            max: value,
+            sum: value,
+            count: 1,
+            last: value,
+        }
+    }
+
+    fn add(&mut self, value: f64) {
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value;
+        self.count += 1;
+        self.last = value;
+    }
+
+    /// Format: {"min":20.5,"max":22.0,"avg":21.2,"last":21.0,"count":5}
+    fn to_json(self) -> String {
+        serde_json::json!({
+            "min": self.min,
+            "max": self.max,
+            "avg": self.sum / self.count as f64,
+            "last": self.last,
+            "count": self.count,
+        })
+        .to_string()
+    }
+}
+
+/// Collects numeric values per command over AGG_WINDOW
+#[derive(Debug)]
+struct Aggregator {
+    window: Duration,
+    /// Commands to aggregate (all when empty)
+    commands: HashSet<String>,
+    window_start: Instant,
+    values: HashMap<String, Aggregate>,
+}
+
+impl Aggregator {
+    fn new(window: Duration, commands: HashSet<String>, now: Instant) -> Self {
+        Self {
+            window,
+            commands,
+            window_start: now,
+            values: HashMap::new(),
+        }
+    }
+
+    /// Whether a result is aggregated: numeric and of a selected command
+    fn includes(&self, result: &CommandResult) -> bool {
+        matches!(result.value, Value::Number(_))
+            && (self.commands.is_empty() || self.commands.contains(&result.command))
+    }
+
+    /// Record a successful result if its command is aggregated and numeric
+    fn record(&mut self, result: &CommandResult) {
+        let Value::Number(value) = result.value else {
+            return;
+        };
+        if !self.includes(result) {
+            return;
+        }
+        self.values
+            .entry(result.command.clone())
+            .and_modify(|agg| agg.add(value))
+            .or_insert_with(|| Aggregate::new(value));
+    }
+
+    /// Once the window has elapsed, return its aggregates and start a new window
+    fn take_if_elapsed(&mut self, now: Instant) -> Option<Vec<(String, Aggregate)>> {
+        if now.duration_since(self.window_start) < self.window {
+            return None;
+        }
+        self.window_start = now;
+        let mut values: Vec<_> = self.values.drain().collect();
+        values.sort_by(|a, b| a.0.cmp(&b.0));
+        Some(values)
+    }
+}
+
+/// Progress marker of the polling loop, watched by [`run_stall_watchdog`]
+///
+/// Stores the time of the last progress as milliseconds since creation on the
+/// monotonic clock, so wall clock jumps (NTP at boot) cannot trip it.
+#[derive(Debug)]
+pub struct Heartbeat {
+    start: Instant,
+    last_ms: AtomicU64,
+}
+
+impl Heartbeat {
+    /// Not started until the first [`beat`](Self::beat)
+    const NOT_STARTED: u64 = u64::MAX;
+
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            last_ms: AtomicU64::new(Self::NOT_STARTED),
+        }
+    }
+
+    /// Record progress
+    pub fn beat(&self) {
+        let now_ms = self.start.elapsed().as_millis() as u64;
+        self.last_ms.store(now_ms, Ordering::Relaxed);
+    }
+
+    /// Time since the last progress, `None` before the first beat
+    pub fn since_last(&self) -> Option<Duration> {
+        let last_ms = self.last_ms.load(Ordering::Relaxed);
+        if last_ms == Self::NOT_STARTED {
+            return None;
+        }
+        Some(
+            self.start
+                .elapsed()
+                .saturating_sub(Duration::from_millis(last_ms)),
+        )
+    }
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Watch the polling loop's heartbeat (`STALL_MULTIPLIER`)
+///
+/// Checks every `check_interval` and returns once the loop has made no
+/// progress for `stall_timeout`, so the caller can exit; the waits for MQTT
+/// before the first cycle do not count. Also returns once `cancel` is
+/// triggered.
+pub async fn run_stall_watchdog(
+    heartbeat: Arc<Heartbeat>,
+    stall_timeout: Duration,
+    check_interval: Duration,
+    cancel: CancellationToken,
+) {
+    let mut check = interval(check_interval);
+    loop {
+        tokio::select! {
+            _ = check.tick() => {}
+            _ = cancel.cancelled() => {
+                debug!("Stall watchdog stopped");
+                return;
+            }
+        }
+        if let Some(idle) = heartbeat.since_last().filter(|idle| *idle >= stall_timeout) {
+            error!(
+                "Polling loop made no progress for {} seconds, giving up",
+                idle.as_secs()
+            );
+            return;
+        }
+    }
+}
+
+/// Counts consecutive polling cycles without any successful read
+#[derive(Debug)]
+struct Watchdog {
+    threshold: u32,
+    failed_cycles: u32,
+}
+
+impl Watchdog {
+    fn new(threshold: u32) -> Self {
+        Self {
+            threshold,
+            failed_cycles: 0,
+        }
+    }
+
+    /// Record a finished cycle; returns true when the watchdog trips
+    ///
+    /// The count restarts after a trip so a restart gets a fresh window.
+    fn record_cycle(&mut self, successes: usize) -> bool {
+        if successes > 0 {
+            self.failed_cycles = 0;
+            return false;
+        }
+        self.failed_cycles += 1;
+        if self.failed_cycles < self.threshold {
+            return false;
+        }
+        self.failed_cycles = 0;
+        true
+    }
+}
+
+/// Reachability of vcontrold as published on `{base}/status/vcontrold`
+#[derive(Debug, Default)]
+struct LinkStatus {
+    /// `None` until the first cycle that ran commands
+    connected: Option<bool>,
+}
+
+impl LinkStatus {
+    /// Record a cycle's first connection error (`None` if it had none)
+    ///
+    /// Returns the payload to publish when reachability changed:
+    /// `{"connected":false,"error":"connection lost"}` or `{"connected":true}`.
+    fn update(&mut self, error: Option<&str>) -> Option<String> {
+        let connected = error.is_none();
+        if self.connected == Some(connected) {
+            return None;
+        }
+        self.connected = Some(connected);
+        let payload = match error {
+            Some(e) => serde_json::json!({"connected": false, "error": e}),
+            None => serde_json::json!({"connected": true}),
+        };
+        Some(payload.to_string())
+    }
+}
+
+/// Random delay in `0..=jitter` spreading cycles of several instances apart
+fn jitter_delay(jitter: Duration) -> Duration {
+    if jitter.is_zero() {
+        return Duration::ZERO;
+    }
+    // RandomState is randomly seeded, which is plenty for spreading publishes
+    let random = RandomState::new().build_hasher().finish();
+    jitter.mul_f64(random as f64 / u64::MAX as f64)
+}
+
+/// Whether a triggered cycle would start too soon after the previous cycle
+fn is_debounced(last_cycle: Option<Instant>, now: Instant) -> bool {
+    last_cycle.is_some_and(|last| now.duration_since(last) < POLL_TRIGGER_DEBOUNCE)
+}
+
+/// Wait until the MQTT connection is up
+async fn wait_for_mqtt(mqtt_connected: &AtomicBool) {
+    while !mqtt_connected.load(Ordering::Relaxed) {
+        tokio::time::sleep(MQTT_WAIT_INTERVAL).await;
+    }
+}
+
+/// Wait for the next poll trigger; never resolves without a trigger channel
+async fn next_trigger(poll_trigger: &mut Option<mpsc::Receiver<()>>) -> Option<()> {
+    match poll_trigger {
+        Some(rx) => rx.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Publish the last values saved in `STATE_FILE` (`REPUBLISH_ON_START`) of
+/// the commands that are still polled
+async fn republish_state(publisher: &Publisher<'_>, path: &Path, commands: &[String]) {
+    let mut values = state::load(path);
+    values.retain(|command, _| commands.contains(command));
+    if values.is_empty() {
+        info!("No saved values to republish");
+        return;
+    }
+    info!("Republishing {} saved values", values.len());
+    for (command, stored) in &values {
+        let Some(result) = stored.to_result(command) else {
+            continue;
+        };
+        if let Err(e) = publisher.publish_result(&result, &stored.ts).await {
+            warn!("Failed to republish {}: {}", command, e);
+        }
+    }
+}
+
+/// Clear the retained topics of commands saved in `path` but no longer polled
+async fn clear_stale_topics(publisher: &Publisher<'_>, path: &Path, commands: &[String]) {
+    let stale: Vec<String> = state::load(path)
+        .into_keys()
+        .filter(|command| !commands.contains(command))
+        .collect();
+    if stale.is_empty() {
+        info!("No stale command topics to clear");
+        return;
+    }
+    for command in &stale {
+        info!(
+            "Clearing retained topics of {}, no longer in COMMANDS",
+            command
+        );
+        publisher.clear_retained(command).await;
+    }
+}
+
+/// Optional channels of the polling loop, all off by default
+#[derive(Default)]
+pub struct PollingControls {
+    /// Extra cycles requested via ${MQTT_TOPIC}/poll
+    pub poll_trigger: Option<mpsc::Receiver<()>>,
+    /// Commands switched on and off at runtime
+    pub command_toggles: Option<CommandToggles>,
+    /// Receives a vcontrold restart request (WATCHDOG_FAILURES)
+    pub watchdog_tx: Option<mpsc::Sender<()>>,
+    /// Updated every time the loop wakes up, for the STALL_MULTIPLIER watchdog
+    pub heartbeat: Option<Arc<Heartbeat>>,
+}
+
 /// Run the polling loop
 ///
 /// 1. Parse COMMANDS as comma-separated list
-/// 2. Batch commands into groups respecting MAX_LENGTH character limit
+/// 2. Batch commands into groups respecting MAX_LENGTH character limit and
+///    publish the topic catalog to ${MQTT_TOPIC}/topics (and, with
+///    PUBLISH_ONLINE, reset command/<name>/online to false); with
+///    CLEAR_STALE_ON_START, clear the retained topics of commands saved in
+///    STATE_FILE that are no longer configured; with REPUBLISH_ON_START,
+///    publish the values saved in STATE_FILE
 /// 3. For each batch:
 ///    - Execute commands via vcontrold client
 ///    - Publish each value to ${MQTT_TOPIC}/command/<name>
-/// 4. Sleep INTERVAL seconds
-/// 5. Repeat
+/// 4. If InfluxDB output is enabled, write all values of the cycle as one batch
+/// 5. At the end of each AGG_WINDOW, publish min/max/avg/last per command to
+///    ${MQTT_TOPIC}/command/<name>/agg
+/// 6. Every STATS_INTERVAL cycles, publish per-command counters to ${MQTT_TOPIC}/stats;
+///    after WATCHDOG_FAILURES cycles without a successful read, request a
+///    vcontrold restart
+/// 7. Sleep INTERVAL seconds plus a random delay of up to INTERVAL_JITTER, or
+///    until a message on ${MQTT_TOPIC}/poll triggers an extra cycle (ignored
+///    within 10s of the previous cycle)
+/// 8. Repeat until `cancel` is triggered; a cycle in progress is completed
+///
+/// See [`PollingControls`] for the optional channels the loop listens and
+/// reports to.
 pub async fn run_polling_loop(
     config: &Config,
     vcontrold: Arc<VcontroldClient>,
     mqtt_client: Arc<MqttClient>,
     mqtt_connected: Arc<AtomicBool>,
+    extra_sinks: Vec<Box<dyn OutputSink>>,
+    controls: PollingControls,
+    cancel: CancellationToken,
 ) {
-    if config.commands.is_empty() {
+    let PollingControls {
+        mut poll_trigger,
+        command_toggles,
+        watchdog_tx,
+        heartbeat,
+    } = controls;
+    let commands = config.polled_commands();
+    if commands.is_empty() {
         warn!("No commands configured for polling");
         return;
     }
 
     // Pre-batch commands
-    let batches = batch_commands(&config.commands, config.max_length);
+    let batches = plan_batches(
+        commands,
+        config.max_length,
+        config.max_batch_count,
+        config.ordered,
+    );
     info!(
         "Polling {} commands in {} batches every {} seconds",
-        config.commands.len(),
+        commands.len(),
         batches.len(),
         config.interval.as_secs()
     );
@@ -94,12 +521,93 @@ pub async fn run_polling_loop(
     // overwhelming the MQTT client after a stall (e.g. broker outage where
     // publishes hit the timeout and the interval falls behind).
     poll_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
-    let publisher = Publisher::new(&mqtt_client);
+    if config.poll_on_start {
+        // The first tick fires immediately; hold it back until the broker is
+        // connected so the startup cycle is not skipped
+        info!("Waiting for MQTT connection before the startup cycle");
+        tokio::select! {
+            _ = wait_for_mqtt(&mqtt_connected) => {}
+            _ = cancel.cancelled() => return,
+        }
+    } else {
+        poll_interval.reset();
+    }
+    let publisher = Publisher::new(&mqtt_client, &config.publish);
+    // MQTT first, then the optional outputs
+    let mut sinks = Sinks::default();
+    sinks.push(Box::new(Publisher::new(&mqtt_client, &config.publish)));
+    for sink in extra_sinks {
+        sinks.push(sink);
+    }
+
+    // Retained catalog of the value topics, in batch order
+    let catalog_commands: Vec<String> = batches.iter().flatten().cloned().collect();
+    publisher.publish_topic_catalog(&catalog_commands).await;
+    publisher.reset_online_flags(&catalog_commands).await;
+
+    if config.clear_stale_on_start {
+        if let Some(path) = &config.state_file {
+            tokio::select! {
+                _ = wait_for_mqtt(&mqtt_connected) => {}
+                _ = cancel.cancelled() => return,
+            }
+            clear_stale_topics(&publisher, path, &config.commands).await;
+        }
+    }
+
+    if config.republish_on_start {
+        if let Some(path) = &config.state_file {
+            tokio::select! {
+                _ = wait_for_mqtt(&mqtt_connected) => {}
+                _ = cancel.cancelled() => return,
+            }
+            republish_state(&publisher, path, &catalog_commands).await;
+        }
+    }
 
     let mut was_disconnected = false;
+    let mut stats = CommandStats::default();
+    // Commands vcontrold reported as unknown; they would fail every cycle
+    let mut unknown_commands: HashSet<String> = HashSet::new();
+    let mut cycles: u64 = 0;
+    let mut last_cycle: Option<Instant> = None;
+    let mut watchdog = Watchdog::new(config.watchdog_failures);
+    let mut link_status = LinkStatus::default();
+    // Window aggregates (AGG_WINDOW), published in addition to the raw values
+    let mut aggregator = (!config.agg_window.is_zero()).then(|| {
+        Aggregator::new(
+            config.agg_window,
+            config.agg_commands.clone(),
+            Instant::now(),
+        )
+    });
+
+    let beat = || heartbeat.iter().for_each(|heartbeat| heartbeat.beat());
+    beat();
 
     loop {
-        poll_interval.tick().await;
+        // A triggered cycle runs in addition to the interval schedule
+        let triggered = tokio::select! {
+            _ = poll_interval.tick() => false,
+            Some(()) = next_trigger(&mut poll_trigger) => true,
+            _ = cancel.cancelled() => {
+                debug!("Polling loop stopped");
+                return;
+            }
+        };
+        beat();
+        if triggered {
+            if is_debounced(last_cycle, Instant::now()) {
+                debug!("Ignoring poll trigger within debounce window");
+                continue;
+            }
+            info!("Polling triggered via MQTT");
+        } else if !config.interval_jitter.is_zero() {
+            tokio::select! {
+                _ = tokio::time::sleep(jitter_delay(config.interval_jitter)) => {}
+                _ = cancel.cancelled() => return,
+            }
+        }
 
         // Skip entire cycle when the MQTT broker is unreachable. This avoids
         // unnecessary vcontrold/Optolink traffic and prevents filling the
@@ -117,24 +625,59 @@ pub async fn run_polling_loop(
         }
 
         debug!("Starting polling cycle");
+        let cycle_start = Instant::now();
+        last_cycle = Some(cycle_start);
+        // Captured once so every value of this cycle shares one timestamp
+        let cycle_time = SystemTime::now();
+        let mut cycle_results = Vec::new();
+        let mut cycle_commands = 0;
+        let mut cycle_successes = 0;
+        // First connection-level failure of the cycle (bus down, not a bad command)
+        let mut link_error: Option<String> = None;
 
         for (batch_idx, batch) in batches.iter().enumerate() {
+            let batch: Vec<String> = batch
+                .iter()
+                .filter(|command| !unknown_commands.contains(*command))
+                .filter(|command| {
+                    command_toggles
+                        .as_ref()
+                        .is_none_or(|toggles| toggles.is_enabled(command))
+                })
+                .cloned()
+                .collect();
+            if batch.is_empty() {
+                continue;
+            }
             if config.debug {
                 debug!("Executing batch {}: {}", batch_idx + 1, batch.join(","));
             }
 
-            let results = vcontrold.execute_batch(batch).await;
+            cycle_commands += batch.len();
+            let results = vcontrold.execute_batch(&batch).await;
+            let results = vcontrold
+                .retry_transient_failures(&batch, results, config.command_retries)
+                .await;
 
             // Process results
             let mut successful_results = Vec::new();
-            for result in results {
+            for (command, result) in batch.iter().zip(results) {
                 match result {
                     Ok(cmd_result) => {
                         if cmd_result.error.is_some() {
                             warn!(
                                 "Command {} returned error: {:?}",
-                                cmd_result.command, cmd_result.error
+                                cmd_result.command,
+                                cmd_result.error_with_detail()
                             );
+                            stats.record_failure(command);
+                            if cmd_result.error_kind == Some(ErrorKind::UnknownCommand) {
+                                warn!(
+                                    "Command {} is unknown to vcontrold, no longer polling it",
+                                    command
+                                );
+                                unknown_commands.insert(command.clone());
+                            }
                         } else {
                             if config.debug {
                                 debug!(
@@ -142,20 +685,101 @@ pub async fn run_polling_loop(
                                     cmd_result.command, cmd_result.value
                                 );
                             }
+                            stats.record_success(command);
                             successful_results.push(cmd_result);
                         }
                     }
+                    Err(e) if e.is_connection_error() => {
+                        error!(
+                            "vcontrold unreachable in batch {} ({}): {}",
+                            batch_idx + 1,
+                            command,
+                            e
+                        );
+                        link_error.get_or_insert_with(|| e.to_string());
+                        stats.record_failure(command);
+                    }
                     Err(e) => {
-                        error!("Failed to execute command in batch {}: {}", batch_idx + 1, e);
+                        warn!(
+                            "Command {} failed in batch {}: {}",
+                            command,
+                            batch_idx + 1,
+                            e
+                        );
+                        stats.record_failure(command);
                     }
                 }
             }
 
-            // Publish successful results
-            publisher.publish_results(&successful_results).await;
+            // Publish successful results; with AGG_RAW=false aggregated
+            // values only go out as window summaries
+            match aggregator.as_ref().filter(|_| !config.agg_raw) {
+                Some(aggregator) => {
+                    let raw: Vec<CommandResult> = successful_results
+                        .iter()
+                        .filter(|result| !aggregator.includes(result))
+                        .cloned()
+                        .collect();
+                    sinks.publish(&raw, cycle_time).await;
+                }
+                None => sinks.publish(&successful_results, cycle_time).await,
+            }
+            cycle_successes += successful_results.len();
+            if let Some(aggregator) = aggregator.as_mut() {
+                successful_results.iter().for_each(|r| aggregator.record(r));
+            }
+            cycle_results.extend(successful_results);
+        }
+
+        sinks.publish_cycle(&cycle_results, cycle_time).await;
+
+        if let Some(values) = aggregator
+            .as_mut()
+            .and_then(|a| a.take_if_elapsed(Instant::now()))
+        {
+            for (command, aggregate) in values {
+                publisher
+                    .publish_aggregate(&command, &aggregate.to_json())
+                    .await;
+            }
+        }
+
+        if cycle_commands > 0 {
+            if let Some(payload) = link_status.update(link_error.as_deref()) {
+                publisher.publish_vcontrold_status(&payload).await;
+            }
         }
 
-        debug!("Polling cycle complete");
+        if let Some(tx) = watchdog_tx.as_ref().filter(|_| cycle_commands > 0) {
+            if watchdog.record_cycle(cycle_successes) {
+                error!(
+                    "Watchdog: {} consecutive polling cycles without a successful read, requesting vcontrold restart",
+                    config.watchdog_failures
+                );
+                let _ = tx.try_send(());
+            }
+        }
+
+        cycles += 1;
+        if config.stats_interval > 0 && cycles.is_multiple_of(config.stats_interval) {
+            publisher.publish_stats(&stats.to_json()).await;
+            publisher
+                .publish_link_stats(&vcontrold.stats().to_json())
+                .await;
+        }
+
+        // One line per cycle; the counts are also fields for structured logs
+        let cycle_errors = cycle_commands - cycle_successes;
+        let duration_ms = cycle_start.elapsed().as_millis() as u64;
+        info!(
+            ok = cycle_successes,
+            errors = cycle_errors,
+            duration_ms,
+            "cycle complete: {} ok, {} errors, {}ms",
+            cycle_successes,
+            cycle_errors,
+            duration_ms
+        );
     }
 }
 
@@ -166,7 +790,7 @@ mod tests {
     #[test]
     fn test_batch_commands_single_batch() {
         let commands: Vec<String> = vec!["cmd1".into(), "cmd2".into(), "cmd3".into()];
-        let batches = batch_commands(&commands, 100);
+        let batches = batch_commands(&commands, 100, None);
         assert_eq!(batches.len(), 1);
         assert_eq!(batches[0], commands);
     }
@@ -182,7 +806,7 @@ mod tests {
         // Max length 40: "getTempWWObenIst,getTempWWsoll" = 30 chars
         // Adding "getTempA" = 30 + 1 + 8 = 39 chars (fits)
         // Adding "getTempB" = 39 + 1 + 8 = 48 chars (doesn't fit)
-        let batches = batch_commands(&commands, 40);
+        let batches = batch_commands(&commands, 40, None);
         assert_eq!(batches.len(), 2);
         assert_eq!(
             batches[0],
@@ -194,7 +818,7 @@ mod tests {
     #[test]
     fn test_batch_commands_empty() {
         let commands: Vec<String> = vec![];
-        let batches = batch_commands(&commands, 100);
+        let batches = batch_commands(&commands, 100, None);
         assert!(batches.is_empty());
     }
 
@@ -202,11 +826,87 @@ mod tests {
     fn test_batch_commands_single_long_command() {
         // Even if a single command exceeds max_length, it should still be in its own batch
         let commands: Vec<String> = vec!["veryLongCommandName".into()];
-        let batches = batch_commands(&commands, 5);
+        let batches = batch_commands(&commands, 5, None);
         assert_eq!(batches.len(), 1);
         assert_eq!(batches[0], vec!["veryLongCommandName"]);
     }
 
+    #[test]
+    fn test_batch_commands_command_at_max_length() {
+        // A command exactly max_length long fills a batch; its neighbours move out
+        let commands: Vec<String> = vec!["getA".into(), "getTempA".into(), "getB".into()];
+        let batches = batch_commands(&commands, 8, None);
+        assert_eq!(batches, vec![vec!["getA"], vec!["getTempA"], vec!["getB"]]);
+
+        // One character over still gets a batch of its own
+        let batches = batch_commands(&commands, 7, None);
+        assert_eq!(batches, vec![vec!["getA"], vec!["getTempA"], vec!["getB"]]);
+    }
+
+    #[test]
+    fn test_batch_commands_count_limit() {
+        let commands: Vec<String> = vec![
+            "getTempA".into(),
+            "getTempB".into(),
+            "getTempC".into(),
+            "getTempD".into(),
+            "getTempE".into(),
+        ];
+        // All five fit in 512 characters; the count limit splits them
+        let batches = batch_commands(&commands, 512, Some(2));
+        assert_eq!(
+            batches,
+            vec![
+                vec!["getTempA", "getTempB"],
+                vec!["getTempC", "getTempD"],
+                vec!["getTempE"],
+            ]
+        );
+        assert_eq!(batch_commands(&commands, 512, None).len(), 1);
+
+        // Whichever limit is reached first flushes the batch: 17 characters
+        // fit two commands, before the count limit of three is reached
+        let sizes: Vec<usize> = batch_commands(&commands, 17, Some(3))
+            .iter()
+            .map(Vec::len)
+            .collect();
+        assert_eq!(sizes, vec![2, 2, 1]);
+    }
+
+    #[test]
+    fn test_plan_batches_ordered() {
+        let commands: Vec<String> = vec!["getA".into(), "getB".into(), "getC".into()];
+        assert_eq!(
+            plan_batches(&commands, 100, None, false),
+            vec![commands.clone()]
+        );
+        assert_eq!(
+            plan_batches(&commands, 100, None, true),
+            vec![vec!["getA"], vec!["getB"], vec!["getC"]]
+        );
+    }
+
+    #[test]
+    fn test_command_stats_counts_successes_and_consecutive_failures() {
+        let mut stats = CommandStats::default();
+        stats.record_success("getTempA");
+        stats.record_success("getTempA");
+        stats.record_failure("getTempA");
+        stats.record_failure("getTempB");
+        stats.record_failure("getTempB");
+
+        let json: serde_json::Value = serde_json::from_str(&stats.to_json()).unwrap();
+        assert_eq!(json["getTempA"]["successes"], 2);
+        assert_eq!(json["getTempA"]["consecutive_failures"], 1);
+        assert_eq!(json["getTempB"]["successes"], 0);
+        assert_eq!(json["getTempB"]["consecutive_failures"], 2);
+
+        stats.record_success("getTempB");
+        let json: serde_json::Value = serde_json::from_str(&stats.to_json()).unwrap();
+        assert_eq!(json["getTempB"]["successes"], 1);
+        assert_eq!(json["getTempB"]["consecutive_failures"], 0);
+    }
+
     /// Verify that the polling interval uses Skip behavior: after a long stall
     /// only one tick fires rather than a burst of all missed ticks.
     ///
@@ -243,6 +943,262 @@ mod tests {
         );
     }
 
+    /// Records the number of results of each completed cycle
+    struct CycleRecorder(Arc<std::sync::Mutex<Vec<usize>>>);
+
+    impl OutputSink for CycleRecorder {
+        fn publish_cycle<'a>(
+            &'a self,
+            results: &'a [CommandResult],
+            _: SystemTime,
+        ) -> crate::sink::SinkFuture<'a> {
+            self.0.lock().unwrap().push(results.len());
+            Box::pin(std::future::ready(()))
+        }
+    }
+
+    /// With `POLL_ON_START`, the startup cycle waits for MQTT and then runs
+    /// right away; without it, the first cycle comes one interval later.
+    #[tokio::test]
+    async fn test_poll_on_start_waits_for_mqtt() {
+        let responses = HashMap::from([("getTempA".to_string(), "21.5".to_string())]);
+        let server = crate::vcontrold::MockServer::bind("127.0.0.1:0", responses)
+            .await
+            .unwrap();
+        let port = server.local_addr().unwrap().port();
+        tokio::spawn(server.run());
+
+        let start_polling = |poll_on_start: &'static str, connected: &Arc<AtomicBool>| {
+            let config = Config::from_lookup(|name| match name {
+                "MQTT_HOST" => Some("localhost".to_string()),
+                "MQTT_TOPIC" => Some("test".to_string()),
+                "COMMANDS" => Some("getTempA".to_string()),
+                "INTERVAL" => Some("600".to_string()),
+                "POLL_ON_START" => Some(poll_on_start.to_string()),
+                _ => None,
+            })
+            .unwrap();
+            let cycles = Arc::new(std::sync::Mutex::new(Vec::new()));
+            let sink = CycleRecorder(Arc::clone(&cycles));
+            let connected = Arc::clone(connected);
+            let cancel = CancellationToken::new();
+            let handle = tokio::spawn({
+                let cancel = cancel.clone();
+                async move {
+                    let (mqtt_client, _eventloop) = MqttClient::new(&config.mqtt, "test").unwrap();
+                    run_polling_loop(
+                        &config,
+                        Arc::new(VcontroldClient::new("127.0.0.1", port)),
+                        Arc::new(mqtt_client),
+                        connected,
+                        vec![Box::new(sink)],
+                        PollingControls::default(),
+                        cancel,
+                    )
+                    .await;
+                }
+            });
+            (cycles, cancel, handle)
+        };
+
+        // No cycle while MQTT is down, then the startup cycle right away
+        let connected = Arc::new(AtomicBool::new(false));
+        let (cycles, cancel, handle) = start_polling("true", &connected);
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(cycles.lock().unwrap().is_empty());
+        connected.store(true, Ordering::Relaxed);
+        let ran = tokio::time::timeout(Duration::from_secs(5), async {
+            while cycles.lock().unwrap().is_empty() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await;
+        assert!(
+            ran.is_ok(),
+            "startup cycle did not run after MQTT connected"
+        );
+        assert_eq!(*cycles.lock().unwrap(), vec![1]);
+        cancel.cancel();
+        handle.await.unwrap();
+
+        // Without POLL_ON_START nothing runs before the first interval
+        let connected = Arc::new(AtomicBool::new(true));
+        let (cycles, cancel, handle) = start_polling("false", &connected);
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(cycles.lock().unwrap().is_empty());
+        cancel.cancel();
+        handle.await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stall_watchdog_waits_for_first_beat_then_trips() {
+        let heartbeat = Arc::new(Heartbeat::new());
+        let watchdog = tokio::spawn(run_stall_watchdog(
+            Arc::clone(&heartbeat),
+            Duration::from_secs(30),
+            Duration::from_secs(10),
+            CancellationToken::new(),
+        ));
+
+        // Not armed before the loop starts
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        assert_eq!(heartbeat.since_last(), None);
+        assert!(!watchdog.is_finished());
+
+        heartbeat.beat();
+        tokio::time::sleep(Duration::from_secs(20)).await;
+        heartbeat.beat();
+        tokio::time::sleep(Duration::from_secs(20)).await;
+        assert!(!watchdog.is_finished(), "progress keeps it quiet");
+
+        tokio::time::sleep(Duration::from_secs(20)).await;
+        assert!(watchdog.is_finished());
+    }
+
+    /// Cancelling the token stops the polling loop while it waits for the
+    /// next cycle, without aborting the task
+    #[tokio::test]
+    async fn test_polling_loop_returns_when_cancelled() {
+        let config = Config::from_lookup(|name| match name {
+            "MQTT_HOST" => Some("localhost".to_string()),
+            "MQTT_TOPIC" => Some("test".to_string()),
+            "COMMANDS" => Some("getTempA".to_string()),
+            _ => None,
+        })
+        .unwrap();
+        let (mqtt_client, _eventloop) = MqttClient::new(&config.mqtt, "test").unwrap();
+
+        let cancel = CancellationToken::new();
+        let handle = tokio::spawn({
+            let cancel = cancel.clone();
+            async move {
+                run_polling_loop(
+                    &config,
+                    Arc::new(VcontroldClient::new("127.0.0.1", 1)),
+                    Arc::new(mqtt_client),
+                    Arc::new(AtomicBool::new(false)),
+                    Vec::new(),
+                    PollingControls::default(),
+                    cancel,
+                )
+                .await;
+            }
+        });
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        cancel.cancel();
+        let result = tokio::time::timeout(Duration::from_secs(5), handle).await;
+        assert!(matches!(result, Ok(Ok(()))));
+    }
+
+    #[test]
+    fn test_poll_trigger_debounce() {
+        let now = Instant::now();
+        assert!(!is_debounced(None, now));
+        assert!(is_debounced(Some(now), now + Duration::from_secs(3)));
+        assert!(!is_debounced(Some(now), now + POLL_TRIGGER_DEBOUNCE));
+    }
+
+    #[test]
+    fn test_aggregator_summarizes_window() {
+        let start = Instant::now();
+        let window = Duration::from_secs(300);
+        let mut aggregator = Aggregator::new(window, HashSet::new(), start);
+        aggregator.record(&CommandResult::new("getTempA", Value::Number(20.0)));
+        aggregator.record(&CommandResult::new("getTempA", Value::Number(23.0)));
+        aggregator.record(&CommandResult::new("getTempA", Value::Number(21.5)));
+
+        assert!(aggregator
+            .take_if_elapsed(start + Duration::from_secs(60))
+            .is_none());
+
+        let values = aggregator.take_if_elapsed(start + window).unwrap();
+        assert_eq!(values.len(), 1);
+        let json: serde_json::Value = serde_json::from_str(&values[0].1.to_json()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"min": 20.0, "max": 23.0, "avg": 21.5, "last": 21.5, "count": 3})
+        );
+
+        // The next window starts empty
+        let values = aggregator.take_if_elapsed(start + window * 2).unwrap();
+        assert!(values.is_empty());
+    }
+
+    #[test]
+    fn test_aggregator_only_records_selected_numeric_commands() {
+        let commands = HashSet::from(["getTempA".to_string()]);
+        let mut aggregator = Aggregator::new(Duration::ZERO, commands, Instant::now());
+        aggregator.record(&CommandResult::new("getTempA", Value::Number(20.0)));
+        aggregator.record(&CommandResult::new("getTempB", Value::Number(45.0)));
+        aggregator.record(&CommandResult::new(
+            "getTempA",
+            Value::String("on".to_string()),
+        ));
+
+        assert!(aggregator.includes(&CommandResult::new("getTempA", Value::Number(1.0))));
+        assert!(!aggregator.includes(&CommandResult::new("getTempB", Value::Number(1.0))));
+
+        let values = aggregator.take_if_elapsed(Instant::now()).unwrap();
+        assert_eq!(values.len(), 1);
+        assert_eq!(values[0].0, "getTempA");
+        assert_eq!(values[0].1.count, 1);
+    }
+
+    #[test]
+    fn test_watchdog_trips_after_consecutive_empty_cycles() {
+        let mut watchdog = Watchdog::new(3);
+        assert!(!watchdog.record_cycle(0));
+        assert!(!watchdog.record_cycle(0));
+        assert!(
+            !watchdog.record_cycle(2),
+            "a successful read resets the count"
+        );
+        assert!(!watchdog.record_cycle(0));
+        assert!(!watchdog.record_cycle(0));
+        assert!(watchdog.record_cycle(0));
+        assert!(!watchdog.record_cycle(0), "count restarts after a trip");
+    }
+
+    #[test]
+    fn test_link_status_publishes_transitions_only() {
+        let mut status = LinkStatus::default();
+        assert_eq!(
+            status.update(None).as_deref(),
+            Some(r#"{"connected":true}"#)
+        );
+        assert_eq!(status.update(None), None);
+        assert_eq!(
+            status.update(Some("connection lost")).as_deref(),
+            Some(r#"{"connected":false,"error":"connection lost"}"#)
+        );
+        assert_eq!(status.update(Some("connection failed: refused")), None);
+        assert_eq!(
+            status.update(None).as_deref(),
+            Some(r#"{"connected":true}"#)
+        );
+    }
+
+    #[test]
+    fn test_connection_errors_are_told_apart_from_command_errors() {
+        use crate::error::VcontroldError;
+
+        assert!(VcontroldError::ConnectionLost.is_connection_error());
+        assert!(VcontroldError::ConnectionFailed("refused".into()).is_connection_error());
+        assert!(!VcontroldError::Command("ERR: command unknown".into()).is_connection_error());
+        assert!(!VcontroldError::Timeout.is_connection_error());
+        assert!(!VcontroldError::Skipped.is_connection_error());
+    }
+
+    #[test]
+    fn test_jitter_delay_stays_within_window() {
+        assert_eq!(jitter_delay(Duration::ZERO), Duration::ZERO);
+        let jitter = Duration::from_secs(5);
+        for _ in 0..100 {
+            assert!(jitter_delay(jitter) <= jitter);
+        }
+    }
+
     #[test]
     fn test_mqtt_connected_flag_state_transitions() {
         // Verify the AtomicBool flag behaves correctly across the
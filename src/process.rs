@@ -2,24 +2,109 @@
 //!
 //! Spawns and monitors the vcontrold process.
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::process::{Child, Command};
-use tokio::time::sleep;
-use tracing::{info, warn};
+use tokio::time::{sleep, timeout, Instant};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
 
 use crate::error::ProcessError;
 use crate::vcontrold::VcontroldClient;
 
-/// Default config file path
-const DEFAULT_CONFIG_PATH: &str = "/config/vcontrold.xml";
+/// Default readiness probe timeout (`READINESS_TIMEOUT`)
+pub const DEFAULT_READINESS_TIMEOUT: Duration = Duration::from_secs(30);
 
-/// Readiness probe timeout in seconds
-const READINESS_TIMEOUT_SECS: u64 = 30;
+/// Default interval between readiness probe attempts (`READINESS_PROBE_INTERVAL`)
+pub const DEFAULT_READINESS_PROBE_INTERVAL: Duration = Duration::from_secs(1);
 
-/// Interval between readiness probe attempts
-const READINESS_PROBE_INTERVAL: Duration = Duration::from_secs(1);
+/// Interval between checks for the serial device
+const DEVICE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Wait for the serial device to appear before starting vcontrold
+///
+/// USB adapters may enumerate after the container has started. Polls every
+/// second until `device` exists, failing after `wait_timeout`.
+pub async fn wait_for_device(device: &Path, wait_timeout: Duration) -> Result<(), ProcessError> {
+    let deadline = Instant::now() + wait_timeout;
+    let mut logged = false;
+
+    while !device.exists() {
+        if Instant::now() >= deadline {
+            return Err(ProcessError::DeviceNotFound(
+                device.display().to_string(),
+                wait_timeout.as_secs(),
+            ));
+        }
+        if !logged {
+            info!("Waiting for serial device {}...", device.display());
+            logged = true;
+        }
+        sleep(DEVICE_POLL_INTERVAL).await;
+    }
+
+    if logged {
+        info!("Serial device {} is available", device.display());
+    }
+    Ok(())
+}
+
+/// Watch the serial device while vcontrold runs (`USB_CHECK_INTERVAL`)
+///
+/// An unplugged adapter leaves vcontrold running with every command failing,
+/// which otherwise looks like generic timeouts. Checks every `interval`
+/// whether `device` still exists and keeps `present` up to date for the
+/// health endpoint. On disappearance the connection to vcontrold is dropped,
+/// so commands reconnect from scratch once the device is back. Returns once
+/// `cancel` is triggered.
+pub async fn monitor_device(
+    device: PathBuf,
+    interval: Duration,
+    present: Arc<AtomicBool>,
+    client: Arc<VcontroldClient>,
+    cancel: CancellationToken,
+) {
+    loop {
+        tokio::select! {
+            _ = sleep(interval) => {}
+            _ = cancel.cancelled() => return,
+        }
+        let exists = device.exists();
+        match (present.swap(exists, Ordering::Relaxed), exists) {
+            (true, false) => {
+                error!(
+                    "Serial device {} disappeared - Optolink adapter unplugged?",
+                    device.display()
+                );
+                client.mark_disconnected();
+            }
+            (false, true) => info!("Serial device {} is back", device.display()),
+            _ => {}
+        }
+    }
+}
+
+/// Build the vcontrold command line (extra args go before the debug flags)
+fn build_command(
+    bin: &str,
+    config_path: &Path,
+    extra_args: &[String],
+    debug_mode: bool,
+) -> Command {
+    let mut cmd = Command::new(bin);
+    cmd.arg("-n") // Don't fork into background
+        .arg("-x")
+        .arg(config_path)
+        .args(extra_args);
+
+    if debug_mode {
+        cmd.arg("--verbose").arg("--debug");
+    }
+    cmd
+}
 
 /// vcontrold process manager
 pub struct VcontroldProcess {
@@ -29,13 +114,14 @@ pub struct VcontroldProcess {
 impl VcontroldProcess {
     /// Spawn vcontrold with the given configuration
     ///
-    /// - Normal: `vcontrold -n -x /config/vcontrold.xml`
-    /// - Debug: `vcontrold -n -x /config/vcontrold.xml --verbose --debug`
-    pub async fn spawn(config_path: Option<&Path>, debug_mode: bool) -> Result<Self, ProcessError> {
-        let config_path = config_path
-            .map(|p| p.to_path_buf())
-            .unwrap_or_else(|| Path::new(DEFAULT_CONFIG_PATH).to_path_buf());
-
+    /// - Normal: `vcontrold -n -x /config/vcontrold.xml [VCONTROLD_ARGS]`
+    /// - Debug: `vcontrold -n -x /config/vcontrold.xml [VCONTROLD_ARGS] --verbose --debug`
+    pub async fn spawn(
+        bin: &str,
+        config_path: &Path,
+        extra_args: &[String],
+        debug_mode: bool,
+    ) -> Result<Self, ProcessError> {
         // Check if config file exists
         if !config_path.exists() {
             return Err(ProcessError::ConfigNotFound(
@@ -43,14 +129,7 @@ impl VcontroldProcess {
             ));
         }
 
-        let mut cmd = Command::new("vcontrold");
-        cmd.arg("-n") // Don't fork into background
-            .arg("-x")
-            .arg(&config_path);
-
-        if debug_mode {
-            cmd.arg("--verbose").arg("--debug");
-        }
+        let mut cmd = build_command(bin, config_path, extra_args, debug_mode);
 
         // Capture stdout/stderr
         cmd.stdout(Stdio::inherit())
@@ -58,7 +137,8 @@ impl VcontroldProcess {
             .stdin(Stdio::null());
 
         info!(
-            "Starting vcontrold with config: {}{}",
+            "Starting {} with config: {}{}",
+            bin,
             config_path.display(),
             if debug_mode { " (debug mode)" } else { "" }
         );
@@ -72,14 +152,24 @@ impl VcontroldProcess {
         Ok(Self { child })
     }
 
-    /// Wait for vcontrold to be ready (TCP port responding)
-    pub async fn wait_ready(&self) -> Result<(), ProcessError> {
-        let client = VcontroldClient::localhost();
+    /// Wait for vcontrold to be ready (TCP port or socket responding)
+    ///
+    /// Probes every `probe_interval`, failing after `readiness_timeout`.
+    pub async fn wait_ready(
+        &self,
+        client: &VcontroldClient,
+        readiness_timeout: Duration,
+        probe_interval: Duration,
+    ) -> Result<(), ProcessError> {
         let start = std::time::Instant::now();
 
-        info!("Waiting for vcontrold to be ready...");
+        info!(
+            "Waiting for vcontrold to be ready (up to {} seconds, probing every {} ms)...",
+            readiness_timeout.as_secs(),
+            probe_interval.as_millis()
+        );
 
-        while start.elapsed().as_secs() < READINESS_TIMEOUT_SECS {
+        while start.elapsed() < readiness_timeout {
             if client.is_ready().await {
                 info!(
                     "vcontrold is ready after {} seconds",
@@ -87,10 +177,10 @@ impl VcontroldProcess {
                 );
                 return Ok(());
             }
-            sleep(READINESS_PROBE_INTERVAL).await;
+            sleep(probe_interval).await;
         }
 
-        Err(ProcessError::ReadinessTimeout(READINESS_TIMEOUT_SECS))
+        Err(ProcessError::ReadinessTimeout(readiness_timeout.as_secs()))
     }
 
     /// Check if the process is still running
@@ -114,7 +204,31 @@ impl VcontroldProcess {
         Ok(status.code())
     }
 
-    /// Kill the process
+    /// Stop the process: SIGTERM, then SIGKILL if it is still running after `grace`
+    ///
+    /// The grace period lets vcontrold release the serial device cleanly.
+    /// Without SIGTERM (non-Unix targets) the process is killed right away.
+    pub async fn stop(&mut self, grace: Duration) {
+        #[cfg(unix)]
+        if let Some(pid) = self.child.id() {
+            // SAFETY: kill(2) has no memory safety requirements; the pid is
+            // our own child, which has not been reaped yet (`id` is Some)
+            if unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) } == 0 {
+                match timeout(grace, self.child.wait()).await {
+                    Ok(_) => return,
+                    Err(_) => warn!(
+                        "vcontrold still running {:?} after SIGTERM, sending SIGKILL",
+                        grace
+                    ),
+                }
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = grace;
+        self.kill().await;
+    }
+
+    /// Kill the process (SIGKILL)
     pub async fn kill(&mut self) {
         if let Err(e) = self.child.kill().await {
             warn!("Failed to kill vcontrold: {}", e);
@@ -127,3 +241,121 @@ impl VcontroldProcess {
         self.child.id()
     }
 }
+
+impl Drop for VcontroldProcess {
+    /// Synchronous fallback if the process was not stopped explicitly
+    fn drop(&mut self) {
+        let _ = self.child.start_kill();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawn_shell(script: &str) -> VcontroldProcess {
+        let child = Command::new("sh").arg("-c").arg(script).spawn().unwrap();
+        VcontroldProcess { child }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn stop_terminates_with_sigterm() {
+        let mut process = spawn_shell("trap 'kill $!; exit 0' TERM; sleep 30 & wait");
+        // Give the shell time to install the trap
+        sleep(Duration::from_millis(200)).await;
+        process.stop(Duration::from_secs(5)).await;
+        assert_eq!(process.wait().await.unwrap(), Some(0));
+    }
+
+    #[tokio::test]
+    async fn stop_escalates_to_sigkill() {
+        let mut process = spawn_shell("trap '' TERM; exec sleep 30");
+        let start = Instant::now();
+        process.stop(Duration::from_millis(200)).await;
+        assert!(!process.is_running());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn build_command_appends_extra_args_before_debug_flags() {
+        let extra = vec!["-g".to_string(), "/var/log/vcontrold.log".to_string()];
+        let cmd = build_command(
+            "/opt/vcontrold/bin/vcontrold",
+            Path::new("/config/vcontrold.xml"),
+            &extra,
+            true,
+        );
+        let cmd = cmd.as_std();
+        assert_eq!(cmd.get_program(), "/opt/vcontrold/bin/vcontrold");
+        let args: Vec<_> = cmd.get_args().collect();
+        assert_eq!(
+            args,
+            [
+                "-n",
+                "-x",
+                "/config/vcontrold.xml",
+                "-g",
+                "/var/log/vcontrold.log",
+                "--verbose",
+                "--debug"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn wait_for_device_returns_when_device_exists() {
+        let device = std::env::temp_dir();
+        assert!(wait_for_device(&device, Duration::ZERO).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn monitor_device_tracks_presence() {
+        let server = crate::vcontrold::MockServer::bind(
+            "127.0.0.1:0",
+            [("getTempA".to_string(), "1".to_string())].into(),
+        )
+        .await
+        .unwrap();
+        let port = server.local_addr().unwrap().port();
+        tokio::spawn(server.run());
+        let client = Arc::new(VcontroldClient::new("127.0.0.1", port));
+        client.execute("getTempA").await.unwrap();
+        assert!(client.is_connected());
+
+        let device = std::env::temp_dir().join(format!("vcontrold-usb-{}", std::process::id()));
+        std::fs::write(&device, "").unwrap();
+        tokio::time::pause();
+        let present = Arc::new(AtomicBool::new(true));
+        let cancel = CancellationToken::new();
+        let monitor = tokio::spawn(monitor_device(
+            device.clone(),
+            Duration::from_secs(10),
+            Arc::clone(&present),
+            Arc::clone(&client),
+            cancel.clone(),
+        ));
+
+        std::fs::remove_file(&device).unwrap();
+        tokio::time::sleep(Duration::from_secs(11)).await;
+        assert!(!present.load(Ordering::Relaxed));
+        assert!(!client.is_connected());
+
+        std::fs::write(&device, "").unwrap();
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        assert!(present.load(Ordering::Relaxed));
+
+        cancel.cancel();
+        monitor.await.unwrap();
+        std::fs::remove_file(&device).unwrap();
+    }
+
+    #[tokio::test]
+    async fn wait_for_device_times_out() {
+        tokio::time::pause();
+
+        let device = Path::new("/dev/vcontrold-mqttd-test-missing");
+        let result = wait_for_device(device, Duration::from_secs(5)).await;
+        assert!(matches!(result, Err(ProcessError::DeviceNotFound(_, 5))));
+    }
+}
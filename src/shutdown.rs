@@ -0,0 +1,123 @@
+//! Graceful shutdown of the bridge tasks
+//!
+//! `main.rs` spawns the MQTT event loop, the polling loop and the subscriber
+//! as separate tasks sharing one `CancellationToken`. [`Shutdown`] collects
+//! their handles so the daemon, or an application embedding the same tasks,
+//! can stop them in the right order: stop producing publishes, announce the
+//! instance as offline, drain the queued publishes, then disconnect from the
+//! broker and vcontrold.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use crate::error::MqttError;
+use crate::mqtt::{BirthMessage, MqttClient};
+use crate::vcontrold::VcontroldClient;
+
+/// Handles of the running bridge tasks, consumed by [`Shutdown::shutdown`]
+pub struct Shutdown {
+    /// Token passed to `run_event_loop`, `run_polling_loop` and `run_subscriber`
+    cancel: CancellationToken,
+    mqtt_client: Arc<MqttClient>,
+    vcontrold: Arc<VcontroldClient>,
+    eventloop: JoinHandle<Result<(), MqttError>>,
+    /// Tasks producing publishes (polling loop, subscriber)
+    tasks: Vec<JoinHandle<()>>,
+    /// Birth message to mark offline before disconnecting
+    birth: Option<BirthMessage>,
+    /// Time allowed for the tasks to stop and for queued publishes to be
+    /// acknowledged (`SHUTDOWN_GRACE`)
+    grace: Duration,
+}
+
+impl Shutdown {
+    /// Collect the MQTT event loop task (`run_event_loop`), the token its
+    /// tasks were started with and the clients it shuts down
+    pub fn new(
+        cancel: CancellationToken,
+        mqtt_client: Arc<MqttClient>,
+        vcontrold: Arc<VcontroldClient>,
+        eventloop: JoinHandle<Result<(), MqttError>>,
+    ) -> Self {
+        Self {
+            cancel,
+            mqtt_client,
+            vcontrold,
+            eventloop,
+            tasks: Vec::new(),
+            birth: None,
+            grace: Duration::ZERO,
+        }
+    }
+
+    /// Add a task that publishes through the MQTT client (stopped first)
+    pub fn with_task(mut self, task: JoinHandle<()>) -> Self {
+        self.tasks.push(task);
+        self
+    }
+
+    /// Republish the birth message as offline before disconnecting
+    pub fn with_birth(mut self, birth: BirthMessage) -> Self {
+        self.birth = Some(birth);
+        self
+    }
+
+    /// Wait up to `grace` for the tasks to stop and for queued publishes
+    /// before disconnecting (0 = abort the tasks and disconnect immediately)
+    pub fn with_grace(mut self, grace: Duration) -> Self {
+        self.grace = grace;
+        self
+    }
+
+    /// Stop all tasks and disconnect, returning once complete
+    ///
+    /// Tasks finish the cycle or request they are working on; those still
+    /// running after the grace period are aborted. If the event loop has
+    /// already exited, nothing can be drained and the offline status is not
+    /// published.
+    pub async fn shutdown(mut self) {
+        // Stop producing new publishes
+        self.cancel.cancel();
+        for mut task in self.tasks.drain(..) {
+            if task.is_finished() {
+                continue;
+            }
+            if timeout(self.grace, &mut task).await.is_err() {
+                warn!("Task did not stop within SHUTDOWN_GRACE, aborting it");
+                task.abort();
+                let _ = task.await;
+            }
+        }
+
+        if !self.eventloop.is_finished() {
+            if let Some(birth) = &self.birth {
+                if let Err(e) = self.mqtt_client.publish_offline(birth) {
+                    warn!("Failed to publish offline status: {}", e);
+                }
+            }
+            // Let the event loop drain the queued publishes before disconnecting
+            if !self.grace.is_zero() {
+                info!(
+                    "Draining MQTT publishes (up to {} seconds)...",
+                    self.grace.as_secs()
+                );
+                match self.mqtt_client.disconnect(self.grace).await {
+                    Ok(()) => {
+                        if timeout(self.grace, &mut self.eventloop).await.is_err() {
+                            warn!("MQTT publishes not drained within SHUTDOWN_GRACE");
+                        }
+                    }
+                    Err(e) => warn!("Failed to disconnect from MQTT broker: {}", e),
+                }
+            }
+        }
+        self.eventloop.abort();
+
+        self.vcontrold.disconnect().await;
+    }
+}
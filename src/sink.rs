@@ -0,0 +1,253 @@
+//! Output sinks for polled values
+//!
+//! The polling loop hands every successful result to each registered sink:
+//! once per result as soon as its batch completes, and once per cycle with
+//! all results of the cycle. MQTT is always registered; further outputs
+//! (InfluxDB, stdout, ...) implement [`OutputSink`] and are added in `main.rs`.
+
+use std::future::Future;
+use std::io::Write;
+use std::pin::Pin;
+use std::time::SystemTime;
+
+use tracing::warn;
+
+use crate::mqtt::format_rfc3339;
+use crate::vcontrold::CommandResult;
+
+/// Future returned by the sink methods
+pub type SinkFuture<'a> = Pin<Box<dyn Future<Output = ()> + Send + 'a>>;
+
+/// A destination for polled values
+///
+/// Sinks handle their own errors (log and carry on); a failing sink never
+/// stops the polling loop or the other sinks. Both methods default to doing
+/// nothing, so a sink only implements what it needs.
+pub trait OutputSink: Send + Sync {
+    /// Output a single successful result
+    ///
+    /// `time` is captured once per cycle, so all results of a cycle share it.
+    fn publish<'a>(&'a self, result: &'a CommandResult, time: SystemTime) -> SinkFuture<'a> {
+        let _ = (result, time);
+        Box::pin(std::future::ready(()))
+    }
+
+    /// Output all successful results of a completed cycle
+    fn publish_cycle<'a>(
+        &'a self,
+        results: &'a [CommandResult],
+        time: SystemTime,
+    ) -> SinkFuture<'a> {
+        let _ = (results, time);
+        Box::pin(std::future::ready(()))
+    }
+}
+
+/// Registered sinks, fanned out to in registration order
+#[derive(Default)]
+pub struct Sinks<'s> {
+    sinks: Vec<Box<dyn OutputSink + 's>>,
+}
+
+impl<'s> Sinks<'s> {
+    /// Register a sink
+    pub fn push(&mut self, sink: Box<dyn OutputSink + 's>) {
+        self.sinks.push(sink);
+    }
+
+    /// Number of registered sinks
+    pub fn len(&self) -> usize {
+        self.sinks.len()
+    }
+
+    /// Whether no sink is registered
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    /// Hand each result to every sink
+    pub async fn publish(&self, results: &[CommandResult], time: SystemTime) {
+        for result in results {
+            for sink in &self.sinks {
+                sink.publish(result, time).await;
+            }
+        }
+    }
+
+    /// Hand a completed cycle to every sink
+    pub async fn publish_cycle(&self, results: &[CommandResult], time: SystemTime) {
+        for sink in &self.sinks {
+            sink.publish_cycle(results, time).await;
+        }
+    }
+}
+
+/// Writes each result as a JSON line to stdout (`STDOUT_OUTPUT`)
+///
+/// Bypasses the tracing logger, which `main` moves to stderr while this sink
+/// is enabled, so the output can be piped into other tools as is.
+#[derive(Debug, Default)]
+pub struct StdoutSink {
+    /// Skip results without a value instead of writing `"value":null`
+    omit_nulls: bool,
+    /// Add the raw vcontrold response as `"raw"`
+    raw: bool,
+    /// Add the value's unit as `"unit"`
+    units: bool,
+}
+
+impl StdoutSink {
+    /// Skip results without a value instead of writing `"value":null`
+    pub fn with_omit_nulls(mut self, omit_nulls: bool) -> Self {
+        self.omit_nulls = omit_nulls;
+        self
+    }
+
+    /// Add the raw vcontrold response to each line as `"raw"`
+    pub fn with_raw(mut self, raw: bool) -> Self {
+        self.raw = raw;
+        self
+    }
+
+    /// Add the unit of each value that has one to its line as `"unit"`
+    pub fn with_units(mut self, units: bool) -> Self {
+        self.units = units;
+        self
+    }
+}
+
+impl OutputSink for StdoutSink {
+    fn publish<'a>(&'a self, result: &'a CommandResult, time: SystemTime) -> SinkFuture<'a> {
+        if self.omit_nulls && result.value.to_json_value().is_null() {
+            return Box::pin(std::future::ready(()));
+        }
+        let line = json_line(result, time, self.raw, self.units);
+        let mut stdout = std::io::stdout().lock();
+        if let Err(e) = writeln!(stdout, "{}", line).and_then(|_| stdout.flush()) {
+            warn!("Failed to write to stdout: {}", e);
+        }
+        Box::pin(std::future::ready(()))
+    }
+}
+
+/// Format a result as `{"command":...,"value":...,"ts":...}`, with `"raw"`
+/// holding the unparsed response and `"unit"` the unit if requested
+fn json_line(result: &CommandResult, time: SystemTime, raw: bool, units: bool) -> String {
+    let mut line = serde_json::json!({
+        "command": result.command,
+        "value": result.value.to_json_value(),
+        "ts": format_rfc3339(time),
+    });
+    if raw {
+        line["raw"] = result.raw.as_str().into();
+    }
+    if let Some(unit) = result.unit.as_deref().filter(|_| units) {
+        line["unit"] = unit.into();
+    }
+    line.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcontrold::Value;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    /// Records what it receives as `"publish:<command>"` / `"cycle:<n>"`
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl OutputSink for Recorder {
+        fn publish<'a>(&'a self, result: &'a CommandResult, _: SystemTime) -> SinkFuture<'a> {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("publish:{}", result.command));
+            Box::pin(std::future::ready(()))
+        }
+
+        fn publish_cycle<'a>(
+            &'a self,
+            results: &'a [CommandResult],
+            _: SystemTime,
+        ) -> SinkFuture<'a> {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("cycle:{}", results.len()));
+            Box::pin(std::future::ready(()))
+        }
+    }
+
+    /// Only overrides the cycle method
+    struct CycleOnly(Arc<Mutex<Vec<String>>>);
+
+    impl OutputSink for CycleOnly {
+        fn publish_cycle<'a>(
+            &'a self,
+            results: &'a [CommandResult],
+            _: SystemTime,
+        ) -> SinkFuture<'a> {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("only-cycle:{}", results.len()));
+            Box::pin(std::future::ready(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sinks_fan_out_in_order() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut sinks = Sinks::default();
+        sinks.push(Box::new(Recorder(Arc::clone(&log))));
+        sinks.push(Box::new(CycleOnly(Arc::clone(&log))));
+
+        let results = vec![
+            CommandResult::new("getTempA", Value::Number(1.0)),
+            CommandResult::new("getTempB", Value::Number(1.0)),
+        ];
+        let now = SystemTime::now();
+        sinks.publish(&results, now).await;
+        sinks.publish_cycle(&results, now).await;
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![
+                "publish:getTempA",
+                "publish:getTempB",
+                "cycle:2",
+                "only-cycle:2"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_json_line() {
+        let result = CommandResult::new("getTempA", Value::Number(1.0)).with_raw("1");
+        let time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        assert_eq!(
+            json_line(&result, time, false, false),
+            r#"{"command":"getTempA","value":1.0,"ts":"2023-11-14T22:13:20Z"}"#
+        );
+
+        let status = CommandResult::new("getStatus", Value::String("an \"x\"".to_string()));
+        let line = json_line(&status, time, false, false);
+        let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(json["value"], "an \"x\"");
+        assert!(!line.contains('\n'));
+
+        assert_eq!(
+            json_line(&result, time, true, false),
+            r#"{"command":"getTempA","value":1.0,"ts":"2023-11-14T22:13:20Z","raw":"1"}"#
+        );
+
+        let mut temp = result.clone();
+        temp.unit = Some("°C".to_string());
+        assert_eq!(
+            json_line(&temp, time, false, true),
+            r#"{"command":"getTempA","value":1.0,"ts":"2023-11-14T22:13:20Z","unit":"°C"}"#
+        );
+        assert!(!json_line(&temp, time, false, false).contains("unit"));
+    }
+}
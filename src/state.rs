@@ -0,0 +1,238 @@
+//! Persistent snapshot of the last polled values (`STATE_FILE`)
+//!
+//! Registered as an output sink, [`StateFile`] rewrites the snapshot after
+//! every polling cycle with the last good value of each command. With
+//! `REPUBLISH_ON_START`, the polling loop publishes the snapshot once before
+//! its first cycle, so consumers see the last known values right after a
+//! restart even if the broker did not keep the retained messages. With
+//! `CLEAR_STALE_ON_START`, commands in the snapshot that are no longer
+//! configured have their retained topics cleared and are dropped from it.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use tracing::{debug, warn};
+
+use crate::mqtt::format_rfc3339;
+use crate::sink::{OutputSink, SinkFuture};
+use crate::vcontrold::{CommandResult, Value};
+
+/// Last good value of a command and when it was read
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredValue {
+    pub value: serde_json::Value,
+    /// RFC3339 time of the read
+    pub ts: String,
+}
+
+impl StoredValue {
+    /// Rebuild a result that publishes like the original read
+    pub fn to_result(&self, command: &str) -> Option<CommandResult> {
+        let value = match &self.value {
+            serde_json::Value::Number(n) => Value::Number(n.as_f64()?),
+            serde_json::Value::String(s) => Value::String(s.clone()),
+            _ => return None,
+        };
+        Some(CommandResult::new(command, value).with_raw(self.value.to_string()))
+    }
+}
+
+/// Snapshot file of the last good value per command
+pub struct StateFile {
+    path: PathBuf,
+    values: Mutex<BTreeMap<String, StoredValue>>,
+}
+
+impl StateFile {
+    /// Open the snapshot at `path`, keeping the values already stored there
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let values = load(&path);
+        Self {
+            path,
+            values: Mutex::new(values),
+        }
+    }
+
+    /// Drop the values of commands not in `commands`
+    ///
+    /// Used with `CLEAR_STALE_ON_START`, so the next write no longer lists
+    /// the commands whose topics were cleared.
+    pub fn with_commands(self, commands: &[String]) -> Self {
+        self.values
+            .lock()
+            .unwrap()
+            .retain(|command, _| commands.contains(command));
+        self
+    }
+
+    /// Merge the successful results of a cycle and rewrite the file
+    fn update(&self, results: &[CommandResult], time: SystemTime) {
+        let snapshot = {
+            let mut values = self.values.lock().unwrap();
+            let ts = format_rfc3339(time);
+            for result in results {
+                let value = result.value.to_json_value();
+                if result.error.is_some() || value.is_null() {
+                    continue;
+                }
+                values.insert(
+                    result.command.clone(),
+                    StoredValue {
+                        value,
+                        ts: ts.clone(),
+                    },
+                );
+            }
+            to_json(&values)
+        };
+        if let Err(e) = write_atomic(&self.path, &snapshot) {
+            warn!("Failed to write {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+impl OutputSink for StateFile {
+    fn publish_cycle<'a>(
+        &'a self,
+        results: &'a [CommandResult],
+        time: SystemTime,
+    ) -> SinkFuture<'a> {
+        self.update(results, time);
+        Box::pin(std::future::ready(()))
+    }
+}
+
+/// Read a snapshot; a missing, empty or unreadable file yields no values
+///
+/// Format: `{"getTempA":{"value":21.5,"ts":"2026-01-21T12:53:20Z"},...}`
+pub fn load(path: &Path) -> BTreeMap<String, StoredValue> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            debug!("No state file at {} yet", path.display());
+            return BTreeMap::new();
+        }
+        Err(e) => {
+            warn!("Failed to read {}: {}", path.display(), e);
+            return BTreeMap::new();
+        }
+    };
+    if contents.trim().is_empty() {
+        return BTreeMap::new();
+    }
+    match parse(&contents) {
+        Some(values) => values,
+        None => {
+            warn!("Ignoring malformed state file {}", path.display());
+            BTreeMap::new()
+        }
+    }
+}
+
+fn parse(contents: &str) -> Option<BTreeMap<String, StoredValue>> {
+    let json: serde_json::Value = serde_json::from_str(contents).ok()?;
+    json.as_object()?
+        .iter()
+        .map(|(command, entry)| {
+            let stored = StoredValue {
+                value: entry.get("value")?.clone(),
+                ts: entry.get("ts")?.as_str()?.to_string(),
+            };
+            Some((command.clone(), stored))
+        })
+        .collect()
+}
+
+fn to_json(values: &BTreeMap<String, StoredValue>) -> String {
+    let map: serde_json::Map<String, serde_json::Value> = values
+        .iter()
+        .map(|(command, stored)| {
+            let entry = serde_json::json!({"value": stored.value, "ts": stored.ts});
+            (command.clone(), entry)
+        })
+        .collect();
+    serde_json::Value::Object(map).to_string()
+}
+
+/// Write via a temporary file and rename, so a crash never leaves a torn file
+fn write_atomic(path: &Path, contents: &str) -> std::io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    std::fs::write(&tmp, contents)?;
+    std::fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn test_state_file_round_trip() {
+        let path =
+            std::env::temp_dir().join(format!("vcontrold-state-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let state = StateFile::new(&path);
+        let time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        state.update(
+            &[
+                CommandResult::new("getTempA", Value::Number(21.5)),
+                CommandResult::new("getStatus", Value::String("on".to_string())),
+                CommandResult::failed("getTempB", "ERR: timeout"),
+            ],
+            time,
+        );
+
+        let values = load(&path);
+        assert_eq!(values.len(), 2);
+        assert_eq!(values["getTempA"].ts, "2023-11-14T22:13:20Z");
+        let restored = values["getTempA"].to_result("getTempA").unwrap();
+        assert!(matches!(restored.value, Value::Number(n) if n == 21.5));
+        let restored = values["getStatus"].to_result("getStatus").unwrap();
+        assert!(matches!(restored.value, Value::String(ref s) if s == "on"));
+
+        // A reopened file keeps the values a later cycle did not read
+        let state = StateFile::new(&path);
+        state.update(&[CommandResult::new("getTempA", Value::Number(22.0))], time);
+        assert_eq!(load(&path).len(), 2);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_state_file_with_commands_drops_removed() {
+        let path =
+            std::env::temp_dir().join(format!("vcontrold-state-prune-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        StateFile::new(&path).update(
+            &[
+                CommandResult::new("getTempA", Value::Number(21.5)),
+                CommandResult::new("getOldName", Value::Number(1.0)),
+            ],
+            time,
+        );
+
+        let state = StateFile::new(&path).with_commands(&["getTempA".to_string()]);
+        state.update(&[CommandResult::new("getTempA", Value::Number(22.0))], time);
+        let values = load(&path);
+        assert_eq!(values.len(), 1);
+        assert!(values.contains_key("getTempA"));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_load_missing_or_malformed() {
+        let path = std::env::temp_dir().join("vcontrold-state-missing.json");
+        let _ = std::fs::remove_file(&path);
+        assert!(load(&path).is_empty());
+        assert!(parse("[1,2]").is_none());
+        assert!(parse(r#"{"getTempA":{"value":1}}"#).is_none());
+        assert_eq!(parse("{}").unwrap().len(), 0);
+    }
+}
@@ -1,21 +1,26 @@
 //! vcontrold TCP client with persistent connection
 //!
-//! Manages a persistent TCP connection to vcontrold, with automatic reconnection.
+//! Manages a persistent TCP (or Unix domain socket) connection to vcontrold,
+//! with automatic reconnection.
 
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
 use tokio::net::TcpStream;
-use tokio::sync::{Mutex, MutexGuard};
-use tokio::time::timeout;
+use tokio::sync::{Mutex, MutexGuard, RwLock};
+use tokio::time::{sleep, timeout, timeout_at};
 use tracing::{debug, error, info, warn};
 
 use crate::error::VcontroldError;
 
 use super::protocol::{
-    extract_response, format_command, format_quit, is_fatal_error_response, parse_response,
-    validate_command, CommandResult, PROMPT,
+    extract_response, find_prompt, format_command, format_quit, is_fatal_error_response,
+    parse_command_list, parse_response, validate_command, CommandResult, ErrorKind, LIST_COMMANDS,
+    PROMPT,
 };
 
 /// Default vcontrold port
@@ -25,31 +30,269 @@ pub const DEFAULT_PORT: u16 = 3002;
 const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// Read timeout for responses
-const READ_TIMEOUT: Duration = Duration::from_secs(30);
+pub const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Default cap on the size of a single response (1 MiB)
+pub const DEFAULT_MAX_RESPONSE: usize = 1024 * 1024;
+
+/// Prefix selecting a Unix domain socket instead of a TCP host
+const UNIX_SCHEME: &str = "unix:";
+
+/// Synthetic response returned for every command in dry-run mode
+const DRY_RUN_RESPONSE: &str = "0 (dry run)";
+
+/// Address of the vcontrold daemon
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    /// TCP `host:port`
+    Tcp { host: String, port: u16 },
+    /// Unix domain socket path
+    Unix(PathBuf),
+}
+
+impl Endpoint {
+    /// Parse a target: `unix:/path/to/socket` selects a Unix domain socket,
+    /// anything else is treated as a TCP host combined with `port`
+    fn parse(host: String, port: u16) -> Self {
+        match host.strip_prefix(UNIX_SCHEME) {
+            Some(path) => Endpoint::Unix(PathBuf::from(path)),
+            None => Endpoint::Tcp { host, port },
+        }
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Tcp { host, port } => write!(f, "{}:{}", host, port),
+            Endpoint::Unix(path) => write!(f, "{}{}", UNIX_SCHEME, path.display()),
+        }
+    }
+}
+
+/// Counters of the vcontrold link, updated lock-free on every command
+#[derive(Debug, Default)]
+struct LinkCounters {
+    /// Commands sent to vcontrold
+    commands: AtomicU64,
+    /// Commands that got no response within their read timeout
+    timeouts: AtomicU64,
+    /// Connections established (the first one included)
+    connects: AtomicU64,
+    /// Sum and maximum of the response latencies in microseconds
+    latency_total_us: AtomicU64,
+    latency_max_us: AtomicU64,
+    /// Commands that got a response (the latency sample count)
+    responses: AtomicU64,
+}
+
+impl LinkCounters {
+    fn record_latency(&self, latency: Duration) {
+        let us = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+        self.responses.fetch_add(1, Ordering::Relaxed);
+        self.latency_total_us.fetch_add(us, Ordering::Relaxed);
+        self.latency_max_us.fetch_max(us, Ordering::Relaxed);
+    }
+}
+
+/// Snapshot of the vcontrold link health, see [`VcontroldClient::stats`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ClientStats {
+    /// Commands sent to vcontrold
+    pub commands: u64,
+    /// Commands that timed out waiting for a response
+    pub timeouts: u64,
+    /// Connections established after the first one
+    pub reconnects: u64,
+    /// Mean time from sending a command to its response
+    pub avg_latency: Duration,
+    /// Slowest response so far
+    pub max_latency: Duration,
+}
+
+impl ClientStats {
+    /// Fraction of sent commands that timed out (0 when nothing was sent)
+    pub fn timeout_rate(&self) -> f64 {
+        if self.commands == 0 {
+            0.0
+        } else {
+            self.timeouts as f64 / self.commands as f64
+        }
+    }
+
+    /// Build the link stats payload
+    ///
+    /// Format: {"commands":120,"timeouts":2,"timeout_rate":0.0167,"reconnects":1,"avg_latency_ms":85.2,"max_latency_ms":412.0}
+    pub fn to_json(&self) -> String {
+        let ms = |d: Duration| (d.as_secs_f64() * 10_000.0).round() / 10.0;
+        serde_json::json!({
+            "commands": self.commands,
+            "timeouts": self.timeouts,
+            "timeout_rate": (self.timeout_rate() * 10_000.0).round() / 10_000.0,
+            "reconnects": self.reconnects,
+            "avg_latency_ms": ms(self.avg_latency),
+            "max_latency_ms": ms(self.max_latency),
+        })
+        .to_string()
+    }
+}
+
+/// Byte stream to vcontrold (TCP or Unix domain socket)
+trait Stream: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Stream for T {}
+
+type BoxedStream = Box<dyn Stream>;
 
 /// vcontrold client with persistent connection
 pub struct VcontroldClient {
-    host: String,
-    port: u16,
+    endpoint: Endpoint,
     connection: Mutex<Option<Connection>>,
     /// Tracks whether the persistent TCP connection is alive.
     /// Updated on connect/disconnect; exposed for health checks.
     connected: Arc<AtomicBool>,
+    /// Drop the connection before the next command; set by
+    /// [`Self::mark_disconnected`] while a command holds the connection
+    drop_requested: AtomicBool,
+    /// Re-issue commands that timed out within a batch once, individually
+    batch_retry: bool,
+    /// Return synthetic results without ever connecting to vcontrold
+    dry_run: bool,
+    /// Commands whose response must be numeric
+    numeric_commands: HashSet<String>,
+    /// Commands whose response is parsed as hex bytes
+    hex_commands: HashSet<String>,
+    /// Unit overrides per command (`UNITS`)
+    units: HashMap<String, String>,
+    /// Pause between consecutive commands of a batch or sequence
+    command_delay: Duration,
+    /// Batches hold this shared; atomic sequences hold it exclusively so no
+    /// other command is interleaved with them
+    sequence_lock: RwLock<()>,
+    /// Link health counters, see [`Self::stats`]
+    counters: LinkCounters,
+    /// Prompt vcontrold prints when ready for the next command
+    prompt: String,
+    /// Largest response (including the prompt) read before giving up
+    max_response: usize,
+    /// How long an empty response waits for a value sent after the prompt
+    late_response_wait: Duration,
 }
 
 struct Connection {
-    reader: BufReader<tokio::io::ReadHalf<TcpStream>>,
-    writer: tokio::io::WriteHalf<TcpStream>,
+    reader: BufReader<tokio::io::ReadHalf<BoxedStream>>,
+    writer: tokio::io::WriteHalf<BoxedStream>,
+    /// A command was sent and its response not read completely yet; still
+    /// set when the executing future was dropped (e.g. by a request
+    /// deadline), in which case the connection is out of sync
+    in_flight: bool,
 }
 
 impl VcontroldClient {
     /// Create a new client (does not connect immediately)
+    ///
+    /// A `host` of the form `unix:/path/to/socket` connects to a Unix domain
+    /// socket instead; `port` is ignored in that case.
     pub fn new(host: impl Into<String>, port: u16) -> Self {
         Self {
-            host: host.into(),
-            port,
+            endpoint: Endpoint::parse(host.into(), port),
             connection: Mutex::new(None),
             connected: Arc::new(AtomicBool::new(false)),
+            drop_requested: AtomicBool::new(false),
+            batch_retry: false,
+            dry_run: false,
+            numeric_commands: HashSet::new(),
+            hex_commands: HashSet::new(),
+            units: HashMap::new(),
+            command_delay: Duration::ZERO,
+            sequence_lock: RwLock::new(()),
+            counters: LinkCounters::default(),
+            prompt: PROMPT.to_string(),
+            max_response: DEFAULT_MAX_RESPONSE,
+            late_response_wait: Duration::ZERO,
+        }
+    }
+
+    /// Enable or disable the single individual retry of timed-out batch commands
+    pub fn with_batch_retry(mut self, enabled: bool) -> Self {
+        self.batch_retry = enabled;
+        self
+    }
+
+    /// Enable or disable dry-run mode
+    ///
+    /// In dry-run mode every command returns a fixed dummy value and no
+    /// connection to vcontrold is ever opened.
+    pub fn with_dry_run(mut self, enabled: bool) -> Self {
+        self.dry_run = enabled;
+        self.connected.store(enabled, Ordering::Relaxed);
+        self
+    }
+
+    /// Treat non-numeric responses of the given commands as errors
+    pub fn with_numeric_commands(mut self, commands: HashSet<String>) -> Self {
+        self.numeric_commands = commands;
+        self
+    }
+
+    /// Parse the responses of the given commands as hex bytes
+    pub fn with_hex_commands(mut self, commands: HashSet<String>) -> Self {
+        self.hex_commands = commands;
+        self
+    }
+
+    /// Report `unit` instead of the parsed unit for the mapped commands
+    pub fn with_units(mut self, units: HashMap<String, String>) -> Self {
+        self.units = units;
+        self
+    }
+
+    /// Pause for `delay` between consecutive commands of a batch or sequence
+    pub fn with_command_delay(mut self, delay: Duration) -> Self {
+        self.command_delay = delay;
+        self
+    }
+
+    /// Expect `prompt` instead of `vctrld>` (for patched vcontrold builds)
+    pub fn with_prompt(mut self, prompt: &str) -> Self {
+        self.prompt = prompt.to_string();
+        self
+    }
+
+    /// Abort reads of responses larger than `bytes` with a protocol error
+    pub fn with_max_response(mut self, bytes: usize) -> Self {
+        self.max_response = bytes;
+        self
+    }
+
+    /// Wait up to `wait` for a value sent after the prompt when a response is
+    /// empty (for builds that flush the prompt first); zero disables the wait
+    pub fn with_late_response_wait(mut self, wait: Duration) -> Self {
+        self.late_response_wait = wait;
+        self
+    }
+
+    /// Wait out the configured command delay (before every command but the first)
+    async fn delay_command(&self, idx: usize) {
+        if idx > 0 && !self.command_delay.is_zero() {
+            sleep(self.command_delay).await;
+        }
+    }
+
+    /// Apply the numeric check or hex parsing if `command` is listed for it,
+    /// then its unit override
+    fn check_response(&self, command: &str, result: CommandResult) -> CommandResult {
+        let name = command.split_whitespace().next().unwrap_or_default();
+        let result = if self.hex_commands.contains(name) {
+            result.require_hex()
+        } else if self.numeric_commands.contains(name) {
+            result.require_numeric()
+        } else {
+            result
+        };
+        match self.units.get(name) {
+            Some(unit) => result.with_unit(unit),
+            None => result,
         }
     }
 
@@ -58,33 +301,76 @@ impl VcontroldClient {
         Self::new("127.0.0.1", DEFAULT_PORT)
     }
 
+    /// Create a client for a Unix domain socket
+    pub fn unix(path: impl Into<PathBuf>) -> Self {
+        let mut client = Self::localhost();
+        client.endpoint = Endpoint::Unix(path.into());
+        client
+    }
+
     /// Ensure we have an active connection, reconnecting if necessary
     async fn ensure_connected(&self) -> Result<(), VcontroldError> {
         let mut conn_guard = self.connection.lock().await;
+        if self.drop_requested.swap(false, Ordering::Relaxed) && conn_guard.take().is_some() {
+            warn!("Dropping connection marked as disconnected");
+        }
+        if conn_guard.as_ref().is_some_and(|conn| conn.in_flight) {
+            warn!("Previous command was abandoned before its response, reconnecting");
+            invalidate_locked_connection(&mut conn_guard, self.connected.as_ref(), false).await;
+        }
         if conn_guard.is_none() {
-            info!("Connecting to vcontrold at {}:{}", self.host, self.port);
+            info!("Connecting to vcontrold at {}", self.endpoint);
             let connection = self.connect_internal().await?;
             *conn_guard = Some(connection);
             self.connected.store(true, Ordering::Relaxed);
+            self.counters.connects.fetch_add(1, Ordering::Relaxed);
         }
         Ok(())
     }
 
+    /// Snapshot of the link counters (commands, timeouts, reconnects, latency)
+    pub fn stats(&self) -> ClientStats {
+        let counters = &self.counters;
+        let responses = counters.responses.load(Ordering::Relaxed);
+        let total_us = counters.latency_total_us.load(Ordering::Relaxed);
+        ClientStats {
+            commands: counters.commands.load(Ordering::Relaxed),
+            timeouts: counters.timeouts.load(Ordering::Relaxed),
+            reconnects: counters.connects.load(Ordering::Relaxed).saturating_sub(1),
+            avg_latency: Duration::from_micros(total_us.checked_div(responses).unwrap_or(0)),
+            max_latency: Duration::from_micros(counters.latency_max_us.load(Ordering::Relaxed)),
+        }
+    }
+
     /// Internal connection logic
     async fn connect_internal(&self) -> Result<Connection, VcontroldError> {
-        let addr = format!("{}:{}", self.host, self.port);
-
-        let stream = timeout(CONNECT_TIMEOUT, TcpStream::connect(&addr))
-            .await
-            .map_err(|_| VcontroldError::ConnectionFailed("connection timeout".to_string()))?
-            .map_err(|e| VcontroldError::ConnectionFailed(e.to_string()))?;
+        let stream: BoxedStream = match &self.endpoint {
+            Endpoint::Tcp { .. } => {
+                let addr = self.endpoint.to_string();
+                Box::new(connect_with_timeout(TcpStream::connect(&addr)).await?)
+            }
+            #[cfg(unix)]
+            Endpoint::Unix(path) => {
+                Box::new(connect_with_timeout(tokio::net::UnixStream::connect(path)).await?)
+            }
+            #[cfg(not(unix))]
+            Endpoint::Unix(_) => {
+                return Err(VcontroldError::ConnectionFailed(
+                    "Unix domain sockets are not supported on this platform".to_string(),
+                ))
+            }
+        };
 
         let (read_half, write_half) = tokio::io::split(stream);
         let mut reader = BufReader::new(read_half);
 
-        // Wait for initial prompt (no newline, so read byte by byte)
+        // Wait for initial prompt (no newline, so scan for it)
         let mut buffer = String::new();
-        let result = timeout(READ_TIMEOUT, read_until_prompt(&mut reader, &mut buffer)).await;
+        let result = timeout(
+            READ_TIMEOUT,
+            read_until_prompt(&mut reader, &mut buffer, &self.prompt, self.max_response),
+        )
+        .await;
 
         match result {
             Ok(Ok(())) => {
@@ -97,11 +383,21 @@ impl VcontroldClient {
         Ok(Connection {
             reader,
             writer: write_half,
+            in_flight: false,
         })
     }
 
     /// Execute a single command and return the result
     pub async fn execute(&self, command: &str) -> Result<CommandResult, VcontroldError> {
+        self.execute_with_timeout(command, READ_TIMEOUT).await
+    }
+
+    /// Execute a single command, waiting at most `read_timeout` for the response
+    pub async fn execute_with_timeout(
+        &self,
+        command: &str,
+        read_timeout: Duration,
+    ) -> Result<CommandResult, VcontroldError> {
         enum ExecuteOutcome {
             Success(CommandResult),
             FatalResponse(CommandResult),
@@ -112,6 +408,10 @@ impl VcontroldClient {
         }
 
         validate_command(command)?;
+        if self.dry_run {
+            debug!("Dry run, not sending command: {}", command);
+            return Ok(parse_response(command, DRY_RUN_RESPONSE));
+        }
         self.ensure_connected().await?;
 
         let mut conn_guard = self.connection.lock().await;
@@ -121,6 +421,9 @@ impl VcontroldClient {
             // Send command
             let cmd_str = format_command(command);
             debug!("Sending command: {}", command);
+            self.counters.commands.fetch_add(1, Ordering::Relaxed);
+            conn.in_flight = true;
+            let sent_at = Instant::now();
             if let Err(e) = conn.writer.write_all(cmd_str.as_bytes()).await {
                 error!("Failed to send command: {}", e);
                 ExecuteOutcome::Error {
@@ -137,18 +440,26 @@ impl VcontroldClient {
                 // Read response until prompt
                 let mut buffer = String::new();
                 let read_result = timeout(
-                    READ_TIMEOUT,
-                    read_until_prompt(&mut conn.reader, &mut buffer),
+                    read_timeout,
+                    read_response(
+                        &mut conn.reader,
+                        &mut buffer,
+                        &self.prompt,
+                        self.max_response,
+                        self.late_response_wait,
+                    ),
                 )
                 .await;
 
                 match read_result {
                     Ok(Ok(())) => {
-                        let response = extract_response(&buffer).unwrap_or("");
+                        conn.in_flight = false;
+                        self.counters.record_latency(sent_at.elapsed());
+                        let response = extract_response(&buffer, &self.prompt).unwrap_or("");
                         debug!("Received response: {}", response);
 
                         let result = parse_response(command, response);
-                        if result.error.as_deref().is_some_and(is_fatal_error_response) {
+                        if is_fatal_error_response(&result.raw) {
                             ExecuteOutcome::FatalResponse(result)
                         } else {
                             ExecuteOutcome::Success(result)
@@ -162,16 +473,19 @@ impl VcontroldClient {
                         error: e,
                         send_quit: true,
                     },
-                    Err(_) => ExecuteOutcome::Error {
-                        error: VcontroldError::Timeout,
-                        send_quit: true,
-                    },
+                    Err(_) => {
+                        self.counters.timeouts.fetch_add(1, Ordering::Relaxed);
+                        ExecuteOutcome::Error {
+                            error: VcontroldError::Timeout,
+                            send_quit: true,
+                        }
+                    }
                 }
             }
         };
 
         match outcome {
-            ExecuteOutcome::Success(result) => Ok(result),
+            ExecuteOutcome::Success(result) => Ok(self.check_response(command, result)),
             ExecuteOutcome::FatalResponse(result) => {
                 warn!(
                     "Fatal vcontrold session error for {} - resetting connection before the next command",
@@ -189,17 +503,228 @@ impl VcontroldClient {
     }
 
     /// Execute multiple commands and return all results
+    ///
+    /// A command that times out drops the connection, so the remaining
+    /// commands of the batch continue on a fresh one. With batch retry
+    /// enabled, every command that stalled (never produced a response) is
+    /// re-issued once on its own after the rest of the batch has completed,
+    /// so a single intermittently hanging parameter only costs itself.
     pub async fn execute_batch(
         &self,
         commands: &[String],
     ) -> Vec<Result<CommandResult, VcontroldError>> {
+        self.execute_batch_with_timeout(commands, READ_TIMEOUT)
+            .await
+    }
+
+    /// Execute multiple commands with a per-command read timeout
+    ///
+    /// Same as [`Self::execute_batch`], but each command (and its retry)
+    /// waits at most `read_timeout` for a response.
+    pub async fn execute_batch_with_timeout(
+        &self,
+        commands: &[String],
+        read_timeout: Duration,
+    ) -> Vec<Result<CommandResult, VcontroldError>> {
+        let mut results = Vec::with_capacity(commands.len());
+        self.execute_batch_into(commands, read_timeout, &mut results)
+            .await;
+        results
+    }
+
+    /// Execute multiple commands, appending each result to `results` as soon
+    /// as the command completes
+    ///
+    /// Same as [`Self::execute_batch_with_timeout`], but the results of the
+    /// completed commands stay in `results` when the future is dropped
+    /// before the batch is done (e.g. by a `tokio::time::timeout`); the
+    /// interrupted command's connection is replaced before the next command.
+    pub async fn execute_batch_into(
+        &self,
+        commands: &[String],
+        read_timeout: Duration,
+        results: &mut Vec<Result<CommandResult, VcontroldError>>,
+    ) {
+        let _shared = self.sequence_lock.read().await;
+        let first = results.len();
+        // Indices of commands that did not produce a response
+        let mut stalled = Vec::new();
+        for (idx, cmd) in commands.iter().enumerate() {
+            self.delay_command(idx).await;
+            let result = self.execute_with_timeout(cmd, read_timeout).await;
+            if matches!(result, Err(VcontroldError::Timeout)) {
+                stalled.push(idx);
+            }
+            results.push(result);
+        }
+
+        if self.batch_retry {
+            for idx in stalled {
+                warn!("Command {} timed out, retrying individually", commands[idx]);
+                results[first + idx] = self
+                    .execute_with_timeout(&commands[idx], read_timeout)
+                    .await;
+            }
+        }
+    }
+
+    /// Execute commands strictly in order without other commands in between
+    ///
+    /// Waits for running batches to finish and blocks new ones until the
+    /// sequence is done. Stops at the first command that fails or returns an
+    /// error; the remaining commands are reported as `VcontroldError::Skipped`.
+    /// Each command waits at most `read_timeout` for its response.
+    pub async fn execute_atomic(
+        &self,
+        commands: &[String],
+        read_timeout: Duration,
+    ) -> Vec<Result<CommandResult, VcontroldError>> {
+        let _exclusive = self.sequence_lock.write().await;
         let mut results = Vec::with_capacity(commands.len());
-        for cmd in commands {
-            results.push(self.execute(cmd).await);
+        let mut failed = false;
+        for (idx, cmd) in commands.iter().enumerate() {
+            if failed {
+                results.push(Err(VcontroldError::Skipped));
+                continue;
+            }
+            self.delay_command(idx).await;
+            let result = self.execute_with_timeout(cmd, read_timeout).await;
+            failed = !matches!(&result, Ok(r) if r.error.is_none());
+            if failed {
+                warn!("Command {} failed, skipping the rest of the sequence", cmd);
+            }
+            results.push(result);
         }
         results
     }
 
+    /// Query a single command (embedding API)
+    ///
+    /// Same as [`Self::execute`]. A response vcontrold answered with `ERR:`
+    /// is `Ok` with `error` set; `Err` means the command got no response.
+    pub async fn query(&self, command: &str) -> Result<CommandResult, VcontroldError> {
+        self.execute(command).await
+    }
+
+    /// Query several commands in order (embedding API)
+    ///
+    /// Runs them as one batch and pairs every command with its own result,
+    /// so one failing command does not hide the others.
+    pub async fn query_many<S: AsRef<str>>(
+        &self,
+        commands: &[S],
+    ) -> Vec<(String, Result<CommandResult, VcontroldError>)> {
+        let commands: Vec<String> = commands.iter().map(|c| c.as_ref().to_string()).collect();
+        let results = self.execute_batch(&commands).await;
+        commands.into_iter().zip(results).collect()
+    }
+
+    /// Re-execute commands whose previous attempt failed with a transient
+    /// (connection-level) error, up to `retries` additional attempts each
+    ///
+    /// `results` must line up with `commands`, as returned by `execute_batch`.
+    /// `ERR:` responses are only retried if classified as a transient bus
+    /// problem (`ErrorKind::DeviceBusy`); all other command errors are
+    /// deterministic.
+    pub async fn retry_transient_failures(
+        &self,
+        commands: &[String],
+        results: Vec<Result<CommandResult, VcontroldError>>,
+        retries: u32,
+    ) -> Vec<Result<CommandResult, VcontroldError>> {
+        let mut results = results;
+        self.retry_failures(
+            commands,
+            &mut results,
+            retries,
+            READ_TIMEOUT,
+            |result| match result {
+                Err(e) if e.is_transient() => Some(e.to_string()),
+                Ok(r) if r.error_kind.is_some_and(ErrorKind::is_transient) => {
+                    Some(r.error.clone().unwrap_or_default())
+                }
+                _ => None,
+            },
+        )
+        .await;
+        results
+    }
+
+    /// Re-execute commands whose connection to vcontrold broke, e.g. because
+    /// vcontrold restarted since the previous command
+    ///
+    /// Each retry runs on a fresh connection and waits at most `read_timeout`.
+    /// Timeouts and `ERR:` responses are never retried.
+    pub async fn retry_connection_failures(
+        &self,
+        commands: &[String],
+        results: Vec<Result<CommandResult, VcontroldError>>,
+        retries: u32,
+        read_timeout: Duration,
+    ) -> Vec<Result<CommandResult, VcontroldError>> {
+        let mut results = results;
+        self.retry_connection_failures_in_place(commands, &mut results, retries, read_timeout)
+            .await;
+        results
+    }
+
+    /// Same as [`Self::retry_connection_failures`], but updates `results` in
+    /// place, so finished retries are kept if the future is dropped
+    pub async fn retry_connection_failures_in_place(
+        &self,
+        commands: &[String],
+        results: &mut [Result<CommandResult, VcontroldError>],
+        retries: u32,
+        read_timeout: Duration,
+    ) {
+        self.retry_failures(
+            commands,
+            results,
+            retries,
+            read_timeout,
+            |result| match result {
+                Err(e) if e.is_connection_lost() => Some(e.to_string()),
+                _ => None,
+            },
+        )
+        .await
+    }
+
+    /// Re-execute every command for which `reason` returns why it should be
+    /// retried, up to `retries` additional attempts each
+    async fn retry_failures(
+        &self,
+        commands: &[String],
+        results: &mut [Result<CommandResult, VcontroldError>],
+        retries: u32,
+        read_timeout: Duration,
+        reason: impl Fn(&Result<CommandResult, VcontroldError>) -> Option<String>,
+    ) {
+        for (command, result) in commands.iter().zip(results.iter_mut()) {
+            let mut attempt = 0;
+            while attempt < retries {
+                let Some(reason) = reason(result) else {
+                    break;
+                };
+                attempt += 1;
+                warn!(
+                    "Command {} failed ({}), retrying ({}/{})",
+                    command, reason, attempt, retries
+                );
+                *result = self.execute_with_timeout(command, read_timeout).await;
+            }
+        }
+    }
+
+    /// Query the names of all commands known to vcontrold
+    pub async fn list_commands(&self) -> Result<Vec<String>, VcontroldError> {
+        let result = self.execute(LIST_COMMANDS).await?;
+        match result.error {
+            Some(e) => Err(VcontroldError::Command(e)),
+            None => Ok(parse_command_list(&result.raw)),
+        }
+    }
+
     /// Disconnect from vcontrold gracefully
     pub async fn disconnect(&self) {
         let mut conn_guard = self.connection.lock().await;
@@ -213,6 +738,10 @@ impl VcontroldClient {
 
     /// Check if vcontrold is responding (for readiness probes)
     pub async fn is_ready(&self) -> bool {
+        if self.dry_run {
+            return true;
+        }
+
         // Try to connect and receive initial prompt
         match self.connect_internal().await {
             Ok(mut conn) => {
@@ -229,22 +758,50 @@ impl VcontroldClient {
         }
     }
 
+    /// Whether the persistent connection is currently open
+    ///
+    /// Cheap and side-effect free, unlike `is_ready` which opens a fresh
+    /// connection. Reflects the state after the last command, so a peer that
+    /// closed an idle connection is only noticed by the next command.
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
     /// Get a shared reference to the connection-alive flag (for health checks)
     pub fn connected_flag(&self) -> Arc<AtomicBool> {
         Arc::clone(&self.connected)
     }
 
     /// Mark connection as lost (called when we detect issues)
-    #[allow(dead_code)]
-    pub async fn mark_disconnected(&self) {
-        let mut conn_guard = self.connection.lock().await;
-        if conn_guard.take().is_some() {
-            warn!("Connection marked as disconnected");
-        }
+    ///
+    /// Never waits for a command in progress: if one holds the connection,
+    /// the connection is dropped before the next command instead.
+    pub fn mark_disconnected(&self) {
         self.connected.store(false, Ordering::Relaxed);
+        match self.connection.try_lock() {
+            Ok(mut conn_guard) => {
+                if conn_guard.take().is_some() {
+                    warn!("Connection marked as disconnected");
+                }
+            }
+            Err(_) => {
+                debug!("Connection busy, dropping it before the next command");
+                self.drop_requested.store(true, Ordering::Relaxed);
+            }
+        }
     }
 }
 
+/// Await a connect future, mapping timeouts and I/O errors to `ConnectionFailed`
+async fn connect_with_timeout<S>(
+    connect: impl std::future::Future<Output = std::io::Result<S>>,
+) -> Result<S, VcontroldError> {
+    timeout(CONNECT_TIMEOUT, connect)
+        .await
+        .map_err(|_| VcontroldError::ConnectionFailed("connection timeout".to_string()))?
+        .map_err(|e| VcontroldError::ConnectionFailed(e.to_string()))
+}
+
 async fn invalidate_locked_connection(
     conn_guard: &mut MutexGuard<'_, Option<Connection>>,
     connected: &AtomicBool,
@@ -262,30 +819,93 @@ async fn invalidate_locked_connection(
 
 /// Read from reader until the prompt is found
 ///
-/// Accumulates raw bytes and checks for the prompt in the byte stream.
-/// Once found, the buffer is converted to a (lossy) UTF-8 string.
-/// This avoids silently dropping non-ASCII bytes (e.g. `°` in unit
-/// strings) that would break prompt detection.
-async fn read_until_prompt<R: AsyncReadExt + Unpin>(
+/// Scans the reader's buffered chunks (8 KiB for the connection's
+/// `BufReader`) for the prompt, also when it straddles two chunks, and only
+/// consumes bytes up to the end of the prompt, so anything vcontrold sends
+/// after it stays buffered. Raw bytes are accumulated and converted to a
+/// (lossy) UTF-8 string once the prompt is found. This avoids silently
+/// dropping non-ASCII bytes (e.g. `°` in unit strings) that would break
+/// prompt detection. The prompt only counts at the start of a line (see
+/// [`find_prompt`]).
+///
+/// A response growing beyond `max_len` bytes without a prompt fails with
+/// [`VcontroldError::Protocol`]; the caller then drops the connection.
+async fn read_until_prompt<R: AsyncBufRead + Unpin>(
     reader: &mut R,
     buffer: &mut String,
+    prompt: &str,
+    max_len: usize,
 ) -> Result<(), VcontroldError> {
-    let prompt_bytes = PROMPT.as_bytes();
+    let prompt_bytes = prompt.as_bytes();
     let mut raw = Vec::new();
-    let mut byte_buf = [0u8; 1];
     loop {
-        match reader.read(&mut byte_buf).await {
-            Ok(0) => return Err(VcontroldError::ConnectionLost),
-            Ok(_) => {
-                raw.push(byte_buf[0]);
-                if raw.ends_with(prompt_bytes) {
-                    *buffer = String::from_utf8_lossy(&raw).into_owned();
-                    return Ok(());
-                }
-            }
-            Err(e) => return Err(VcontroldError::Io(e)),
+        let chunk = reader.fill_buf().await.map_err(VcontroldError::Io)?;
+        if chunk.is_empty() {
+            return Err(VcontroldError::ConnectionLost);
+        }
+        let chunk_len = chunk.len();
+        let old_len = raw.len();
+        // The prompt may have started in the previous chunk
+        let search_from = old_len.saturating_sub(prompt_bytes.len().saturating_sub(1));
+        raw.extend_from_slice(chunk);
+
+        let found =
+            find_prompt(&raw, prompt_bytes, search_from).map(|pos| pos + prompt_bytes.len());
+        if found.unwrap_or(raw.len()) > max_len {
+            return Err(VcontroldError::Protocol(format!(
+                "response exceeds {} bytes",
+                max_len
+            )));
+        }
+        if let Some(end) = found {
+            reader.consume(end - old_len);
+            raw.truncate(end);
+            *buffer = String::from_utf8_lossy(&raw).into_owned();
+            return Ok(());
+        }
+        reader.consume(chunk_len);
+    }
+}
+
+/// Read the response to a command, tolerating a prompt sent too early
+///
+/// Some vcontrold builds emit the prompt before the value is flushed: the
+/// first prompt then closes an empty response and the value follows with a
+/// prompt of its own. If the response is empty and anything other than
+/// whitespace arrives within `late_wait`, the response is read again up to
+/// the next prompt; otherwise the empty response stands. A zero `late_wait`
+/// returns right after the first prompt.
+async fn read_response<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    buffer: &mut String,
+    prompt: &str,
+    max_len: usize,
+    late_wait: Duration,
+) -> Result<(), VcontroldError> {
+    read_until_prompt(reader, buffer, prompt, max_len).await?;
+    if late_wait.is_zero()
+        || extract_response(buffer, prompt).is_some_and(|response| !response.is_empty())
+    {
+        return Ok(());
+    }
+
+    let deadline = tokio::time::Instant::now() + late_wait;
+    loop {
+        let (blank, len) = match timeout_at(deadline, reader.fill_buf()).await {
+            Ok(Ok(chunk)) if !chunk.is_empty() => (
+                chunk.iter().take_while(|b| b.is_ascii_whitespace()).count(),
+                chunk.len(),
+            ),
+            _ => return Ok(()),
+        };
+        // Trailing whitespace after the prompt is not a late value
+        reader.consume(blank);
+        if blank < len {
+            break;
         }
     }
+    debug!("Received data after an empty response, reading up to the next prompt");
+    read_until_prompt(reader, buffer, prompt, max_len).await
 }
 
 impl Drop for VcontroldClient {
@@ -297,8 +917,9 @@ impl Drop for VcontroldClient {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::vcontrold::Value;
-    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use crate::vcontrold::{MockServer, Value};
+    use std::collections::HashMap;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
     use tokio::net::{TcpListener, TcpStream};
 
     async fn write_prompt(stream: &mut TcpStream) {
@@ -360,6 +981,27 @@ mod tests {
         server.await.unwrap();
     }
 
+    #[tokio::test]
+    async fn is_connected_tracks_persistent_connection_only() {
+        let responses = HashMap::from([("getTempA".to_string(), "21.5".to_string())]);
+        let server = MockServer::bind("127.0.0.1:0", responses).await.unwrap();
+        let port = server.local_addr().unwrap().port();
+        tokio::spawn(server.run());
+
+        let client = VcontroldClient::new("127.0.0.1", port);
+        assert!(!client.is_connected());
+
+        // The readiness probe uses a throwaway connection
+        assert!(client.is_ready().await);
+        assert!(!client.is_connected());
+
+        client.execute("getTempA").await.unwrap();
+        assert!(client.is_connected());
+
+        client.disconnect().await;
+        assert!(!client.is_connected());
+    }
+
     #[tokio::test]
     async fn execute_resets_connection_after_fatal_error_response() {
         let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
@@ -421,4 +1063,657 @@ mod tests {
         client.disconnect().await;
         server.await.unwrap();
     }
+
+    #[test]
+    fn endpoint_parses_unix_scheme() {
+        assert_eq!(
+            Endpoint::parse("unix:/run/vcontrold.sock".to_string(), DEFAULT_PORT),
+            Endpoint::Unix(PathBuf::from("/run/vcontrold.sock"))
+        );
+        assert_eq!(
+            Endpoint::parse("127.0.0.1".to_string(), DEFAULT_PORT),
+            Endpoint::Tcp {
+                host: "127.0.0.1".to_string(),
+                port: DEFAULT_PORT
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn list_commands_parses_multi_line_response() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            write_prompt(&mut stream).await;
+
+            let mut reader = BufReader::new(stream);
+            let mut command = String::new();
+            reader.read_line(&mut command).await.unwrap();
+            assert_eq!(command, "commands\n");
+
+            let mut stream = reader.into_inner();
+            stream
+                .write_all(b"getTempA: Aussentemperatur\ngetTempWWsoll: Warmwasser soll\nvctrld>")
+                .await
+                .unwrap();
+            stream.flush().await.unwrap();
+        });
+
+        let client = VcontroldClient::new("127.0.0.1", port);
+        let commands = client.list_commands().await.unwrap();
+        assert_eq!(commands, vec!["getTempA", "getTempWWsoll"]);
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn dry_run_returns_dummy_value_without_connecting() {
+        // Nothing listens on this port; a real connection attempt would fail
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+
+        let client = VcontroldClient::new("127.0.0.1", port).with_dry_run(true);
+        assert!(client.is_ready().await);
+
+        let results = client
+            .execute_batch(&["getTempA".to_string(), "setTempWWsoll 50".to_string()])
+            .await;
+        for (result, command) in results.into_iter().zip(["getTempA", "setTempWWsoll 50"]) {
+            let result = result.unwrap();
+            assert_eq!(result.command, command);
+            assert!(result.error.is_none());
+            assert!(matches!(result.value, Value::Number(n) if n == 0.0));
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn execute_over_unix_socket() {
+        let path =
+            std::env::temp_dir().join(format!("vcontrold-mqttd-test-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream.write_all(PROMPT.as_bytes()).await.unwrap();
+
+            let mut reader = BufReader::new(stream);
+            let mut command = String::new();
+            reader.read_line(&mut command).await.unwrap();
+            assert_eq!(command, "getTempA\n");
+            reader
+                .get_mut()
+                .write_all(b"21.5 Grad Celsius\nvctrld>")
+                .await
+                .unwrap();
+        });
+
+        let client = VcontroldClient::new(format!("unix:{}", path.display()), DEFAULT_PORT);
+        let result = client.execute("getTempA").await.unwrap();
+        assert!(matches!(result.value, Value::Number(n) if (n - 21.5).abs() < 0.001));
+
+        server.await.unwrap();
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn retry_transient_failures_retries_connection_loss_exactly_once() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        // Every connection receives the command and then drops the socket
+        // without answering, so each attempt fails with ConnectionLost.
+        let server = tokio::spawn(async move {
+            let mut attempts = 0;
+            while let Ok(Ok((mut stream, _))) =
+                tokio::time::timeout(Duration::from_millis(500), listener.accept()).await
+            {
+                write_prompt(&mut stream).await;
+                let mut reader = BufReader::new(stream);
+                let mut command = String::new();
+                reader.read_line(&mut command).await.unwrap();
+                assert_eq!(command, "getTempA\n");
+                attempts += 1;
+            }
+            attempts
+        });
+
+        let client = VcontroldClient::new("127.0.0.1", port);
+        let commands = vec!["getTempA".to_string()];
+
+        let results = client.execute_batch(&commands).await;
+        assert!(matches!(results[0], Err(VcontroldError::ConnectionLost)));
+
+        let results = client.retry_transient_failures(&commands, results, 1).await;
+        assert!(matches!(results[0], Err(VcontroldError::ConnectionLost)));
+
+        assert_eq!(server.await.unwrap(), 2, "initial attempt plus one retry");
+    }
+
+    #[tokio::test]
+    async fn retry_transient_failures_skips_command_errors() {
+        let client = VcontroldClient::new("127.0.0.1", 1);
+        let commands = vec!["badCommand".to_string()];
+        let results = vec![Err(VcontroldError::Command("unknown".to_string()))];
+
+        let results = client.retry_transient_failures(&commands, results, 3).await;
+
+        assert!(matches!(results[0], Err(VcontroldError::Command(_))));
+
+        // Unknown commands are permanent as well
+        let results = vec![Ok(parse_response("badCommand", "ERR: command unknown"))];
+        let results = client.retry_transient_failures(&commands, results, 3).await;
+        assert_eq!(
+            results[0].as_ref().unwrap().error_kind,
+            Some(ErrorKind::UnknownCommand)
+        );
+    }
+
+    #[tokio::test]
+    async fn retry_connection_failures_only_retries_lost_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            write_prompt(&mut stream).await;
+
+            // Only getTempA is re-sent
+            let mut reader = BufReader::new(stream);
+            let mut command = String::new();
+            reader.read_line(&mut command).await.unwrap();
+            assert_eq!(command, "getTempA\n");
+            reader
+                .get_mut()
+                .write_all(b"21.5 Grad Celsius\nvctrld>")
+                .await
+                .unwrap();
+        });
+
+        let client = VcontroldClient::new("127.0.0.1", port);
+        let commands: Vec<String> = vec!["getTempA".into(), "getTempB".into(), "badCmd".into()];
+        let results = vec![
+            Err(VcontroldError::ConnectionLost),
+            Err(VcontroldError::Timeout),
+            Ok(parse_response("badCmd", "ERR: command unknown")),
+        ];
+
+        let results = client
+            .retry_connection_failures(&commands, results, 1, READ_TIMEOUT)
+            .await;
+        assert!(results[0].as_ref().unwrap().error.is_none());
+        assert!(matches!(results[1], Err(VcontroldError::Timeout)));
+        assert!(results[2].as_ref().unwrap().error.is_some());
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn retry_transient_failures_retries_device_busy_response() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            write_prompt(&mut stream).await;
+
+            let mut reader = BufReader::new(stream);
+            let mut command = String::new();
+            reader.read_line(&mut command).await.unwrap();
+            assert_eq!(command, "getTempA\n");
+
+            let mut stream = reader.into_inner();
+            stream
+                .write_all(b"21.5 Grad Celsius\nvctrld>")
+                .await
+                .unwrap();
+            stream.flush().await.unwrap();
+        });
+
+        let client = VcontroldClient::new("127.0.0.1", port);
+        let commands = vec!["getTempA".to_string()];
+        let results = vec![Ok(parse_response("getTempA", "ERR: telegram too short"))];
+
+        let results = client.retry_transient_failures(&commands, results, 1).await;
+        let result = results[0].as_ref().unwrap();
+        assert!(result.error.is_none());
+        assert!(matches!(result.value, Value::Number(n) if (n - 21.5).abs() < 0.001));
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn dropped_batch_keeps_finished_results_and_resets_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = tokio::spawn(async move {
+            let (mut first, _) = listener.accept().await.unwrap();
+            write_prompt(&mut first).await;
+            let mut reader = BufReader::new(first);
+            let mut command = String::new();
+            reader.read_line(&mut command).await.unwrap();
+            assert_eq!(command, "getTempA\n");
+            reader
+                .get_mut()
+                .write_all(b"21.5 Grad Celsius\nvctrld>")
+                .await
+                .unwrap();
+            command.clear();
+            reader.read_line(&mut command).await.unwrap();
+            assert_eq!(command, "getSlow\n");
+
+            // The late answer must not be read as the next command's response
+            let (mut second, _) = listener.accept().await.unwrap();
+            let _ = reader.get_mut().write_all(b"99\nvctrld>").await;
+            write_prompt(&mut second).await;
+            let mut reader = BufReader::new(second);
+            command.clear();
+            reader.read_line(&mut command).await.unwrap();
+            assert_eq!(command, "getTempB\n");
+            reader
+                .get_mut()
+                .write_all(b"45.0 Grad Celsius\nvctrld>")
+                .await
+                .unwrap();
+        });
+
+        let client = VcontroldClient::new("127.0.0.1", port);
+        let commands: Vec<String> = vec!["getTempA".into(), "getSlow".into()];
+        let mut results = Vec::new();
+        let batch = client.execute_batch_into(&commands, READ_TIMEOUT, &mut results);
+        assert!(timeout(Duration::from_millis(200), batch).await.is_err());
+        assert_eq!(results.len(), 1);
+        assert!(matches!(&results[0], Ok(r) if matches!(r.value, Value::Number(n) if n == 21.5)));
+
+        let result = client.execute("getTempB").await.unwrap();
+        assert!(matches!(result.value, Value::Number(n) if n == 45.0));
+
+        client.disconnect().await;
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn execute_atomic_stops_at_first_failure() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            write_prompt(&mut stream).await;
+
+            let mut reader = BufReader::new(stream);
+            for (expected, response) in [
+                ("setBetriebsart 1\n", &b"OK\nvctrld>"[..]),
+                ("setTempRaumNorSoll 99\n", &b"ERR: invalid\nvctrld>"[..]),
+            ] {
+                let mut command = String::new();
+                reader.read_line(&mut command).await.unwrap();
+                assert_eq!(command, expected);
+                reader.get_mut().write_all(response).await.unwrap();
+            }
+
+            // getTempA is never sent
+            let mut quit = String::new();
+            reader.read_line(&mut quit).await.unwrap();
+            assert_eq!(quit, "quit\n");
+        });
+
+        let delay = Duration::from_millis(50);
+        let client = VcontroldClient::new("127.0.0.1", port).with_command_delay(delay);
+        let commands: Vec<String> = vec![
+            "setBetriebsart 1".into(),
+            "setTempRaumNorSoll 99".into(),
+            "getTempA".into(),
+        ];
+
+        let started = std::time::Instant::now();
+        let results = client.execute_atomic(&commands, READ_TIMEOUT).await;
+        assert!(started.elapsed() >= delay);
+
+        assert!(results[0].as_ref().unwrap().error.is_none());
+        assert!(results[1].as_ref().unwrap().error.is_some());
+        assert!(matches!(results[2], Err(VcontroldError::Skipped)));
+
+        client.disconnect().await;
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn query_many_pairs_commands_with_results() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            write_prompt(&mut stream).await;
+
+            let mut reader = BufReader::new(stream);
+            for (expected, response) in [
+                ("getTempA\n", &b"21.5 Grad Celsius\nvctrld>"[..]),
+                ("badCommand\n", &b"ERR: command unknown\nvctrld>"[..]),
+            ] {
+                let mut command = String::new();
+                reader.read_line(&mut command).await.unwrap();
+                assert_eq!(command, expected);
+                reader.get_mut().write_all(response).await.unwrap();
+            }
+        });
+
+        let client = VcontroldClient::new("127.0.0.1", port);
+        let results = client.query_many(&["getTempA", "badCommand"]).await;
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].0, "getTempA");
+        let first = results[0].1.as_ref().unwrap();
+        assert!(matches!(first.value, Value::Number(n) if (n - 21.5).abs() < 0.001));
+        assert_eq!(results[1].0, "badCommand");
+        let second = results[1].1.as_ref().unwrap();
+        assert_eq!(second.error_kind, Some(ErrorKind::UnknownCommand));
+
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn execute_with_timeout_fails_fast() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            write_prompt(&mut stream).await;
+
+            // Never answer the command; the client gives up and sends quit
+            let mut reader = BufReader::new(stream);
+            let mut command = String::new();
+            reader.read_line(&mut command).await.unwrap();
+            assert_eq!(command, "getTempA\n");
+            let mut quit = String::new();
+            reader.read_line(&mut quit).await.unwrap();
+            assert_eq!(quit, "quit\n");
+        });
+
+        let client = VcontroldClient::new("127.0.0.1", port);
+        let started = std::time::Instant::now();
+        let result = client
+            .execute_with_timeout("getTempA", Duration::from_millis(100))
+            .await;
+
+        assert!(matches!(result, Err(VcontroldError::Timeout)));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn execute_batch_retries_timed_out_command_individually() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = tokio::spawn(async move {
+            // First connection: getTempA answers, getTempB stalls until the
+            // client gives up and sends quit.
+            let (mut first_stream, _) = listener.accept().await.unwrap();
+            write_prompt(&mut first_stream).await;
+
+            let mut first_reader = BufReader::new(first_stream);
+            let mut command = String::new();
+            first_reader.read_line(&mut command).await.unwrap();
+            assert_eq!(command, "getTempA\n");
+            first_reader
+                .get_mut()
+                .write_all(b"21.5 Grad Celsius\nvctrld>")
+                .await
+                .unwrap();
+
+            let mut command = String::new();
+            first_reader.read_line(&mut command).await.unwrap();
+            assert_eq!(command, "getTempB\n");
+
+            let mut quit = String::new();
+            first_reader.read_line(&mut quit).await.unwrap();
+            assert_eq!(quit, "quit\n");
+
+            // Second connection: the rest of the batch, then the retry.
+            let (mut second_stream, _) = listener.accept().await.unwrap();
+            write_prompt(&mut second_stream).await;
+
+            let mut second_reader = BufReader::new(second_stream);
+            for (expected, response) in [
+                ("getTempC\n", b"30.0 Grad Celsius\nvctrld>"),
+                ("getTempB\n", b"45.0 Grad Celsius\nvctrld>"),
+            ] {
+                let mut command = String::new();
+                second_reader.read_line(&mut command).await.unwrap();
+                assert_eq!(command, expected);
+                second_reader.get_mut().write_all(response).await.unwrap();
+            }
+        });
+
+        let client = VcontroldClient::new("127.0.0.1", port).with_batch_retry(true);
+        let commands: Vec<String> = vec!["getTempA".into(), "getTempB".into(), "getTempC".into()];
+
+        // Real time with a short read timeout: paused time would auto-advance
+        // past the timeout while the socket reads are still in flight
+        let results = client
+            .execute_batch_with_timeout(&commands, Duration::from_millis(500))
+            .await;
+
+        assert_eq!(results.len(), 3);
+        for (result, expected) in results.iter().zip([21.5, 45.0, 30.0]) {
+            let result = result.as_ref().expect("every command should succeed");
+            assert!(matches!(result.value, Value::Number(n) if (n - expected).abs() < 0.001));
+        }
+
+        // getTempB timed out once and the batch continued on a new connection
+        let stats = client.stats();
+        assert_eq!(stats.commands, 4);
+        assert_eq!(stats.timeouts, 1);
+        assert_eq!(stats.reconnects, 1);
+        assert_eq!(stats.timeout_rate(), 0.25);
+
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn read_until_prompt_handles_any_chunk_boundary() {
+        let stream = "getTempA: 21.5 °C\nline two\nvctrld>next".as_bytes();
+        for capacity in 1..=stream.len() {
+            let mut reader = BufReader::with_capacity(capacity, stream);
+            let mut buffer = String::new();
+            read_until_prompt(&mut reader, &mut buffer, PROMPT, DEFAULT_MAX_RESPONSE)
+                .await
+                .unwrap();
+            assert_eq!(
+                buffer, "getTempA: 21.5 °C\nline two\nvctrld>",
+                "capacity {}",
+                capacity
+            );
+
+            // Bytes after the prompt are left for the next read
+            let mut rest = String::new();
+            reader.read_to_string(&mut rest).await.unwrap();
+            assert_eq!(rest, "next", "capacity {}", capacity);
+        }
+    }
+
+    #[tokio::test]
+    async fn read_until_prompt_ignores_embedded_prompt() {
+        let stream = "Name: my vctrld> box\nvctrld>".as_bytes();
+        for capacity in 1..=stream.len() {
+            let mut reader = BufReader::with_capacity(capacity, stream);
+            let mut buffer = String::new();
+            read_until_prompt(&mut reader, &mut buffer, PROMPT, DEFAULT_MAX_RESPONSE)
+                .await
+                .unwrap();
+            assert_eq!(
+                buffer, "Name: my vctrld> box\nvctrld>",
+                "capacity {}",
+                capacity
+            );
+            assert_eq!(
+                extract_response(&buffer, PROMPT),
+                Some("Name: my vctrld> box")
+            );
+        }
+
+        // The greeting prompt starts the stream
+        let mut reader = BufReader::new(&b"vctrld>"[..]);
+        let mut buffer = String::new();
+        read_until_prompt(&mut reader, &mut buffer, PROMPT, DEFAULT_MAX_RESPONSE)
+            .await
+            .unwrap();
+        assert_eq!(buffer, "vctrld>");
+    }
+
+    #[tokio::test]
+    async fn read_until_prompt_eof_and_invalid_utf8() {
+        let mut reader = BufReader::with_capacity(4, &b"partial vctrl"[..]);
+        let mut buffer = String::new();
+        let err = read_until_prompt(&mut reader, &mut buffer, PROMPT, DEFAULT_MAX_RESPONSE)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, VcontroldError::ConnectionLost));
+
+        let mut reader = BufReader::with_capacity(2, &b"21.5 \xb0C\nvctrld>"[..]);
+        read_until_prompt(&mut reader, &mut buffer, PROMPT, DEFAULT_MAX_RESPONSE)
+            .await
+            .unwrap();
+        assert_eq!(buffer, "21.5 \u{fffd}C\nvctrld>");
+    }
+
+    #[tokio::test]
+    async fn read_response_reads_value_sent_after_early_prompt() {
+        let (client, mut server) = tokio::io::duplex(64);
+        tokio::spawn(async move {
+            server.write_all(b"vctrld>\n").await.unwrap();
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            server
+                .write_all(b"21.5 Grad Celsius\nvctrld>")
+                .await
+                .unwrap();
+        });
+
+        let mut reader = BufReader::new(client);
+        let mut buffer = String::new();
+        read_response(
+            &mut reader,
+            &mut buffer,
+            PROMPT,
+            DEFAULT_MAX_RESPONSE,
+            Duration::from_secs(5),
+        )
+        .await
+        .unwrap();
+        assert_eq!(extract_response(&buffer, PROMPT), Some("21.5 Grad Celsius"));
+    }
+
+    #[tokio::test]
+    async fn read_response_keeps_empty_response_without_late_value() {
+        // Only trailing whitespace follows the prompt
+        let (client, mut server) = tokio::io::duplex(64);
+        server.write_all(b"vctrld>\n  ").await.unwrap();
+
+        let mut reader = BufReader::new(client);
+        let mut buffer = String::new();
+        read_response(
+            &mut reader,
+            &mut buffer,
+            PROMPT,
+            DEFAULT_MAX_RESPONSE,
+            Duration::from_millis(50),
+        )
+        .await
+        .unwrap();
+        assert_eq!(extract_response(&buffer, PROMPT), Some(""));
+
+        // A regular response does not wait at all
+        let mut reader = BufReader::new(&b"21.5\nvctrld>"[..]);
+        read_response(
+            &mut reader,
+            &mut buffer,
+            PROMPT,
+            DEFAULT_MAX_RESPONSE,
+            Duration::from_secs(60),
+        )
+        .await
+        .unwrap();
+        assert_eq!(extract_response(&buffer, PROMPT), Some("21.5"));
+        drop(server);
+    }
+
+    #[tokio::test]
+    async fn mark_disconnected_does_not_wait_for_command_in_progress() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    write_prompt(&mut stream).await;
+                    let mut reader = BufReader::new(stream);
+                    let mut command = String::new();
+                    while reader.read_line(&mut command).await.unwrap_or(0) > 0 {
+                        tokio::time::sleep(Duration::from_millis(200)).await;
+                        let stream = reader.get_mut();
+                        stream.write_all(b"1\nvctrld>").await.unwrap();
+                        stream.flush().await.unwrap();
+                        command.clear();
+                    }
+                });
+            }
+        });
+
+        let client = Arc::new(VcontroldClient::new("127.0.0.1", port));
+        let in_progress = tokio::spawn({
+            let client = Arc::clone(&client);
+            async move { client.execute("getTempA").await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        client.mark_disconnected();
+        assert!(!client.is_connected());
+        assert!(in_progress.await.unwrap().is_ok());
+
+        // The busy connection is replaced before the next command
+        client.execute("getTempA").await.unwrap();
+        assert_eq!(client.stats().reconnects, 1);
+        assert!(client.is_connected());
+    }
+
+    #[tokio::test]
+    async fn read_response_without_late_wait_keeps_empty_response() {
+        let mut reader = BufReader::new(&b"vctrld>\n21.5\nvctrld>"[..]);
+        let mut buffer = String::new();
+        read_response(
+            &mut reader,
+            &mut buffer,
+            PROMPT,
+            DEFAULT_MAX_RESPONSE,
+            Duration::ZERO,
+        )
+        .await
+        .unwrap();
+        assert_eq!(extract_response(&buffer, PROMPT), Some(""));
+    }
+
+    #[tokio::test]
+    async fn read_until_prompt_caps_response_size() {
+        // A peer that never sends the prompt
+        let garbage = vec![b'x'; 64 * 1024];
+        let mut reader = BufReader::new(&garbage[..]);
+        let mut buffer = String::new();
+        let err = read_until_prompt(&mut reader, &mut buffer, PROMPT, 16 * 1024)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, VcontroldError::Protocol(_)), "{:?}", err);
+        assert!(buffer.is_empty());
+
+        // Exactly at the cap is still accepted
+        let mut reader = BufReader::new(&b"1\nvctrld>"[..]);
+        read_until_prompt(&mut reader, &mut buffer, PROMPT, 9)
+            .await
+            .unwrap();
+        assert_eq!(buffer, "1\nvctrld>");
+    }
 }
@@ -0,0 +1,172 @@
+//! Mock vcontrold server for development and tests
+//!
+//! Speaks the vcontrold text protocol over TCP without any hardware: prints
+//! the prompt, answers each command line from a fixed command → response map
+//! and closes the connection on `quit`. Commands missing from the map answer
+//! `ERR: command unknown`, except `commands` (lists the mapped commands) and
+//! `version`.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info};
+
+use super::protocol::{LIST_COMMANDS, PROMPT, RELOAD_COMMAND, VERSION_COMMAND};
+
+/// Response to commands missing from the map
+const UNKNOWN_COMMAND: &str = "ERR: command unknown";
+
+/// In-process stand-in for vcontrold
+pub struct MockServer {
+    listener: TcpListener,
+    responses: Arc<HashMap<String, String>>,
+    prompt: String,
+}
+
+impl MockServer {
+    /// Bind to `addr` (`127.0.0.1:0` picks a free port)
+    pub async fn bind(addr: &str, responses: HashMap<String, String>) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr).await?,
+            responses: Arc::new(responses),
+            prompt: PROMPT.to_string(),
+        })
+    }
+
+    /// Print a different prompt (see `VCONTROLD_PROMPT`)
+    pub fn with_prompt(mut self, prompt: &str) -> Self {
+        self.prompt = prompt.to_string();
+        self
+    }
+
+    /// Address the server listens on
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accept and serve connections until the task is dropped
+    pub async fn run(self) -> io::Result<()> {
+        info!("Mock vcontrold listening on {}", self.local_addr()?);
+        loop {
+            let (stream, peer) = self.listener.accept().await?;
+            debug!("Mock vcontrold connection from {}", peer);
+            let responses = Arc::clone(&self.responses);
+            let prompt = self.prompt.clone();
+            tokio::spawn(async move {
+                if let Err(e) = serve(stream, &responses, &prompt).await {
+                    debug!("Mock vcontrold connection closed: {}", e);
+                }
+            });
+        }
+    }
+}
+
+/// Serve one client connection until `quit` or EOF
+async fn serve(
+    stream: TcpStream,
+    responses: &HashMap<String, String>,
+    prompt: &str,
+) -> io::Result<()> {
+    let mut stream = BufReader::new(stream);
+    stream.get_mut().write_all(prompt.as_bytes()).await?;
+
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if stream.read_line(&mut line).await? == 0 {
+            return Ok(());
+        }
+        let command = line.trim();
+        if command == "quit" {
+            return Ok(());
+        }
+        let reply = format!("{}\n{}", respond(responses, command), prompt);
+        stream.get_mut().write_all(reply.as_bytes()).await?;
+    }
+}
+
+/// Look up the response to a command
+fn respond(responses: &HashMap<String, String>, command: &str) -> String {
+    if let Some(response) = responses.get(command) {
+        return response.clone();
+    }
+    match command {
+        LIST_COMMANDS => {
+            let mut names: Vec<&String> = responses.keys().collect();
+            names.sort();
+            names
+                .iter()
+                .map(|name| format!("{}: mock", name))
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+        VERSION_COMMAND => format!("VERSION: {}-mock", env!("CARGO_PKG_VERSION")),
+        RELOAD_COMMAND => String::new(),
+        _ => UNKNOWN_COMMAND.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcontrold::{Value, VcontroldClient};
+
+    async fn start(responses: &[(&str, &str)]) -> u16 {
+        let responses = responses
+            .iter()
+            .map(|(c, r)| (c.to_string(), r.to_string()))
+            .collect();
+        let server = MockServer::bind("127.0.0.1:0", responses).await.unwrap();
+        let port = server.local_addr().unwrap().port();
+        tokio::spawn(server.run());
+        port
+    }
+
+    #[tokio::test]
+    async fn mock_serves_execute_and_batches() {
+        let port = start(&[("getTempA", "21.5 Grad Celsius"), ("getStatus", "Betrieb")]).await;
+        let client = VcontroldClient::new("127.0.0.1", port);
+
+        let result = client.execute("getTempA").await.unwrap();
+        assert!(matches!(result.value, Value::Number(n) if (n - 21.5).abs() < 0.001));
+
+        let commands = vec![
+            "getStatus".to_string(),
+            "getNope".to_string(),
+            "getTempA".to_string(),
+        ];
+        let results = client.execute_batch(&commands).await;
+        assert!(
+            matches!(&results[0], Ok(r) if matches!(&r.value, Value::String(s) if s == "Betrieb"))
+        );
+        assert_eq!(
+            results[1].as_ref().unwrap().error.as_deref(),
+            Some(UNKNOWN_COMMAND)
+        );
+        assert!(results[2].as_ref().unwrap().error.is_none());
+
+        assert_eq!(
+            client.list_commands().await.unwrap(),
+            vec!["getStatus", "getTempA"]
+        );
+        client.disconnect().await;
+    }
+
+    #[tokio::test]
+    async fn mock_uses_custom_prompt() {
+        let responses = HashMap::from([("getTempA".to_string(), "7".to_string())]);
+        let server = MockServer::bind("127.0.0.1:0", responses)
+            .await
+            .unwrap()
+            .with_prompt("vcontrol>");
+        let port = server.local_addr().unwrap().port();
+        tokio::spawn(server.run());
+
+        let client = VcontroldClient::new("127.0.0.1", port).with_prompt("vcontrol>");
+        let result = client.execute("getTempA").await.unwrap();
+        assert!(matches!(result.value, Value::Number(n) if n == 7.0));
+    }
+}
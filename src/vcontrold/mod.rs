@@ -1,7 +1,14 @@
-//! vcontrold module - TCP client for vcontrold daemon
+//! vcontrold module - TCP/Unix socket client for vcontrold daemon
 
 mod client;
+mod mock;
 mod protocol;
+pub mod typed;
 
-pub use client::VcontroldClient;
-pub use protocol::{build_json_response, CommandResult, Value};
+pub use client::{ClientStats, VcontroldClient, DEFAULT_MAX_RESPONSE, DEFAULT_PORT};
+pub use mock::MockServer;
+pub use protocol::{
+    build_json_object, build_json_object_with_errors, build_json_response,
+    build_json_response_with_errors, format_hex, strip_version_label, CommandResult, ErrorKind,
+    JsonOptions, Value, DEVICE_TYPE_COMMAND, PROMPT, RELOAD_COMMAND, VERSION_COMMAND,
+};
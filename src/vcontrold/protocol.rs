@@ -2,9 +2,12 @@
 //!
 //! Protocol constants and response parsing for vcontrold communication.
 
+use serde_json::Map;
+use tracing::warn;
+
 use crate::error::VcontroldError;
 
-/// Prompt string sent by vcontrold when ready for commands
+/// Default prompt string sent by vcontrold when ready for commands
 pub const PROMPT: &str = "vctrld>";
 
 /// Goodbye message sent when disconnecting
@@ -14,8 +17,45 @@ pub const BYE: &str = "good bye!";
 /// Error prefix in vcontrold responses
 pub const ERR_PREFIX: &str = "ERR:";
 
+/// Command listing all commands known to vcontrold
+pub const LIST_COMMANDS: &str = "commands";
+
+/// Command reporting the vcontrold version
+pub const VERSION_COMMAND: &str = "version";
+
+/// Command making vcontrold reload its XML configuration
+pub const RELOAD_COMMAND: &str = "reload";
+
+/// Command reporting the device type (defined in most vcontrold configurations)
+pub const DEVICE_TYPE_COMMAND: &str = "getDevType";
+
+/// JSON key collecting failed commands in `build_json_response_with_errors`
+pub const ERRORS_KEY: &str = "errors";
+
+/// JSON key collecting the raw responses with [`JsonOptions::raw`]
+pub const RAW_KEY: &str = "raw";
+
+/// JSON key collecting the units with [`JsonOptions::units`]
+pub const UNITS_KEY: &str = "units";
+
+/// How result values are rendered into JSON objects
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonOptions {
+    /// Leave results without a value (including non-finite numbers) out
+    /// instead of emitting `null`
+    pub omit_nulls: bool,
+    /// Add a `raw` object with the unparsed vcontrold response per command
+    pub raw: bool,
+    /// Add a `units` object with the unit of each value that has one
+    pub units: bool,
+}
+
 /// Result of executing a command
+///
+/// Built by [`parse_response`]; use [`CommandResult::new`] and
+/// [`CommandResult::failed`] to create one elsewhere.
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub struct CommandResult {
     /// The command that was executed
     pub command: String,
@@ -24,8 +64,137 @@ pub struct CommandResult {
     /// Raw response string (useful for debugging)
     #[allow(dead_code)]
     pub raw: String,
-    /// Error message if command failed
+    /// Error message if command failed (first line of an `ERR:` response)
     pub error: Option<String>,
+    /// Further lines of a multi-line error response
+    pub error_detail: Option<String>,
+    /// Classification of `error`
+    pub error_kind: Option<ErrorKind>,
+    /// Unit following a numeric value (`Grad Celsius` in `21.5 Grad Celsius`),
+    /// or its `UNITS` override
+    pub unit: Option<String>,
+}
+
+/// Classification of an `ERR:` response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The command is not defined in the vcontrold configuration
+    UnknownCommand,
+    /// Transient Optolink bus problem (telegram, fd, busy); worth retrying
+    DeviceBusy,
+    /// Framing/communication failure between vcontrold and the device
+    ProtocolError,
+    /// Anything else
+    Other,
+}
+
+impl ErrorKind {
+    /// Classify an error message
+    pub fn classify(message: &str) -> Self {
+        let message = message.to_lowercase();
+        let contains_any = |needles: &[&str]| needles.iter().any(|n| message.contains(n));
+        if contains_any(&["command unknown", "unknown command"]) {
+            ErrorKind::UnknownCommand
+        } else if contains_any(&["telegram", "fd ", "fd:", "busy", "not ready"]) {
+            ErrorKind::DeviceBusy
+        } else if contains_any(&["framer", "checksum", "error in send"]) {
+            ErrorKind::ProtocolError
+        } else {
+            ErrorKind::Other
+        }
+    }
+
+    /// Whether retrying the command may succeed
+    pub fn is_transient(self) -> bool {
+        self == ErrorKind::DeviceBusy
+    }
+}
+
+impl CommandResult {
+    /// Create a successful result with `value` and an empty raw response
+    pub fn new(command: impl Into<String>, value: Value) -> Self {
+        CommandResult {
+            command: command.into(),
+            value,
+            raw: String::new(),
+            error: None,
+            error_detail: None,
+            error_kind: None,
+            unit: None,
+        }
+    }
+
+    /// Create a failed result; `error` is also used as the raw response
+    pub fn failed(command: impl Into<String>, error: impl Into<String>) -> Self {
+        let error = error.into();
+        CommandResult {
+            command: command.into(),
+            value: Value::None,
+            raw: error.clone(),
+            error_kind: Some(ErrorKind::classify(&error)),
+            error: Some(error),
+            error_detail: None,
+            unit: None,
+        }
+    }
+
+    /// Set the raw response
+    pub fn with_raw(mut self, raw: impl Into<String>) -> Self {
+        self.raw = raw.into();
+        self
+    }
+
+    /// Turn a successful non-numeric result into an error
+    ///
+    /// Used for commands that must always return a number, so placeholder
+    /// text such as `---` (disconnected sensor) is never published as a value.
+    pub fn require_numeric(mut self) -> Self {
+        if self.error.is_none() && !matches!(self.value, Value::Number(_)) {
+            self.error = Some(format!("{} non-numeric response: {}", ERR_PREFIX, self.raw));
+            self.error_kind = Some(ErrorKind::Other);
+            self.value = Value::None;
+            self.unit = None;
+        }
+        self
+    }
+
+    /// Parse a successful result as space-separated hex bytes (`01 A3 FF`)
+    ///
+    /// Used for raw telegram reads, whose bytes would otherwise be mangled
+    /// into a number or string; a response that is not all hex bytes becomes
+    /// an error.
+    pub fn require_hex(mut self) -> Self {
+        if self.error.is_some() {
+            return self;
+        }
+        match parse_hex_bytes(&self.raw) {
+            Some(bytes) => self.value = Value::Bytes(bytes),
+            None => {
+                self.error = Some(format!("{} non-hex response: {}", ERR_PREFIX, self.raw));
+                self.error_kind = Some(ErrorKind::Other);
+                self.value = Value::None;
+            }
+        }
+        self.unit = None;
+        self
+    }
+
+    /// Replace the parsed unit of a successful result with `unit`
+    pub fn with_unit(mut self, unit: &str) -> Self {
+        if self.error.is_none() && !matches!(self.value, Value::None) {
+            self.unit = Some(unit.to_string());
+        }
+        self
+    }
+
+    /// The error message followed by its detail, for logging
+    pub fn error_with_detail(&self) -> Option<String> {
+        let error = self.error.as_deref()?;
+        Some(match &self.error_detail {
+            Some(detail) => format!("{} ({})", error, detail),
+            None => error.to_string(),
+        })
+    }
 }
 
 /// A value returned by vcontrold
@@ -35,6 +204,8 @@ pub enum Value {
     Number(f64),
     /// String value
     String(String),
+    /// Raw bytes (`HEX_COMMANDS`)
+    Bytes(Vec<u8>),
     /// No value / error
     None,
 }
@@ -43,37 +214,81 @@ impl Value {
     /// Convert to JSON-compatible representation
     pub fn to_json_value(&self) -> serde_json::Value {
         match self {
-            Value::Number(n) => serde_json::json!(*n),
+            Value::Number(n) if n.is_finite() => serde_json::json!(*n),
+            Value::Number(_) => serde_json::Value::Null,
             Value::String(s) => serde_json::json!(s),
+            Value::Bytes(bytes) => serde_json::json!(format_hex(bytes)),
             Value::None => serde_json::Value::Null,
         }
     }
 }
 
+/// Format bytes as an uppercase hex string without separators (`01A3FF`)
+pub fn format_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+/// Parse whitespace-separated hex bytes, each optionally prefixed with `0x`
+///
+/// `None` if the text is empty or any word is not a single hex byte.
+fn parse_hex_bytes(raw: &str) -> Option<Vec<u8>> {
+    let bytes: Option<Vec<u8>> = raw
+        .split_whitespace()
+        .map(|word| {
+            let digits = word
+                .strip_prefix("0x")
+                .or_else(|| word.strip_prefix("0X"))
+                .unwrap_or(word);
+            if digits.is_empty() || digits.len() > 2 {
+                return None;
+            }
+            u8::from_str_radix(digits, 16).ok()
+        })
+        .collect();
+    bytes.filter(|bytes| !bytes.is_empty())
+}
+
 /// Parse a raw response line from vcontrold
 ///
 /// Response format: "value unit" or "value" or "ERR: message"
 pub fn parse_response(command: &str, raw: &str) -> CommandResult {
     let raw = raw.trim();
 
-    // Check for error response
+    // Check for error response: the `ERR:` line, optionally followed by
+    // detail lines
     if raw.starts_with(ERR_PREFIX) {
+        let (error, detail) = match raw.split_once('\n') {
+            Some((error, detail)) => (error.trim(), Some(detail.trim())),
+            None => (raw, None),
+        };
         return CommandResult {
             command: command.to_string(),
             value: Value::None,
             raw: raw.to_string(),
-            error: Some(raw.to_string()),
+            error: Some(error.to_string()),
+            error_detail: detail.filter(|d| !d.is_empty()).map(str::to_string),
+            error_kind: Some(ErrorKind::classify(raw)),
+            unit: None,
         };
     }
 
-    // Try to parse as number (first word)
-    let first_word = raw.split_whitespace().next().unwrap_or(raw);
-    let value = if let Ok(num) = first_word.parse::<f64>() {
-        Value::Number(num)
-    } else if !raw.is_empty() {
-        Value::String(raw.to_string())
-    } else {
-        Value::None
+    // Try to parse as number (first word), followed by an optional unit
+    let (first_word, rest) = raw.split_once(char::is_whitespace).unwrap_or((raw, ""));
+    let mut unit = None;
+    let value = match first_word.parse::<f64>() {
+        Ok(num) if num.is_finite() => {
+            unit = Some(rest.trim())
+                .filter(|u| !u.is_empty())
+                .map(str::to_string);
+            Value::Number(num)
+        }
+        // `NaN`/`inf` parse as f64 but are no usable reading
+        Ok(_) => {
+            warn!("Non-finite value from {}: {}", command, raw);
+            Value::None
+        }
+        Err(_) if !raw.is_empty() => Value::String(raw.to_string()),
+        Err(_) => Value::None,
     };
 
     CommandResult {
@@ -81,6 +296,9 @@ pub fn parse_response(command: &str, raw: &str) -> CommandResult {
         value,
         raw: raw.to_string(),
         error: None,
+        error_detail: None,
+        error_kind: None,
+        unit,
     }
 }
 
@@ -101,8 +319,22 @@ pub fn has_prompt(buffer: &str) -> bool {
 }
 
 /// Extract response from buffer (everything before the prompt)
-pub fn extract_response(buffer: &str) -> Option<&str> {
-    buffer.find(PROMPT).map(|idx| buffer[..idx].trim())
+pub fn extract_response<'a>(buffer: &'a str, prompt: &str) -> Option<&'a str> {
+    find_prompt(buffer.as_bytes(), prompt.as_bytes(), 0).map(|idx| buffer[..idx].trim())
+}
+
+/// Position of the first prompt in `haystack` at or after `from`
+///
+/// vcontrold prints the prompt at the start of a line, after the newline
+/// that ends the previous response, so `vctrld>` embedded in a response
+/// line (e.g. a string-valued parameter) is not mistaken for it.
+pub fn find_prompt(haystack: &[u8], prompt: &[u8], from: usize) -> Option<usize> {
+    if prompt.is_empty() {
+        return Some(from.min(haystack.len()));
+    }
+    (from..=haystack.len().saturating_sub(prompt.len()))
+        .filter(|&pos| haystack[pos..].starts_with(prompt))
+        .find(|&pos| pos == 0 || haystack[pos - 1] == b'\n')
 }
 
 /// Check if response indicates an error
@@ -126,15 +358,101 @@ pub fn is_fatal_error_response(response: &str) -> bool {
 
 /// Build JSON output matching vclient -j format
 ///
-/// Format: {"command1":value1,"command2":value2}
+/// Format: {"command1":value1,"command2":value2}, keys in the order of `results`
 pub fn build_json_response(results: &[CommandResult]) -> String {
-    let mut map = serde_json::Map::new();
+    build_json_object(results, JsonOptions::default()).to_string()
+}
+
+/// Build the JSON object behind `build_json_response` (successful results only)
+pub fn build_json_object(results: &[CommandResult], options: JsonOptions) -> serde_json::Value {
+    let succeeded: Vec<CommandResult> = results
+        .iter()
+        .filter(|result| result.error.is_none())
+        .cloned()
+        .collect();
+    build_json_object_with_errors(&succeeded, &[], str::to_string, options)
+}
+
+/// Build JSON output that also reports failed commands
+///
+/// Like `build_json_response`, but failed commands are listed under an
+/// `errors` object with their error message (omitted when nothing failed):
+/// `{"getTempA":21.5,"errors":{"badCmd":"ERR: command unknown"}}`
+pub fn build_json_response_with_errors(results: &[CommandResult]) -> String {
+    build_json_object_with_errors(results, &[], str::to_string, JsonOptions::default()).to_string()
+}
+
+/// Build the object behind `build_json_response_with_errors`
+///
+/// `errors` lists failures without a `CommandResult` (e.g. rejected or
+/// unreachable commands) and follows the failed results; `key` maps a command
+/// to its JSON key. With `options.raw`, the raw responses of the successful
+/// results follow the values under a `raw` object:
+/// `{"getTempA":21.5,"raw":{"getTempA":"21.5 Grad Celsius"}}`; with
+/// `options.units`, their units follow under a `units` object:
+/// `{"getTempA":21.5,"units":{"getTempA":"Grad Celsius"}}`
+pub fn build_json_object_with_errors(
+    results: &[CommandResult],
+    errors: &[(String, String)],
+    key: impl Fn(&str) -> String,
+    options: JsonOptions,
+) -> serde_json::Value {
+    let mut object = Map::new();
+    let mut raw = Map::new();
+    let mut units = Map::new();
+    let mut failed = Map::new();
     for result in results {
-        if result.error.is_none() {
-            map.insert(result.command.clone(), result.value.to_json_value());
+        match &result.error {
+            None => {
+                let value = result.value.to_json_value();
+                if !(options.omit_nulls && value.is_null()) {
+                    object.insert(key(&result.command), value);
+                    raw.insert(key(&result.command), result.raw.as_str().into());
+                    if let Some(unit) = &result.unit {
+                        units.insert(key(&result.command), unit.as_str().into());
+                    }
+                }
+            }
+            Some(e) => {
+                failed.insert(key(&result.command), e.as_str().into());
+            }
         }
     }
-    serde_json::Value::Object(map).to_string()
+    for (command, e) in errors {
+        failed.insert(key(command), e.as_str().into());
+    }
+    if options.raw && !raw.is_empty() {
+        object.insert(RAW_KEY.to_string(), raw.into());
+    }
+    if options.units && !units.is_empty() {
+        object.insert(UNITS_KEY.to_string(), units.into());
+    }
+    if !failed.is_empty() {
+        object.insert(ERRORS_KEY.to_string(), failed.into());
+    }
+    object.into()
+}
+
+/// Strip the `VERSION: ` label from the response to `version`
+///
+/// Responses without the label are returned trimmed but otherwise unchanged.
+pub fn strip_version_label(raw: &str) -> &str {
+    let raw = raw.trim();
+    match raw.split_once(':') {
+        Some((label, value)) if label.eq_ignore_ascii_case("version") => value.trim(),
+        _ => raw,
+    }
+}
+
+/// Parse the response to `commands` into command names
+///
+/// Each line has the form `name: description`.
+pub fn parse_command_list(raw: &str) -> Vec<String> {
+    raw.lines()
+        .filter_map(|line| line.split_once(':').map(|(name, _)| name.trim()))
+        .filter(|name| !name.is_empty() && !name.contains(char::is_whitespace))
+        .map(str::to_string)
+        .collect()
 }
 
 /// Validate that a command string is safe to send
@@ -161,6 +479,30 @@ mod tests {
         let result = parse_response("getTempWWObenIst", "48.1 Grad Celsius");
         assert!(matches!(result.value, Value::Number(n) if (n - 48.1).abs() < 0.001));
         assert!(result.error.is_none());
+        assert_eq!(result.unit.as_deref(), Some("Grad Celsius"));
+        assert_eq!(parse_response("getPumpe", "1").unit, None);
+    }
+
+    #[test]
+    fn test_with_unit_overrides_successful_values_only() {
+        let result = parse_response("getTempA", "21.5 Grad Celsius").with_unit("°C");
+        assert_eq!(result.unit.as_deref(), Some("°C"));
+        let result = parse_response("getTempA", "ERR: command unknown").with_unit("°C");
+        assert_eq!(result.unit, None);
+        let result = parse_response("getTempA", "--- Grad Celsius").require_numeric();
+        assert_eq!(result.unit, None);
+    }
+
+    #[test]
+    fn test_strip_version_label() {
+        assert_eq!(strip_version_label("VERSION: 0.98\n"), "0.98");
+        assert_eq!(strip_version_label("version:0.98"), "0.98");
+        assert_eq!(strip_version_label(" 0.98 "), "0.98");
+        assert_eq!(strip_version_label("V200KW2"), "V200KW2");
+        assert_eq!(
+            strip_version_label("Build: 2024-01-01"),
+            "Build: 2024-01-01"
+        );
     }
 
     #[test]
@@ -170,6 +512,44 @@ mod tests {
         assert!(result.error.is_some());
     }
 
+    #[test]
+    fn test_parse_two_line_error_response() {
+        let result = parse_response(
+            "getTempA",
+            "ERR: command failed\n>FRAMER: Error 0x15 != 0x06 (P300_INIT_OK)\n",
+        );
+        assert_eq!(result.error.as_deref(), Some("ERR: command failed"));
+        assert_eq!(
+            result.error_detail.as_deref(),
+            Some(">FRAMER: Error 0x15 != 0x06 (P300_INIT_OK)")
+        );
+        assert_eq!(result.error_kind, Some(ErrorKind::ProtocolError));
+        assert_eq!(
+            result.error_with_detail().as_deref(),
+            Some("ERR: command failed (>FRAMER: Error 0x15 != 0x06 (P300_INIT_OK))")
+        );
+
+        let result = parse_response("badCommand", "ERR: command unknown");
+        assert!(result.error_detail.is_none());
+    }
+
+    #[test]
+    fn test_error_kind_classification() {
+        let kind = |raw| parse_response("cmd", raw).error_kind;
+        assert_eq!(
+            kind("ERR: command unknown"),
+            Some(ErrorKind::UnknownCommand)
+        );
+        assert_eq!(kind("ERR: telegram too short"), Some(ErrorKind::DeviceBusy));
+        assert_eq!(kind("ERR: fd not valid"), Some(ErrorKind::DeviceBusy));
+        assert_eq!(
+            kind("ERR: >FRAMER: Error in send, terminating"),
+            Some(ErrorKind::ProtocolError)
+        );
+        assert_eq!(kind("ERR: something else"), Some(ErrorKind::Other));
+        assert_eq!(kind("48.1 Grad Celsius"), None);
+    }
+
     #[test]
     fn test_parse_string_response() {
         let result = parse_response("getStatus", "OK");
@@ -177,27 +557,202 @@ mod tests {
         assert!(result.error.is_none());
     }
 
+    #[test]
+    fn test_parse_non_finite_response() {
+        for raw in ["NaN", "inf Grad Celsius", "-infinity"] {
+            let result = parse_response("getTempA", raw);
+            assert!(matches!(result.value, Value::None), "{}", raw);
+            assert!(result.error.is_none());
+            assert_eq!(result.raw, raw);
+        }
+    }
+
+    #[test]
+    fn test_value_to_json_non_finite() {
+        assert_eq!(
+            Value::Number(f64::NAN).to_json_value(),
+            serde_json::Value::Null
+        );
+        assert_eq!(
+            Value::Number(f64::INFINITY).to_json_value(),
+            serde_json::Value::Null
+        );
+        assert_eq!(Value::Number(1.5).to_json_value(), serde_json::json!(1.5));
+    }
+
+    #[test]
+    fn test_require_numeric_rejects_placeholder_text() {
+        let result = parse_response("getTempWWObenIst", "---").require_numeric();
+        assert!(matches!(result.value, Value::None));
+        assert_eq!(
+            result.error.as_deref(),
+            Some("ERR: non-numeric response: ---")
+        );
+    }
+
+    #[test]
+    fn test_require_numeric_keeps_numbers_and_errors() {
+        let result = parse_response("getTempWWObenIst", "48.1 Grad Celsius").require_numeric();
+        assert!(matches!(result.value, Value::Number(n) if (n - 48.1).abs() < 0.001));
+        assert!(result.error.is_none());
+
+        let result = parse_response("getTempWWObenIst", "ERR: command unknown").require_numeric();
+        assert_eq!(result.error.as_deref(), Some("ERR: command unknown"));
+    }
+
+    #[test]
+    fn test_require_hex_parses_bytes() {
+        let result = parse_response("getRaw", "01 A3 FF").require_hex();
+        assert!(matches!(&result.value, Value::Bytes(b) if b == &[0x01, 0xA3, 0xFF]));
+        assert!(result.error.is_none());
+        assert_eq!(result.value.to_json_value(), serde_json::json!("01A3FF"));
+
+        let result = parse_response("getRaw", "0x0a 0XB 7").require_hex();
+        assert!(matches!(&result.value, Value::Bytes(b) if b == &[0x0A, 0x0B, 0x07]));
+    }
+
+    #[test]
+    fn test_require_hex_rejects_other_text() {
+        for raw in ["21.5 Grad Celsius", "01 A3F", "", "0x"] {
+            let result = parse_response("getRaw", raw).require_hex();
+            assert!(matches!(result.value, Value::None), "{}", raw);
+            assert_eq!(
+                result.error,
+                Some(format!("ERR: non-hex response: {}", raw)),
+                "{}",
+                raw
+            );
+        }
+
+        let result = parse_response("getRaw", "ERR: command unknown").require_hex();
+        assert_eq!(result.error.as_deref(), Some("ERR: command unknown"));
+    }
+
+    #[test]
+    fn test_parse_command_list() {
+        let raw = "getTempA: Ermittle die Aussentemperatur in Grad C\n\
+                   getTempWWObenIst: Ermittle die Warmwassertemperatur oben\n\
+                   \n\
+                   setTempWWsoll: Setze die Warmwassersolltemperatur";
+        assert_eq!(
+            parse_command_list(raw),
+            vec!["getTempA", "getTempWWObenIst", "setTempWWsoll"]
+        );
+        assert!(parse_command_list("").is_empty());
+    }
+
     #[test]
     fn test_build_json_response() {
         let results = vec![
-            CommandResult {
-                command: "getTempA".to_string(),
-                value: Value::Number(21.5),
-                raw: "21.5 Grad".to_string(),
-                error: None,
-            },
-            CommandResult {
-                command: "getTempB".to_string(),
-                value: Value::Number(45.0),
-                raw: "45.0 Grad".to_string(),
-                error: None,
-            },
+            CommandResult::new("getTempA", Value::Number(21.5)),
+            CommandResult::new("getTempB", Value::Number(45.0)),
         ];
         let json = build_json_response(&results);
         assert!(json.contains("\"getTempA\":21.5"));
         assert!(json.contains("\"getTempB\":45"));
     }
 
+    #[test]
+    fn test_build_json_response_keeps_request_order() {
+        let results: Vec<CommandResult> = ["getTempB", "getTempC", "getTempA"]
+            .iter()
+            .map(|command| CommandResult::new(*command, Value::Number(1.0)))
+            .collect();
+        assert_eq!(
+            build_json_response(&results),
+            r#"{"getTempB":1.0,"getTempC":1.0,"getTempA":1.0}"#
+        );
+    }
+
+    #[test]
+    fn test_build_json_response_with_errors_keeps_order_and_failures() {
+        let results = vec![
+            CommandResult::new("getTempB", Value::Number(45.0)),
+            CommandResult::failed("getNope", "ERR: command unknown"),
+            CommandResult::new("getStatus", Value::String("Betrieb".to_string())),
+            CommandResult::new("getTempA", Value::Number(21.5)),
+        ];
+        assert_eq!(
+            build_json_response_with_errors(&results),
+            r#"{"getTempB":45.0,"getStatus":"Betrieb","getTempA":21.5,"errors":{"getNope":"ERR: command unknown"}}"#
+        );
+        assert_eq!(
+            build_json_response_with_errors(&results[..1]),
+            r#"{"getTempB":45.0}"#
+        );
+        assert_eq!(build_json_response_with_errors(&[]), "{}");
+    }
+
+    #[test]
+    fn test_build_json_object_null_policy() {
+        let results = vec![
+            CommandResult::new("getTempA", Value::Number(21.5)),
+            CommandResult::new("getEmpty", Value::None),
+            CommandResult::new("getNaN", Value::Number(f64::NAN)),
+        ];
+        assert_eq!(
+            build_json_object(&results, JsonOptions::default()).to_string(),
+            r#"{"getTempA":21.5,"getEmpty":null,"getNaN":null}"#
+        );
+        let omit_nulls = JsonOptions {
+            omit_nulls: true,
+            ..JsonOptions::default()
+        };
+        assert_eq!(
+            build_json_object(&results, omit_nulls).to_string(),
+            r#"{"getTempA":21.5}"#
+        );
+        assert_eq!(
+            build_json_object_with_errors(&results, &[], str::to_string, omit_nulls).to_string(),
+            r#"{"getTempA":21.5}"#
+        );
+    }
+
+    #[test]
+    fn test_build_json_object_with_raw() {
+        let results = vec![
+            CommandResult::new("getTempA", Value::Number(21.5)).with_raw("21.5 Grad Celsius"),
+            CommandResult::failed("getNope", "ERR: command unknown"),
+        ];
+        let raw = JsonOptions {
+            raw: true,
+            ..JsonOptions::default()
+        };
+        assert_eq!(
+            build_json_object_with_errors(&results, &[], str::to_string, raw).to_string(),
+            r#"{"getTempA":21.5,"raw":{"getTempA":"21.5 Grad Celsius"},"errors":{"getNope":"ERR: command unknown"}}"#
+        );
+        assert_eq!(
+            build_json_object(&results, raw).to_string(),
+            r#"{"getTempA":21.5,"raw":{"getTempA":"21.5 Grad Celsius"}}"#
+        );
+        // Off by default
+        assert_eq!(
+            build_json_object(&results, JsonOptions::default()).to_string(),
+            r#"{"getTempA":21.5}"#
+        );
+    }
+
+    #[test]
+    fn test_build_json_object_with_units() {
+        let results = vec![
+            parse_response("getTempA", "21.5 Grad Celsius").with_unit("°C"),
+            parse_response("getStatus", "on"),
+        ];
+        let units = JsonOptions {
+            units: true,
+            ..JsonOptions::default()
+        };
+        assert_eq!(
+            build_json_object(&results, units).to_string(),
+            r#"{"getTempA":21.5,"getStatus":"on","units":{"getTempA":"°C"}}"#
+        );
+        assert_eq!(
+            build_json_object(&results, JsonOptions::default()).to_string(),
+            r#"{"getTempA":21.5,"getStatus":"on"}"#
+        );
+    }
+
     #[test]
     fn test_is_fatal_error_response_for_framer_send_failure() {
         let response = "ERR: >FRAMER: Error 0x05 != 0x06 (P300_INIT_OK)\nError in send, terminating\nError executing getTempA";
@@ -0,0 +1,183 @@
+//! Typed accessors for common Viessmann command families
+//!
+//! A convenience layer over [`CommandResult::value`] for embedders that want
+//! a temperature, a switch state or an operating mode instead of a raw
+//! [`Value`]. It does not change what vcontrold returns or what the bridge
+//! publishes; every accessor returns `None` for failed commands and for values
+//! that do not fit the family.
+//!
+//! ```
+//! use vcontrold_mqttd::vcontrold::typed::{as_operating_mode, OperatingMode};
+//! # use vcontrold_mqttd::{CommandResult, Value};
+//! # let result = CommandResult::new("getBetriebArtM1", Value::String("H+WW".into()));
+//! assert_eq!(as_operating_mode(&result), Some(OperatingMode::HeatingDhw));
+//! ```
+//!
+//! # Extending
+//!
+//! Enumerations are parsed with serde: [`as_enum`] deserializes the string
+//! form of a value (a label, or the integer of a numeric code) into any
+//! `Deserialize` type. Further families need no changes here, only an enum
+//! whose `#[serde(alias = ...)]` attributes list the labels and codes of the
+//! vcontrold configuration in use:
+//!
+//! ```
+//! use serde::Deserialize;
+//! use vcontrold_mqttd::vcontrold::typed::as_enum;
+//! # use vcontrold_mqttd::{CommandResult, Value};
+//!
+//! #[derive(Debug, PartialEq, Deserialize)]
+//! enum BurnerState {
+//!     #[serde(alias = "0", alias = "Aus")]
+//!     Off,
+//!     #[serde(alias = "1", alias = "Stufe 1")]
+//!     Stage1,
+//! }
+//! # let result = CommandResult::new("getBrennerStufe", Value::Number(1.0));
+//!
+//! assert_eq!(as_enum::<BurnerState>(&result), Some(BurnerState::Stage1));
+//! ```
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use super::protocol::{CommandResult, Value};
+
+/// Units vcontrold configurations use for temperatures (compared case-insensitively)
+const TEMPERATURE_UNITS: &[&str] = &["grad celsius", "°c", "c"];
+
+/// Heating circuit operating mode (`getBetriebArt*`)
+///
+/// Accepts the codes and short labels of the `BA` enumeration in the
+/// vcontrold `vito.xml` shipped for Vitotronic 200/300 controllers. Serializes
+/// to snake case (`heating_dhw`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperatingMode {
+    /// Domestic hot water only
+    #[serde(alias = "0", alias = "WW")]
+    DhwOnly,
+    /// Continuously reduced
+    #[serde(alias = "1", alias = "RED")]
+    Reduced,
+    /// Continuously normal
+    #[serde(alias = "2", alias = "NORM")]
+    Normal,
+    /// Heating and hot water, frost protection
+    #[serde(alias = "3", alias = "H+WW FS")]
+    HeatingDhwFrostProtection,
+    /// Heating and hot water
+    #[serde(alias = "4", alias = "H+WW")]
+    HeatingDhw,
+    /// Switched off (standby with frost protection)
+    #[serde(alias = "5", alias = "ABSCHALT")]
+    Standby,
+}
+
+/// Get a temperature in °C
+///
+/// Accepts numeric values without a unit or with a Celsius unit
+/// (`Grad Celsius`, `°C`); values with any other unit are not temperatures.
+pub fn as_temperature(result: &CommandResult) -> Option<f64> {
+    if result.error.is_some() {
+        return None;
+    }
+    let is_celsius = result.unit.as_deref().is_none_or(|unit| {
+        TEMPERATURE_UNITS
+            .iter()
+            .any(|known| unit.eq_ignore_ascii_case(known))
+    });
+    match result.value {
+        Value::Number(n) if n.is_finite() && is_celsius => Some(n),
+        _ => None,
+    }
+}
+
+/// Get a switch state (pumps, burner, valves)
+///
+/// `1`/`0` and the labels `on`/`off`, `an`/`aus`, `ein`/`aus`, `true`/`false`
+/// (in any case) are recognized.
+pub fn as_bool(result: &CommandResult) -> Option<bool> {
+    if result.error.is_some() {
+        return None;
+    }
+    match &result.value {
+        Value::Number(n) if *n == 1.0 => Some(true),
+        Value::Number(n) if *n == 0.0 => Some(false),
+        Value::String(s) => match s.trim().to_ascii_lowercase().as_str() {
+            "1" | "on" | "an" | "ein" | "true" => Some(true),
+            "0" | "off" | "aus" | "false" => Some(false),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Get the operating mode of a heating circuit
+pub fn as_operating_mode(result: &CommandResult) -> Option<OperatingMode> {
+    as_enum(result)
+}
+
+/// Deserialize the value into `T`
+///
+/// Strings are passed trimmed, integral numbers as their decimal string
+/// (`1.0` as `"1"`), so a single set of serde aliases covers both labels and
+/// codes. Other values and failed commands give `None`.
+pub fn as_enum<T: DeserializeOwned>(result: &CommandResult) -> Option<T> {
+    if result.error.is_some() {
+        return None;
+    }
+    let label = match &result.value {
+        Value::String(s) => s.trim().to_string(),
+        Value::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => format!("{}", *n as i64),
+        _ => return None,
+    };
+    T::deserialize(serde_json::Value::String(label)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcontrold::protocol::parse_response;
+
+    #[test]
+    fn test_as_temperature() {
+        let temperature = |raw: &str| as_temperature(&parse_response("getTempA", raw));
+        assert_eq!(temperature("21.5 Grad Celsius"), Some(21.5));
+        assert_eq!(temperature("-3.2"), Some(-3.2));
+        assert_eq!(temperature("55 %"), None);
+        assert_eq!(temperature("warm"), None);
+        assert_eq!(temperature("ERR: command unknown"), None);
+        let result = parse_response("getTempA", "70.1 Grad Celsius").with_unit("°C");
+        assert_eq!(as_temperature(&result), Some(70.1));
+    }
+
+    #[test]
+    fn test_as_bool() {
+        let state = |raw: &str| as_bool(&parse_response("getPumpeStatusM1", raw));
+        assert_eq!(state("1"), Some(true));
+        assert_eq!(state("0"), Some(false));
+        assert_eq!(state("An"), Some(true));
+        assert_eq!(state("OFF"), Some(false));
+        assert_eq!(state("2"), None);
+        assert_eq!(state("ERR: timeout"), None);
+    }
+
+    #[test]
+    fn test_as_operating_mode() {
+        let mode = |raw: &str| as_operating_mode(&parse_response("getBetriebArtM1", raw));
+        assert_eq!(mode("H+WW"), Some(OperatingMode::HeatingDhw));
+        assert_eq!(
+            mode("H+WW FS"),
+            Some(OperatingMode::HeatingDhwFrostProtection)
+        );
+        assert_eq!(mode(" WW "), Some(OperatingMode::DhwOnly));
+        assert_eq!(mode("5"), Some(OperatingMode::Standby));
+        assert_eq!(mode("1.5"), None);
+        assert_eq!(mode("Party"), None);
+        assert_eq!(
+            serde_json::to_string(&OperatingMode::HeatingDhw).unwrap(),
+            "\"heating_dhw\""
+        );
+    }
+}